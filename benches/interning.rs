@@ -0,0 +1,209 @@
+//! Compares the linear-scan key/value interning done when a layer is re-encoded
+//! (`transform.rs`) against interning through hash maps.
+//!
+//! Each feature's tags are re-interned into fresh key and value tables, exactly as
+//! the transform does. Run with `cargo bench --bench interning`; besides criterion's
+//! report, the number of distinct keys/values from which hash maps win is printed.
+
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use geozero::mvt::tile::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Distinct keys (and distinct values) in the synthetic layer
+const DISTINCT: [usize; 7] = [4, 8, 16, 32, 64, 256, 1024];
+/// Features in the synthetic layer
+const FEATURES: usize = 1_000;
+/// Tags per feature
+const TAGS_PER_FEATURE: usize = 8;
+
+/// A synthetic layer: key and value tables, and each feature's tags as index pairs
+struct Layer {
+    keys: Vec<String>,
+    values: Vec<Value>,
+    features: Vec<Vec<u32>>,
+}
+
+fn synthetic_layer(distinct: usize) -> Layer {
+    let keys = (0..distinct).map(|i| format!("key_{i}")).collect();
+    // A mix of the value types found in real tiles
+    let values = (0..distinct)
+        .map(|i| match i % 3 {
+            0 => Value {
+                string_value: Some(format!("value_{i}")),
+                ..Default::default()
+            },
+            1 => Value {
+                int_value: Some(i as i64),
+                ..Default::default()
+            },
+            _ => Value {
+                double_value: Some(i as f64 + 0.5),
+                ..Default::default()
+            },
+        })
+        .collect();
+    // A fixed LCG, so both strategies see the same tags on every run
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = || {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1);
+        ((state >> 33) % distinct as u64) as u32
+    };
+    let features = (0..FEATURES)
+        .map(|_| {
+            (0..TAGS_PER_FEATURE)
+                .flat_map(|_| [next(), next()])
+                .collect()
+        })
+        .collect();
+    Layer {
+        keys,
+        values,
+        features,
+    }
+}
+
+/// New key/value tables and each feature's re-indexed tags
+type Interned = (Vec<String>, Vec<Value>, Vec<Vec<u32>>);
+
+/// The current approach: find each key and value with a linear scan
+fn intern_linear(layer: &Layer) -> Interned {
+    let mut keys: Vec<String> = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+    let mut features = Vec::with_capacity(layer.features.len());
+    for tags in &layer.features {
+        let mut new_tags = Vec::with_capacity(tags.len());
+        for kv in tags.chunks_exact(2) {
+            let key = &layer.keys[kv[0] as usize];
+            let value = &layer.values[kv[1] as usize];
+            let key_idx = keys.iter().position(|k| k == key).unwrap_or_else(|| {
+                keys.push(key.clone());
+                keys.len() - 1
+            });
+            let value_idx = values.iter().position(|v| v == value).unwrap_or_else(|| {
+                values.push(value.clone());
+                values.len() - 1
+            });
+            new_tags.extend([key_idx as u32, value_idx as u32]);
+        }
+        features.push(new_tags);
+    }
+    (keys, values, features)
+}
+
+/// A hashable stand-in for `Value`. Floats are compared by their bits, which differs
+/// from `Value`'s `PartialEq` only for NaN and signed zeros.
+#[derive(PartialEq, Eq, Hash)]
+struct ValueKey {
+    string_value: Option<String>,
+    float_value: Option<u32>,
+    double_value: Option<u64>,
+    int_value: Option<i64>,
+    uint_value: Option<u64>,
+    sint_value: Option<i64>,
+    bool_value: Option<bool>,
+}
+
+impl From<&Value> for ValueKey {
+    fn from(value: &Value) -> Self {
+        Self {
+            string_value: value.string_value.clone(),
+            float_value: value.float_value.map(f32::to_bits),
+            double_value: value.double_value.map(f64::to_bits),
+            int_value: value.int_value,
+            uint_value: value.uint_value,
+            sint_value: value.sint_value,
+            bool_value: value.bool_value,
+        }
+    }
+}
+
+/// The proposed approach: look up each key and value in a hash map
+fn intern_hashmap(layer: &Layer) -> Interned {
+    let mut keys: Vec<String> = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+    let mut key_index: HashMap<String, u32> = HashMap::new();
+    let mut value_index: HashMap<ValueKey, u32> = HashMap::new();
+    let mut features = Vec::with_capacity(layer.features.len());
+    for tags in &layer.features {
+        let mut new_tags = Vec::with_capacity(tags.len());
+        for kv in tags.chunks_exact(2) {
+            let key = &layer.keys[kv[0] as usize];
+            let value = &layer.values[kv[1] as usize];
+            let key_idx = match key_index.get(key) {
+                Some(&idx) => idx,
+                None => {
+                    keys.push(key.clone());
+                    let idx = keys.len() as u32 - 1;
+                    key_index.insert(key.clone(), idx);
+                    idx
+                }
+            };
+            let value_idx = *value_index.entry(value.into()).or_insert_with(|| {
+                values.push(value.clone());
+                values.len() as u32 - 1
+            });
+            new_tags.extend([key_idx, value_idx]);
+        }
+        features.push(new_tags);
+    }
+    (keys, values, features)
+}
+
+/// Average time of `f` over enough runs to take at least 50ms
+fn time(f: impl Fn() -> Interned) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while start.elapsed() < Duration::from_millis(50) {
+        black_box(f());
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+/// Print the smallest number of distinct keys/values from which hash maps are faster
+fn report_crossover() {
+    let crossover = DISTINCT.into_iter().find(|&distinct| {
+        let layer = synthetic_layer(distinct);
+        time(|| intern_hashmap(&layer)) < time(|| intern_linear(&layer))
+    });
+    match crossover {
+        Some(distinct) => println!(
+            "hash map interning is faster from {} distinct keys/values \
+             ({} features, {} tags each)",
+            distinct, FEATURES, TAGS_PER_FEATURE
+        ),
+        None => println!(
+            "linear interning is faster up to {} distinct keys/values",
+            DISTINCT[DISTINCT.len() - 1]
+        ),
+    }
+}
+
+fn interning(c: &mut Criterion) {
+    for distinct in DISTINCT {
+        let layer = synthetic_layer(distinct);
+        assert!(
+            intern_linear(&layer) == intern_hashmap(&layer),
+            "both strategies must build identical tables"
+        );
+    }
+    report_crossover();
+
+    let mut group = c.benchmark_group("interning");
+    for distinct in DISTINCT {
+        let layer = synthetic_layer(distinct);
+        group.bench_with_input(BenchmarkId::new("linear", distinct), &layer, |b, layer| {
+            b.iter(|| intern_linear(black_box(layer)))
+        });
+        group.bench_with_input(BenchmarkId::new("hashmap", distinct), &layer, |b, layer| {
+            b.iter(|| intern_hashmap(black_box(layer)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interning);
+criterion_main!(benches);
@@ -0,0 +1,62 @@
+use pmtiles::{Compression, TileType};
+use thiserror::Error;
+
+/// Errors returned at the library boundary
+#[derive(Debug, Error)]
+pub enum WranglerError {
+    #[error("Unsupported tile type: {0:?}")]
+    UnsupportedTileType(TileType),
+
+    #[error("Unsupported tile compression: {0:?}")]
+    UnsupportedCompression(Compression),
+
+    #[error("Failed to parse filter: {0}")]
+    FilterParse(#[source] anyhow::Error),
+
+    #[error("Failed to parse transform config: {0}")]
+    Config(#[source] anyhow::Error),
+
+    #[error("Failed to decode MVT tile: {coord}")]
+    TileDecode {
+        coord: String,
+        #[source]
+        source: prost::DecodeError,
+    },
+
+    #[error("{failed} of {sampled} sampled tiles are not valid MVT")]
+    InvalidTiles { failed: usize, sampled: usize },
+
+    #[error("No features were written to the output")]
+    EmptyOutput,
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for WranglerError {
+    /// Recover a typed error that was passed through `anyhow` inside the pipeline
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<WranglerError>() {
+            Ok(err) => err,
+            Err(err) => match err.downcast::<std::io::Error>() {
+                Ok(err) => WranglerError::Io(err),
+                Err(err) => WranglerError::Other(err),
+            },
+        }
+    }
+}
+
+impl From<pmtiles::PmtError> for WranglerError {
+    fn from(err: pmtiles::PmtError) -> Self {
+        WranglerError::Other(err.into())
+    }
+}
+
+impl From<geozero::error::GeozeroError> for WranglerError {
+    fn from(err: geozero::error::GeozeroError) -> Self {
+        WranglerError::Other(err.into())
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use pmtiles::TileCoord;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::task::JoinSet;
+
+use crate::processing::format_tile_coord;
+
+/// What happened to a tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The tile was transformed and will be written
+    Processed,
+    /// The tile was empty after transformation and will not be written
+    Skipped,
+    /// The tile could not be transformed
+    Error,
+}
+
+/// A single line of the `--events` NDJSON stream
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Tile coordinate as `z/x/y`
+    pub tile: String,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Event {
+    pub fn new(coords: &TileCoord, outcome: Outcome) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            timestamp,
+            tile: format_tile_coord(coords),
+            outcome,
+            message: None,
+        }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+}
+
+/// Sending side of the event channel. Cheap to clone into workers.
+pub type EventSender = flume::Sender<Event>;
+
+/// Send an event if events are enabled. A closed channel is not an error;
+/// losing monitoring output shouldn't abort processing.
+pub fn emit(events: Option<&EventSender>, event: impl FnOnce() -> Event) {
+    if let Some(tx) = events {
+        let _ = tx.send(event());
+    }
+}
+
+/// Spawn a task writing events as NDJSON to `path` (`-` for stderr).
+/// The task finishes once every sender has been dropped.
+pub fn spawn_writer(path: &Path, tasks: &mut JoinSet<Result<()>>) -> Result<EventSender> {
+    let out: Box<dyn Write + Send> = if path == Path::new("-") {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(
+            File::create(path)
+                .with_context(|| format!("failed to create events file {}", path.display()))?,
+        )
+    };
+    let (tx, rx) = flume::unbounded::<Event>();
+    tasks.spawn_blocking(move || {
+        let mut out = BufWriter::new(out);
+        for event in rx.iter() {
+            serde_json::to_writer(&mut out, &event)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+        Ok(())
+    });
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_serialization() {
+        let coords = TileCoord::new(3, 1, 2).unwrap();
+
+        let json = serde_json::to_value(Event::new(&coords, Outcome::Skipped)).unwrap();
+        assert_eq!(json["tile"], "3/1/2");
+        assert_eq!(json["outcome"], "skipped");
+        assert!(json["timestamp"].as_u64().unwrap() > 0);
+        assert!(json.get("message").is_none());
+
+        let json =
+            serde_json::to_value(Event::new(&coords, Outcome::Error).with_message("bad")).unwrap();
+        assert_eq!(json["outcome"], "error");
+        assert_eq!(json["message"], "bad");
+    }
+}
@@ -1,12 +1,14 @@
 use super::expression_compiler::{CompiledExpression, ExpressionCompiler};
 use anyhow::{Result, anyhow};
-use geo::{BoundingRect, Intersects};
-use geo_types::{Geometry, Rect};
+use geo::{BooleanOps, BoundingRect, Coord, Intersects, MapCoords};
+use geo_types::{Geometry, LineString, MultiPolygon, Polygon, Rect};
 use geojson::Geometry as GeoJsonGeometry;
 use rstar::{AABB, RTree, RTreeObject};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use thiserror::Error;
 
 /// Represents a GeoJSON filtering specification
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,34 +32,102 @@ pub struct FilterFeature {
 pub struct FilterProperties {
     pub id: Option<String>,
     pub description: Option<String>,
+    /// A feature with `enabled: false` is skipped entirely (enabled if missing)
+    pub enabled: Option<bool>,
     pub layers: HashMap<String, LayerFilter>,
 }
 
 /// Filter rules for a specific layer
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LayerFilter {
     /// Expression to remove entire features
     pub feature: Option<Expression>,
     /// Expression to remove specific tags
     pub tag: Option<Expression>,
+    /// Rules for features of one geometry type (`Point`, `LineString` or `Polygon`),
+    /// used instead of `feature`/`tag` above for those features
+    #[serde(flatten, default, skip_serializing_if = "HashMap::is_empty")]
+    pub geometry_types: HashMap<String, LayerFilter>,
+}
+
+/// Geometry types a `LayerFilter` can have rules for, as reported by `["type"]`
+const GEOMETRY_TYPES: [&str; 3] = ["Point", "LineString", "Polygon"];
+
+/// A problem found while compiling a filter collection, with where it was found
+#[derive(Debug, Error)]
+#[error("{path}: {source:#}")]
+pub struct CompileError {
+    /// Location in the filter file, e.g. `features[0].properties.layers.roads.feature`
+    pub path: String,
+    #[source]
+    pub source: anyhow::Error,
 }
 
 impl LayerFilter {
     /// Compile the filter expressions for efficient evaluation
     pub fn compile(&self) -> Result<CompiledLayerFilter> {
-        let feature = if let Some(ref expr) = self.feature {
-            Some(ExpressionCompiler::compile(expr)?)
-        } else {
-            None
-        };
+        self.compile_all("")
+            .map_err(|mut errors| errors.remove(0).source)
+    }
 
-        let tag = if let Some(ref expr) = self.tag {
-            Some(ExpressionCompiler::compile(expr)?)
-        } else {
-            None
+    /// Compile both expressions, collecting every error under `path`
+    fn compile_all(&self, path: &str) -> Result<CompiledLayerFilter, Vec<CompileError>> {
+        let mut errors = Vec::new();
+        let mut compile = |name: &str, expr: &Option<Expression>| {
+            let expr = expr.as_ref()?;
+            ExpressionCompiler::compile(expr)
+                .map_err(|source| {
+                    errors.push(CompileError {
+                        path: format!("{path}.{name}"),
+                        source,
+                    })
+                })
+                .ok()
         };
+        let feature = compile("feature", &self.feature);
+        let tag = compile("tag", &self.tag);
 
-        Ok(CompiledLayerFilter { feature, tag })
+        // Sorted so problems are reported in a stable order
+        let mut geometry_type_names = self.geometry_types.keys().collect::<Vec<_>>();
+        geometry_type_names.sort();
+        let mut geometry_types = HashMap::new();
+        for name in geometry_type_names {
+            let rules = &self.geometry_types[name];
+            let rules_path = format!("{path}.{name}");
+            if !GEOMETRY_TYPES.contains(&name.as_str()) {
+                errors.push(CompileError {
+                    path: rules_path,
+                    source: anyhow!(
+                        "Unknown key; expected feature, tag or one of {}",
+                        GEOMETRY_TYPES.join(", ")
+                    ),
+                });
+                continue;
+            }
+            if !rules.geometry_types.is_empty() {
+                errors.push(CompileError {
+                    path: rules_path,
+                    source: anyhow!("Geometry type rules can't be nested"),
+                });
+                continue;
+            }
+            match rules.compile_all(&rules_path) {
+                Ok(compiled) => {
+                    geometry_types.insert(name.clone(), compiled);
+                }
+                Err(rules_errors) => errors.extend(rules_errors),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(CompiledLayerFilter {
+                feature,
+                tag,
+                geometry_types,
+            })
+        } else {
+            Err(errors)
+        }
     }
 }
 
@@ -66,6 +136,28 @@ impl LayerFilter {
 pub struct CompiledLayerFilter {
     pub feature: Option<CompiledExpression>,
     pub tag: Option<CompiledExpression>,
+    pub geometry_types: HashMap<String, CompiledLayerFilter>,
+}
+
+impl CompiledLayerFilter {
+    /// The rules for a feature of the given geometry type, if there are any
+    fn for_geometry_type(&self, geometry_type: Option<&str>) -> &CompiledLayerFilter {
+        geometry_type
+            .and_then(|t| self.geometry_types.get(t))
+            .unwrap_or(self)
+    }
+
+    /// Feature expression for the given geometry type, falling back to the layer's own
+    fn feature_expr(&self, geometry_type: Option<&str>) -> Option<&CompiledExpression> {
+        let rules = self.for_geometry_type(geometry_type);
+        rules.feature.as_ref().or(self.feature.as_ref())
+    }
+
+    /// Tag expression for the given geometry type, falling back to the layer's own
+    fn tag_expr(&self, geometry_type: Option<&str>) -> Option<&CompiledExpression> {
+        let rules = self.for_geometry_type(geometry_type);
+        rules.tag.as_ref().or(self.tag.as_ref())
+    }
 }
 
 /// Represents a filter expression using JSON array syntax
@@ -76,6 +168,7 @@ pub type Expression = Value;
 pub enum Operator {
     // Comparison
     Equal,
+    EqualIgnoreCase,
     NotEqual,
     LessThan,
     GreaterThan,
@@ -87,24 +180,57 @@ pub enum Operator {
     All,
     None,
     Not,
+    If,
 
     // Membership
     In,
+    NotIn,
+    Has,
+    NotHas,
+    IsNull,
+    IsNotNull,
 
     // String operations
     StartsWith,
     EndsWith,
     RegexMatch,
     RegexCapture,
+    RegexCaptureNamed,
+    SplitContains,
+    Split,
+    Length,
+    Substr,
+    NormalizeWhitespace,
+
+    // Math
+    Round,
+    Floor,
+    Ceil,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
 
     // Casting
     Boolean,
     Literal,
+    ParseJson,
+    IsJson,
 
     // Context
-    Tag,  // feature property lookup
-    Key,  // current tag key
-    Type, // feature geometry type (type)
+    Tag,              // feature property lookup
+    Key,              // current tag key
+    ValueType,        // type of the current tag value
+    PointInRegion,    // whether a lon/lat point is inside the filter feature's region
+    DistanceToRegion, // meters from the feature to the filter feature's region boundary
+    BboxIntersects,   // whether the feature's tile-local bounding box overlaps a literal box
+    Type,             // feature geometry type (type)
+    VertexCount,      // number of coordinates in the feature geometry
+    TagCount,         // number of tags on the feature
+    CommandCount,     // length of the feature's encoded MVT geometry
+    Zoom,             // zoom level of the tile
+    Area,             // area of the feature geometry in square meters
 }
 
 impl Operator {
@@ -112,6 +238,7 @@ impl Operator {
     pub fn from_str(s: &str) -> Result<Self> {
         match s {
             "==" => Ok(Operator::Equal),
+            "==i" | "ieq" => Ok(Operator::EqualIgnoreCase),
             "!=" => Ok(Operator::NotEqual),
             "<" => Ok(Operator::LessThan),
             ">" => Ok(Operator::GreaterThan),
@@ -122,32 +249,185 @@ impl Operator {
             "none" => Ok(Operator::None),
             "not" => Ok(Operator::Not),
             "!" => Ok(Operator::Not),
+            "if" => Ok(Operator::If),
             "in" => Ok(Operator::In),
+            "not-in" | "!in" => Ok(Operator::NotIn),
+            "has" | "tag-exists" => Ok(Operator::Has),
+            "!has" => Ok(Operator::NotHas),
+            "is-null" => Ok(Operator::IsNull),
+            "is-not-null" => Ok(Operator::IsNotNull),
             "starts-with" => Ok(Operator::StartsWith),
             "ends-with" => Ok(Operator::EndsWith),
             "regex-match" => Ok(Operator::RegexMatch),
             "regex-capture" => Ok(Operator::RegexCapture),
+            "regex-capture-named" => Ok(Operator::RegexCaptureNamed),
+            "split-contains" => Ok(Operator::SplitContains),
+            "split" => Ok(Operator::Split),
+            "length" => Ok(Operator::Length),
+            "substr" => Ok(Operator::Substr),
+            "normalize-whitespace" => Ok(Operator::NormalizeWhitespace),
+            "round" => Ok(Operator::Round),
+            "+" => Ok(Operator::Add),
+            "-" => Ok(Operator::Subtract),
+            "*" => Ok(Operator::Multiply),
+            "/" => Ok(Operator::Divide),
+            "^" => Ok(Operator::Power),
+            "floor" => Ok(Operator::Floor),
+            "ceil" => Ok(Operator::Ceil),
             "boolean" => Ok(Operator::Boolean),
             "literal" => Ok(Operator::Literal),
-            "tag" => Ok(Operator::Tag),
+            "parse-json" => Ok(Operator::ParseJson),
+            "is-json" => Ok(Operator::IsJson),
+            "tag" | "get" => Ok(Operator::Tag),
             "key" => Ok(Operator::Key),
-            "type" => Ok(Operator::Type),
+            "value-type" => Ok(Operator::ValueType),
+            "point-in-region" => Ok(Operator::PointInRegion),
+            "distance-to-region" => Ok(Operator::DistanceToRegion),
+            "bbox-intersects" => Ok(Operator::BboxIntersects),
+            "type" | "geometry-type" => Ok(Operator::Type),
+            "vertex-count" => Ok(Operator::VertexCount),
+            "tag-count" => Ok(Operator::TagCount),
+            "command-count" => Ok(Operator::CommandCount),
+            "zoom" => Ok(Operator::Zoom),
+            "area" => Ok(Operator::Area),
             _ => Err(anyhow!("Unknown operator: {}", s)),
         }
     }
+
+    /// Whether the operator measures the feature geometry, which is clipped to each
+    /// tile, so a feature spanning several tiles is measured once per fragment
+    pub fn sees_clipped_geometry(&self) -> bool {
+        matches!(
+            self,
+            Operator::VertexCount
+                | Operator::CommandCount
+                | Operator::Area
+                | Operator::DistanceToRegion
+        )
+    }
+}
+
+/// Operators in `expr` that measure clipped geometry (see
+/// [`Operator::sees_clipped_geometry`]), in the order they appear
+pub fn clipped_geometry_operators(expr: &Expression) -> Vec<String> {
+    let mut found = Vec::new();
+    if let Value::Array(items) = expr {
+        let operator = items.first().and_then(Value::as_str);
+        if let Some(name) = operator
+            && Operator::from_str(name).is_ok_and(|op| op.sees_clipped_geometry())
+            && !found.iter().any(|n| n == name)
+        {
+            found.push(name.to_string());
+        }
+        // Arguments of `literal` are data, not expressions
+        if operator != Some("literal") {
+            for name in items.iter().flat_map(clipped_geometry_operators) {
+                if !found.contains(&name) {
+                    found.push(name);
+                }
+            }
+        }
+    }
+    found
+}
+
+impl LayerFilter {
+    /// Expressions of this layer filter and its geometry type rules, with their paths
+    fn expressions(&self, path: &str) -> Vec<(String, &Expression)> {
+        let mut expressions = Vec::new();
+        if let Some(expr) = &self.feature {
+            expressions.push((format!("{path}.feature"), expr));
+        }
+        if let Some(expr) = &self.tag {
+            expressions.push((format!("{path}.tag"), expr));
+        }
+        let mut names = self.geometry_types.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            expressions.extend(self.geometry_types[name].expressions(&format!("{path}.{name}")));
+        }
+        expressions
+    }
 }
 
 impl FilterCollection {
+    /// Rules of enabled features that measure clipped geometry, as warnings: per-tile
+    /// fragments of a feature can be measured differently from the whole feature
+    pub fn clipped_geometry_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (index, feature) in self.features.iter().enumerate() {
+            if !feature.is_enabled() {
+                continue;
+            }
+            let mut layer_names = feature.properties.layers.keys().collect::<Vec<_>>();
+            layer_names.sort();
+            for layer_name in layer_names {
+                let path = format!("features[{index}].properties.layers.{layer_name}");
+                let layer = &feature.properties.layers[layer_name];
+                for (path, expr) in layer.expressions(&path) {
+                    let operators = clipped_geometry_operators(expr);
+                    if !operators.is_empty() {
+                        warnings.push(format!(
+                            "{path}: {} see(s) the geometry clipped to each tile; \
+                             a feature spanning several tiles is measured per tile",
+                            operators.join(", ")
+                        ));
+                    }
+                }
+            }
+        }
+        warnings
+    }
+
+    /// Apply the `*` rules of every feature to `layer` alone, e.g. for a filter file
+    /// named after the layer it is about
+    pub fn scope_to_layer(&mut self, layer: &str) -> Result<()> {
+        for (index, feature) in self.features.iter_mut().enumerate() {
+            let layers = &mut feature.properties.layers;
+            if let Some(rules) = layers.remove("*") {
+                if layers.contains_key(layer) {
+                    return Err(anyhow!(
+                        "features[{index}].properties.layers has rules for both * and {layer}"
+                    ));
+                }
+                layers.insert(layer.to_string(), rules);
+            }
+        }
+        Ok(())
+    }
+
     /// Compile the entire filter collection for efficient evaluation
     pub fn compile(&self) -> Result<CompiledFilterCollection> {
         let mut compiled_features = Vec::new();
 
-        for feature in &self.features {
+        for feature in self.features.iter().filter(|f| f.is_enabled()) {
             compiled_features.push(feature.compile()?);
         }
 
         Ok(CompiledFilterCollection::new(compiled_features))
     }
+
+    /// Compile the collection, reporting every problem instead of stopping at the first
+    pub fn compile_all(&self) -> Result<CompiledFilterCollection, Vec<CompileError>> {
+        let mut compiled_features = Vec::new();
+        let mut errors = Vec::new();
+
+        for (index, feature) in self.features.iter().enumerate() {
+            if !feature.is_enabled() {
+                continue;
+            }
+            match feature.compile_all(&format!("features[{index}]")) {
+                Ok(compiled) => compiled_features.push(compiled),
+                Err(feature_errors) => errors.extend(feature_errors),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(CompiledFilterCollection::new(compiled_features))
+        } else {
+            Err(errors)
+        }
+    }
 }
 
 /// Compiled version of FilterCollection for efficient evaluation
@@ -191,7 +471,16 @@ impl CompiledFilterCollection {
         let mut indexed_entries = Vec::new();
 
         for (index, feature) in features.iter().enumerate() {
-            if let Some(rect) = feature.geometry.bounding_rect() {
+            // Index each polygon of a multipolygon on its own, so the two halves of a
+            // geometry split at the antimeridian don't produce a globe-wide envelope
+            let rects = match &feature.geometry {
+                Geometry::MultiPolygon(polygons) => polygons
+                    .iter()
+                    .filter_map(|p| p.bounding_rect())
+                    .collect::<Vec<_>>(),
+                geometry => geometry.bounding_rect().into_iter().collect(),
+            };
+            for rect in rects {
                 indexed_entries.push(FeatureIndexEntry::new(index, &rect));
             }
         }
@@ -204,6 +493,16 @@ impl CompiledFilterCollection {
         }
     }
 
+    /// Combine several compiled collections, e.g. one per filter file, into one
+    pub fn merge(collections: impl IntoIterator<Item = Self>) -> Self {
+        Self::new(
+            collections
+                .into_iter()
+                .flat_map(|collection| collection.features)
+                .collect(),
+        )
+    }
+
     pub fn get_filter_features(&self, geom: &Geometry<f64>) -> Vec<&CompiledFilterFeature> {
         let mut results = Vec::new();
         let mut seen = HashSet::new();
@@ -234,16 +533,97 @@ impl CompiledFilterCollection {
 #[derive(Debug, Clone)]
 pub struct CompiledFilterFeature {
     pub geometry: Geometry<f64>,
+    /// The geometry in lon/lat, kept when `geometry` is projected to tile coordinates
+    pub region: Arc<Geometry<f64>>,
     pub layers: HashMap<String, CompiledLayerFilter>,
 }
 
+/// Whether an edge between two longitudes takes the short way across the antimeridian:
+/// it spans more than 180°, and isn't just running along the ±180° edge of the map
+/// (as in a whole-world polygon)
+fn is_antimeridian_jump(from_x: f64, to_x: f64) -> bool {
+    (to_x - from_x).abs() > 180.0 && !(from_x.abs() == 180.0 && to_x.abs() == 180.0)
+}
+
+fn crosses_antimeridian(ring: &LineString<f64>) -> bool {
+    ring.lines()
+        .any(|line| is_antimeridian_jump(line.start.x, line.end.x))
+}
+
+/// Shift longitudes so the ring is continuous across the antimeridian
+/// (the result may extend past ±180°)
+fn unwrap_ring(ring: &LineString<f64>) -> LineString<f64> {
+    let mut offset = 0.0;
+    let mut prev_x = None;
+    ring.coords()
+        .map(|&Coord { x, y }| {
+            if let Some(prev_x) = prev_x
+                && is_antimeridian_jump(prev_x, x)
+            {
+                offset += if x > prev_x { -360.0 } else { 360.0 };
+            }
+            prev_x = Some(x);
+            Coord { x: x + offset, y }
+        })
+        .collect()
+}
+
+/// Convert a filter feature's GeoJSON geometry. Empty geometries are an error: without a
+/// bounding rect they can't be indexed, so the feature would silently match nothing.
+fn filter_geometry(geometry: &GeoJsonGeometry) -> Result<Geometry<f64>> {
+    let geometry: Geometry<f64> = geometry.clone().try_into()?;
+    if geometry.bounding_rect().is_none() {
+        return Err(anyhow!(
+            "geometry is empty, so the feature would never match"
+        ));
+    }
+    Ok(split_antimeridian(geometry))
+}
+
+/// Split polygons crossing the antimeridian into parts east and west of it.
+/// Other geometries are returned unchanged.
+fn split_antimeridian(geometry: Geometry<f64>) -> Geometry<f64> {
+    let polygons = match &geometry {
+        Geometry::Polygon(polygon) => vec![polygon.clone()],
+        Geometry::MultiPolygon(polygons) => polygons.0.clone(),
+        _ => return geometry,
+    };
+    if !polygons.iter().any(|p| crosses_antimeridian(p.exterior())) {
+        return geometry;
+    }
+
+    let mut parts = Vec::new();
+    for polygon in polygons {
+        let unwrapped = Polygon::new(
+            unwrap_ring(polygon.exterior()),
+            polygon.interiors().iter().map(unwrap_ring).collect(),
+        );
+        // Cut the unwrapped polygon along each copy of the world and move the pieces back
+        for shift in [-360.0, 0.0, 360.0] {
+            let world = Rect::new((-180.0 - shift, -90.0), (180.0 - shift, 90.0)).to_polygon();
+            let piece = unwrapped
+                .intersection(&world)
+                .map_coords(|Coord { x, y }| Coord { x: x + shift, y });
+            parts.extend(piece);
+        }
+    }
+    Geometry::MultiPolygon(MultiPolygon::new(parts))
+}
+
 impl FilterFeature {
+    /// Whether the feature takes part in filtering; see [`FilterProperties::enabled`]
+    pub fn is_enabled(&self) -> bool {
+        self.properties.enabled != Some(false)
+    }
+
     /// Compile the filter feature for efficient evaluation
     pub fn compile(&self) -> Result<CompiledFilterFeature> {
         let compiled_layers = self.compile_layers()?;
+        let geometry = filter_geometry(&self.geometry)?;
 
         Ok(CompiledFilterFeature {
-            geometry: self.geometry.clone().try_into()?,
+            region: Arc::new(geometry.clone()),
+            geometry,
             layers: compiled_layers,
         })
     }
@@ -259,6 +639,43 @@ impl FilterFeature {
 
         Ok(compiled_layers)
     }
+
+    /// Compile the geometry and all layer filters, collecting every error under `path`
+    fn compile_all(&self, path: &str) -> Result<CompiledFilterFeature, Vec<CompileError>> {
+        let mut errors = Vec::new();
+
+        let geometry = filter_geometry(&self.geometry)
+            .map_err(|source| {
+                errors.push(CompileError {
+                    path: format!("{path}.geometry"),
+                    source,
+                })
+            })
+            .ok();
+
+        // Sorted so problems are reported in a stable order
+        let mut layer_names = self.properties.layers.keys().collect::<Vec<_>>();
+        layer_names.sort();
+        let mut layers = HashMap::new();
+        for layer_name in layer_names {
+            let layer_path = format!("{path}.properties.layers.{layer_name}");
+            match self.properties.layers[layer_name].compile_all(&layer_path) {
+                Ok(compiled) => {
+                    layers.insert(layer_name.clone(), compiled);
+                }
+                Err(layer_errors) => errors.extend(layer_errors),
+            }
+        }
+
+        match geometry {
+            Some(geometry) if errors.is_empty() => Ok(CompiledFilterFeature {
+                region: Arc::new(geometry.clone()),
+                geometry,
+                layers,
+            }),
+            _ => Err(errors),
+        }
+    }
 }
 
 impl CompiledFilterFeature {
@@ -268,16 +685,18 @@ impl CompiledFilterFeature {
         &self,
         context: &super::executor::EvaluationContext,
     ) -> Result<bool> {
+        let geometry_type = context.geometry_type.as_deref();
+
         // Check if there's a layer filter for this specific layer
         if let Some(layer_filter) = self.layers.get(&context.layer_name)
-            && let Some(ref feature_expr) = layer_filter.feature
+            && let Some(feature_expr) = layer_filter.feature_expr(geometry_type)
         {
             return super::executor::ExpressionExecutor::evaluate_bool(feature_expr, context);
         }
 
         // Check if there's a wildcard layer filter
         if let Some(layer_filter) = self.layers.get("*")
-            && let Some(ref feature_expr) = layer_filter.feature
+            && let Some(feature_expr) = layer_filter.feature_expr(geometry_type)
         {
             return super::executor::ExpressionExecutor::evaluate_bool(feature_expr, context);
         }
@@ -289,16 +708,18 @@ impl CompiledFilterFeature {
     /// Check if a specific tag should be removed
     /// Returns true if the tag should be removed (filtered out)
     pub fn should_remove_tag(&self, context: &super::executor::EvaluationContext) -> Result<bool> {
+        let geometry_type = context.geometry_type.as_deref();
+
         // Check if there's a layer filter for this specific layer
         if let Some(layer_filter) = self.layers.get(&context.layer_name)
-            && let Some(ref tag_expr) = layer_filter.tag
+            && let Some(tag_expr) = layer_filter.tag_expr(geometry_type)
         {
             return super::executor::ExpressionExecutor::evaluate_bool(tag_expr, context);
         }
 
         // Check if there's a wildcard layer filter
         if let Some(layer_filter) = self.layers.get("*")
-            && let Some(ref tag_expr) = layer_filter.tag
+            && let Some(tag_expr) = layer_filter.tag_expr(geometry_type)
         {
             return super::executor::ExpressionExecutor::evaluate_bool(tag_expr, context);
         }
@@ -358,6 +779,258 @@ mod tests {
         assert!(feature.properties.layers.contains_key("*"));
     }
 
+    #[test]
+    fn test_antimeridian_filter_is_split() {
+        let filter: FilterCollection = serde_json::from_value(json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[170, -10], [-170, -10], [-170, 10], [170, 10], [170, -10]]]
+                },
+                "properties": { "layers": { "*": { "feature": ["literal", true] } } }
+            }]
+        }))
+        .unwrap();
+        let compiled = filter.compile().unwrap();
+
+        let Geometry::MultiPolygon(parts) = &compiled.features[0].geometry else {
+            panic!("Expected a MultiPolygon");
+        };
+        assert_eq!(parts.0.len(), 2);
+        let mut spans = parts
+            .iter()
+            .map(|p| {
+                let rect = p.bounding_rect().unwrap();
+                (rect.min().x, rect.max().x)
+            })
+            .collect::<Vec<_>>();
+        spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert_eq!(spans, vec![(-180.0, -170.0), (170.0, 180.0)]);
+
+        let area = |min_lon: f64, max_lon: f64| -> Geometry<f64> {
+            Rect::new((min_lon, -1.0), (max_lon, 1.0)).into()
+        };
+        assert_eq!(compiled.get_filter_features(&area(175.0, 176.0)).len(), 1);
+        assert_eq!(compiled.get_filter_features(&area(-176.0, -175.0)).len(), 1);
+        assert!(compiled.get_filter_features(&area(0.0, 1.0)).is_empty());
+        assert!(
+            compiled
+                .get_filter_features(&area(-100.0, 100.0))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_compile_all_reports_every_error() {
+        let filter: FilterCollection = serde_json::from_value(json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [0, 0] },
+                    "properties": { "layers": {
+                        "roads": { "feature": ["no-such-operator", 1] },
+                        "water": { "feature": ["==", ["tag", "kind"], "lake"] }
+                    } }
+                },
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [0, 0] },
+                    "properties": { "layers": {
+                        "*": { "tag": ["starts-with", ["key"]] }
+                    } }
+                }
+            ]
+        }))
+        .unwrap();
+
+        assert!(filter.compile().is_err());
+        let errors = filter.compile_all().unwrap_err();
+        let paths = errors.iter().map(|e| e.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "features[0].properties.layers.roads.feature",
+                "features[1].properties.layers.*.tag",
+            ]
+        );
+        assert!(errors[0].to_string().contains("no-such-operator"));
+    }
+
+    #[test]
+    fn test_empty_filter_geometry_is_an_error() {
+        let filter: FilterCollection = serde_json::from_value(json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [139.7, 35.7] },
+                    "properties": { "layers": { "*": { "feature": ["literal", true] } } }
+                },
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "MultiPolygon", "coordinates": [] },
+                    "properties": { "layers": { "*": { "feature": ["literal", true] } } }
+                }
+            ]
+        }))
+        .unwrap();
+
+        assert!(filter.compile().is_err());
+        let errors = filter.compile_all().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "features[1].geometry");
+        assert!(errors[0].to_string().contains("empty"), "{}", errors[0]);
+
+        // A point has a zero-size bounding rect, and is indexed like any other geometry
+        let point = FilterCollection {
+            features: filter.features[..1].to_vec(),
+            ..filter
+        };
+        let compiled = point.compile_all().unwrap();
+        let nearby = Geometry::Point(geo_types::Point::new(139.7, 35.7));
+        assert_eq!(compiled.get_filter_features(&nearby).len(), 1);
+    }
+
+    #[test]
+    fn test_scope_to_layer() {
+        let mut filter: FilterCollection = serde_json::from_value(json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [0, 0] },
+                    "properties": { "layers": {
+                        "*": { "feature": ["literal", true] },
+                        "water": { "feature": ["literal", true] }
+                    } }
+                }
+            ]
+        }))
+        .unwrap();
+
+        filter.scope_to_layer("roads").unwrap();
+        let mut layers = filter.features[0]
+            .properties
+            .layers
+            .keys()
+            .collect::<Vec<_>>();
+        layers.sort();
+        assert_eq!(layers, vec!["roads", "water"]);
+
+        filter.features[0]
+            .properties
+            .layers
+            .insert("*".to_string(), LayerFilter::default());
+        assert!(filter.scope_to_layer("water").is_err());
+    }
+
+    #[test]
+    fn test_disabled_features_are_skipped() {
+        use super::super::executor::EvaluationContext;
+        use geozero::mvt::tile::Value as TagValue;
+
+        let filter: FilterCollection = serde_json::from_value(json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [0, 0] },
+                    "properties": {
+                        "enabled": false,
+                        "layers": { "*": { "feature": ["==", ["tag", "kind"], "park"] } }
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [0, 0] },
+                    "properties": {
+                        "enabled": true,
+                        "layers": { "*": { "feature": ["==", ["tag", "kind"], "school"] } }
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [0, 0] },
+                    "properties": {
+                        "enabled": false,
+                        "layers": { "*": { "feature": ["no-such-operator"] } }
+                    }
+                }
+            ]
+        }))
+        .unwrap();
+
+        let compiled = filter.compile_all().unwrap();
+        assert_eq!(compiled.features.len(), 1);
+        let removes = |kind: &str| {
+            let properties = HashMap::from([(
+                "kind".to_string(),
+                TagValue {
+                    string_value: Some(kind.to_string()),
+                    ..Default::default()
+                },
+            )]);
+            let ctx = EvaluationContext::new("pois", properties);
+            compiled
+                .features
+                .iter()
+                .any(|f| f.should_remove_feature(&ctx).unwrap())
+        };
+        assert!(removes("school"));
+        assert!(!removes("park"));
+    }
+
+    #[test]
+    fn test_clipped_geometry_warnings() {
+        assert_eq!(
+            clipped_geometry_operators(&json!([
+                "all",
+                [">", ["vertex-count"], 100],
+                ["<", ["distance-to-region"], 10],
+                ["in", ["tag", "kind"], ["literal", ["vertex-count"]]]
+            ])),
+            vec!["vertex-count", "distance-to-region"]
+        );
+        assert!(clipped_geometry_operators(&json!(["==", ["type"], "Polygon"])).is_empty());
+
+        let filter: FilterCollection = serde_json::from_value(json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [0, 0] },
+                    "properties": {
+                        "layers": {
+                            "roads": { "feature": ["==", ["tag", "kind"], "path"] },
+                            "buildings": {
+                                "Polygon": { "feature": [">", ["vertex-count"], 100] }
+                            }
+                        }
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [0, 0] },
+                    "properties": {
+                        "enabled": false,
+                        "layers": { "*": { "feature": [">", ["vertex-count"], 100] } }
+                    }
+                }
+            ]
+        }))
+        .unwrap();
+        let warnings = filter.clipped_geometry_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0].starts_with(
+                "features[0].properties.layers.buildings.Polygon.feature: vertex-count"
+            )
+        );
+    }
+
     #[test]
     fn test_validate_operators() {
         assert!(Operator::from_str("==").is_ok());
@@ -367,6 +1040,146 @@ mod tests {
         assert!(Operator::from_str("invalid-op").is_err());
     }
 
+    #[test]
+    fn test_operator_synonyms() {
+        assert_eq!(Operator::from_str("!in").unwrap(), Operator::NotIn);
+        assert_eq!(Operator::from_str("not-in").unwrap(), Operator::NotIn);
+        assert_eq!(Operator::from_str("get").unwrap(), Operator::Tag);
+        assert_eq!(Operator::from_str("geometry-type").unwrap(), Operator::Type);
+        assert_eq!(Operator::from_str("has").unwrap(), Operator::Has);
+        assert_eq!(Operator::from_str("!has").unwrap(), Operator::NotHas);
+        assert_eq!(Operator::from_str("tag-exists").unwrap(), Operator::Has);
+        assert_eq!(
+            Operator::from_str("==i").unwrap(),
+            Operator::EqualIgnoreCase
+        );
+        assert_eq!(
+            Operator::from_str("ieq").unwrap(),
+            Operator::EqualIgnoreCase
+        );
+    }
+
+    #[test]
+    fn test_tag_filter_sees_sibling_tags() {
+        use super::super::executor::EvaluationContext;
+        use geozero::mvt::tile::Value as TagValue;
+
+        let filter_feature = FilterFeature {
+            feature_type: "Feature".to_string(),
+            geometry: GeoJsonGeometry::new(Value::Point(vec![0.0, 0.0])),
+            properties: FilterProperties {
+                id: None,
+                description: None,
+                enabled: None,
+                layers: HashMap::from([(
+                    "*".to_string(),
+                    LayerFilter {
+                        feature: None,
+                        // Drop `name` when `name:en` is present on the same feature
+                        tag: Some(json!([
+                            "all",
+                            ["==", ["key"], "name"],
+                            ["tag-exists", "name:en"]
+                        ])),
+                        ..Default::default()
+                    },
+                )]),
+            },
+        };
+        let compiled = filter_feature.compile().unwrap();
+
+        let string_value = |s: &str| TagValue {
+            string_value: Some(s.to_string()),
+            ..Default::default()
+        };
+        let with_en = HashMap::from([
+            ("name".to_string(), string_value("東京")),
+            ("name:en".to_string(), string_value("Tokyo")),
+        ]);
+        let without_en = HashMap::from([("name".to_string(), string_value("東京"))]);
+
+        let ctx = EvaluationContext::new("places", with_en.clone()).with_current_key("name");
+        assert!(compiled.should_remove_tag(&ctx).unwrap());
+        let ctx = EvaluationContext::new("places", with_en).with_current_key("name:en");
+        assert!(!compiled.should_remove_tag(&ctx).unwrap());
+        let ctx = EvaluationContext::new("places", without_en).with_current_key("name");
+        assert!(!compiled.should_remove_tag(&ctx).unwrap());
+    }
+
+    #[test]
+    fn test_geometry_type_rules() {
+        use super::super::executor::EvaluationContext;
+        use geozero::mvt::tile::Value as TagValue;
+
+        let filter: FilterCollection = serde_json::from_value(json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": [0, 0] },
+                "properties": { "layers": { "pois": {
+                    "tag": ["==", ["key"], "name"],
+                    "Polygon": { "feature": ["==", ["tag", "kind"], "park"] },
+                    "Point": { "tag": ["==", ["key"], "kind"] }
+                } } }
+            }]
+        }))
+        .unwrap();
+        let compiled = filter.compile().unwrap();
+        let filter_feature = &compiled.features[0];
+
+        let park = HashMap::from([(
+            "kind".to_string(),
+            TagValue {
+                string_value: Some("park".to_string()),
+                ..Default::default()
+            },
+        )]);
+        let context = |geometry_type: &str| {
+            EvaluationContext::new("pois", park.clone()).with_geometry_type(geometry_type)
+        };
+
+        // The polygon-only rule drops polygons but leaves points in the same layer
+        assert!(
+            filter_feature
+                .should_remove_feature(&context("Polygon"))
+                .unwrap()
+        );
+        assert!(
+            !filter_feature
+                .should_remove_feature(&context("Point"))
+                .unwrap()
+        );
+
+        // Points use their own tag rule, other types the layer's flat one
+        let point_kind = context("Point").with_current_key("kind");
+        assert!(filter_feature.should_remove_tag(&point_kind).unwrap());
+        let point_name = context("Point").with_current_key("name");
+        assert!(!filter_feature.should_remove_tag(&point_name).unwrap());
+        let polygon_name = context("Polygon").with_current_key("name");
+        assert!(filter_feature.should_remove_tag(&polygon_name).unwrap());
+    }
+
+    #[test]
+    fn test_geometry_type_rules_reject_unknown_keys() {
+        let filter: FilterCollection = serde_json::from_value(json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": [0, 0] },
+                "properties": { "layers": { "pois": {
+                    "Polygons": { "feature": ["literal", true] }
+                } } }
+            }]
+        }))
+        .unwrap();
+
+        let errors = filter.compile_all().unwrap_err();
+        assert_eq!(
+            errors[0].path,
+            "features[0].properties.layers.pois.Polygons"
+        );
+    }
+
     #[test]
     fn test_layer_filter_with_both_feature_and_tag() {
         let json_str = r#"
@@ -452,6 +1265,7 @@ mod tests {
                 properties: FilterProperties {
                     id: Some("test-filter".to_string()),
                     description: Some("Test filter".to_string()),
+                    enabled: None,
                     layers: {
                         let mut map = HashMap::new();
                         map.insert(
@@ -459,6 +1273,7 @@ mod tests {
                             LayerFilter {
                                 feature: Some(json!(["==", ["tag", "test"], true])),
                                 tag: None,
+                                ..Default::default()
                             },
                         );
                         map
@@ -1,7 +1,11 @@
 use anyhow::Result;
+use geo::orient::{Direction, Orient};
+use geo::{BoundingRect, Contains, Distance, Euclidean, GeodesicArea, MapCoords};
+use geo_types::{Coord, Geometry, MultiLineString, Point, Polygon, Rect};
 use geozero::mvt::tile::Value;
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use super::expression_compiler::{CompiledExpression, ExpressionValue};
 
@@ -14,8 +18,24 @@ pub struct EvaluationContext {
     pub properties: HashMap<String, Value>,
     /// Current tag key being processed (for key-based operations)
     pub current_key: Option<String>,
+    /// Value of the current tag
+    pub current_value: Option<Value>,
     /// Feature geometry type
     pub geometry_type: Option<String>,
+    /// Number of coordinates in the feature geometry, summed across parts
+    pub vertex_count: Option<usize>,
+    /// Length of the feature's encoded MVT geometry (commands and parameters)
+    pub command_count: Option<usize>,
+    /// Zoom level of the tile the feature is in
+    pub zoom: Option<u8>,
+    /// Follow MapLibre semantics for comparisons and truthiness (see FILTERING.md)
+    pub maplibre_compat: bool,
+    /// Region (in lon/lat) of the filter feature being evaluated
+    pub region: Option<Arc<Geometry<f64>>>,
+    /// Feature geometry in lon/lat
+    pub geometry: Option<Arc<Geometry<f64>>>,
+    /// Bounding box of the feature geometry in tile coordinates
+    pub bounding_box: Option<Rect<f64>>,
 }
 
 impl EvaluationContext {
@@ -24,7 +44,15 @@ impl EvaluationContext {
             layer_name: layer_name.to_string(),
             properties,
             current_key: None,
+            current_value: None,
             geometry_type: None,
+            vertex_count: None,
+            command_count: None,
+            zoom: None,
+            maplibre_compat: false,
+            region: None,
+            geometry: None,
+            bounding_box: None,
         }
     }
 
@@ -33,10 +61,106 @@ impl EvaluationContext {
         self
     }
 
+    pub fn with_current_value(mut self, value: &Value) -> Self {
+        self.current_value = Some(value.clone());
+        self
+    }
+
     pub fn with_geometry_type(mut self, geometry_type: &str) -> Self {
         self.geometry_type = Some(geometry_type.to_string());
         self
     }
+
+    pub fn with_vertex_count(mut self, vertex_count: usize) -> Self {
+        self.vertex_count = Some(vertex_count);
+        self
+    }
+
+    pub fn with_command_count(mut self, command_count: usize) -> Self {
+        self.command_count = Some(command_count);
+        self
+    }
+
+    pub fn with_zoom(mut self, zoom: u8) -> Self {
+        self.zoom = Some(zoom);
+        self
+    }
+
+    pub fn with_maplibre_compat(mut self, enabled: bool) -> Self {
+        self.maplibre_compat = enabled;
+        self
+    }
+
+    pub fn with_region(mut self, region: Arc<Geometry<f64>>) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn with_geometry(mut self, geometry: Arc<Geometry<f64>>) -> Self {
+        self.geometry = Some(geometry);
+        self
+    }
+
+    pub fn with_bounding_box(mut self, bounding_box: Rect<f64>) -> Self {
+        self.bounding_box = Some(bounding_box);
+        self
+    }
+}
+
+/// Whether two boxes overlap, counting shared edges
+pub fn rects_overlap(a: &Rect<f64>, b: &Rect<f64>) -> bool {
+    a.min().x <= b.max().x
+        && a.max().x >= b.min().x
+        && a.min().y <= b.max().y
+        && a.max().y >= b.min().y
+}
+
+/// Area of a lon/lat geometry in square meters, whatever the winding of its rings.
+/// The geodesic area takes a clockwise exterior to enclose the rest of the Earth.
+fn geodesic_area(geometry: &Geometry<f64>) -> f64 {
+    match geometry {
+        Geometry::Polygon(polygon) => polygon.orient(Direction::Default).geodesic_area_unsigned(),
+        Geometry::MultiPolygon(polygons) => {
+            polygons.orient(Direction::Default).geodesic_area_unsigned()
+        }
+        Geometry::GeometryCollection(collection) => collection.iter().map(geodesic_area).sum(),
+        // Points and lines have no area
+        _ => 0.0,
+    }
+}
+
+/// Mean Earth radius in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Distance in meters from a lon/lat geometry to the boundary of a lon/lat region,
+/// whether the geometry is inside or outside it. Both are projected onto a plane
+/// tangent at the geometry's latitude, which is accurate for distances up to a few
+/// hundred kilometers.
+fn distance_to_boundary(geometry: &Geometry<f64>, region: &Geometry<f64>) -> f64 {
+    let rings = |polygon: &Polygon<f64>| {
+        std::iter::once(polygon.exterior().clone())
+            .chain(polygon.interiors().iter().cloned())
+            .collect::<Vec<_>>()
+    };
+    let boundary: Geometry<f64> = match region {
+        Geometry::Polygon(polygon) => MultiLineString::new(rings(polygon)).into(),
+        Geometry::MultiPolygon(polygons) => {
+            MultiLineString::new(polygons.iter().flat_map(rings).collect()).into()
+        }
+        other => other.clone(),
+    };
+    let latitude = geometry
+        .bounding_rect()
+        .map_or(0.0, |rect| rect.center().y)
+        .to_radians();
+    let meters_per_degree = EARTH_RADIUS.to_radians();
+    let project = |geom: &Geometry<f64>| {
+        geom.map_coords(|Coord { x, y }| Coord {
+            x: x * meters_per_degree * latitude.cos(),
+            y: y * meters_per_degree,
+        })
+    };
+    Euclidean.distance(&project(geometry), &project(&boundary))
 }
 
 /// Executes compiled expressions against feature data
@@ -51,55 +175,48 @@ impl ExpressionExecutor {
         match expr {
             // Comparison operations
             CompiledExpression::Equal(left, right) => {
+                let cmp = Self::compare_operands(left, right, context)?;
+                Ok(ExpressionValue::Boolean(cmp == Some(Ordering::Equal)))
+            }
+            CompiledExpression::EqualIgnoreCase(left, right) => {
                 let left_val = Self::evaluate(left, context)?;
                 let right_val = Self::evaluate(right, context)?;
                 Ok(ExpressionValue::Boolean(
-                    Self::compare_values(&left_val, &right_val) == Ordering::Equal,
+                    left_val.to_string().to_lowercase() == right_val.to_string().to_lowercase(),
                 ))
             }
             CompiledExpression::NotEqual(left, right) => {
-                let left_val = Self::evaluate(left, context)?;
-                let right_val = Self::evaluate(right, context)?;
-                Ok(ExpressionValue::Boolean(
-                    Self::compare_values(&left_val, &right_val) != Ordering::Equal,
-                ))
+                let cmp = Self::compare_operands(left, right, context)?;
+                Ok(ExpressionValue::Boolean(cmp != Some(Ordering::Equal)))
             }
             CompiledExpression::LessThan(left, right) => {
-                let left_val = Self::evaluate(left, context)?;
-                let right_val = Self::evaluate(right, context)?;
-                Ok(ExpressionValue::Boolean(
-                    Self::compare_values(&left_val, &right_val) == Ordering::Less,
-                ))
+                let cmp = Self::compare_operands(left, right, context)?;
+                Ok(ExpressionValue::Boolean(cmp == Some(Ordering::Less)))
             }
             CompiledExpression::GreaterThan(left, right) => {
-                let left_val = Self::evaluate(left, context)?;
-                let right_val = Self::evaluate(right, context)?;
-                Ok(ExpressionValue::Boolean(
-                    Self::compare_values(&left_val, &right_val) == Ordering::Greater,
-                ))
+                let cmp = Self::compare_operands(left, right, context)?;
+                Ok(ExpressionValue::Boolean(cmp == Some(Ordering::Greater)))
             }
             CompiledExpression::LessThanOrEqual(left, right) => {
-                let left_val = Self::evaluate(left, context)?;
-                let right_val = Self::evaluate(right, context)?;
-                let cmp = Self::compare_values(&left_val, &right_val);
-                Ok(ExpressionValue::Boolean(
-                    cmp == Ordering::Less || cmp == Ordering::Equal,
-                ))
+                let cmp = Self::compare_operands(left, right, context)?;
+                Ok(ExpressionValue::Boolean(matches!(
+                    cmp,
+                    Some(Ordering::Less | Ordering::Equal)
+                )))
             }
             CompiledExpression::GreaterThanOrEqual(left, right) => {
-                let left_val = Self::evaluate(left, context)?;
-                let right_val = Self::evaluate(right, context)?;
-                let cmp = Self::compare_values(&left_val, &right_val);
-                Ok(ExpressionValue::Boolean(
-                    cmp == Ordering::Greater || cmp == Ordering::Equal,
-                ))
+                let cmp = Self::compare_operands(left, right, context)?;
+                Ok(ExpressionValue::Boolean(matches!(
+                    cmp,
+                    Some(Ordering::Greater | Ordering::Equal)
+                )))
             }
 
             // Logical operations
             CompiledExpression::Any(exprs) => {
                 for expr in exprs {
                     let result = Self::evaluate(expr, context)?;
-                    if result.to_bool() {
+                    if Self::truthy(&result, context) {
                         return Ok(ExpressionValue::Boolean(true));
                     }
                 }
@@ -108,7 +225,7 @@ impl ExpressionExecutor {
             CompiledExpression::All(exprs) => {
                 for expr in exprs {
                     let result = Self::evaluate(expr, context)?;
-                    if !result.to_bool() {
+                    if !Self::truthy(&result, context) {
                         return Ok(ExpressionValue::Boolean(false));
                     }
                 }
@@ -117,7 +234,7 @@ impl ExpressionExecutor {
             CompiledExpression::None(exprs) => {
                 for expr in exprs {
                     let result = Self::evaluate(expr, context)?;
-                    if result.to_bool() {
+                    if Self::truthy(&result, context) {
                         return Ok(ExpressionValue::Boolean(false));
                     }
                 }
@@ -125,7 +242,15 @@ impl ExpressionExecutor {
             }
             CompiledExpression::Not(expr) => {
                 let result = Self::evaluate(expr, context)?;
-                Ok(ExpressionValue::Boolean(!result.to_bool()))
+                Ok(ExpressionValue::Boolean(!Self::truthy(&result, context)))
+            }
+            CompiledExpression::If(condition, then, otherwise) => {
+                let result = Self::evaluate(condition, context)?;
+                if Self::truthy(&result, context) {
+                    Self::evaluate(then, context)
+                } else {
+                    Self::evaluate(otherwise, context)
+                }
             }
 
             // Membership operations
@@ -133,6 +258,24 @@ impl ExpressionExecutor {
                 let val = Self::evaluate(expr, context)?;
                 Ok(ExpressionValue::Boolean(values.contains(&val)))
             }
+            CompiledExpression::NotIn(expr, values) => {
+                let val = Self::evaluate(expr, context)?;
+                Ok(ExpressionValue::Boolean(!values.contains(&val)))
+            }
+            CompiledExpression::InArray(expr, values) => {
+                let val = Self::evaluate(expr, context)?;
+                let contains = match Self::evaluate(values, context)? {
+                    ExpressionValue::Array(values) => values.contains(&val),
+                    _ => false,
+                };
+                Ok(ExpressionValue::Boolean(contains))
+            }
+            CompiledExpression::Has(tag_name) => Ok(ExpressionValue::Boolean(
+                context.properties.contains_key(tag_name.as_str()),
+            )),
+            CompiledExpression::IsNull(expr) => Ok(ExpressionValue::Boolean(
+                Self::evaluate(expr, context)? == ExpressionValue::Null,
+            )),
 
             // String operations
             CompiledExpression::StartsWith(expr, prefix) => {
@@ -145,6 +288,46 @@ impl ExpressionExecutor {
                 let str_val = val.to_string();
                 Ok(ExpressionValue::Boolean(str_val.ends_with(suffix)))
             }
+            CompiledExpression::SplitContains(expr, delimiter, target) => {
+                let contains = match Self::evaluate(expr, context)? {
+                    ExpressionValue::Null => false,
+                    val => val
+                        .to_string()
+                        .split(delimiter.as_str())
+                        .any(|part| part.trim() == target),
+                };
+                Ok(ExpressionValue::Boolean(contains))
+            }
+            CompiledExpression::Split(expr, delimiter) => match Self::evaluate(expr, context)? {
+                ExpressionValue::Null => Ok(ExpressionValue::Null),
+                val => Ok(ExpressionValue::Array(
+                    val.to_string()
+                        .split(delimiter.as_str())
+                        .map(|part| ExpressionValue::String(part.trim().to_string()))
+                        .collect(),
+                )),
+            },
+            CompiledExpression::Substr(expr, start, length) => match Self::evaluate(expr, context)?
+            {
+                ExpressionValue::String(s) => Ok(ExpressionValue::String(
+                    s.chars().skip(*start).take(*length).collect(),
+                )),
+                _ => Ok(ExpressionValue::Null),
+            },
+            CompiledExpression::NormalizeWhitespace(expr) => match Self::evaluate(expr, context)? {
+                ExpressionValue::Null => Ok(ExpressionValue::Null),
+                val => Ok(ExpressionValue::String(
+                    val.to_string()
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                )),
+            },
+            CompiledExpression::Length(expr) => match Self::evaluate(expr, context)? {
+                ExpressionValue::String(s) => Ok(ExpressionValue::Number(s.chars().count() as i64)),
+                ExpressionValue::Array(arr) => Ok(ExpressionValue::Number(arr.len() as i64)),
+                _ => Ok(ExpressionValue::Null),
+            },
             CompiledExpression::RegexMatch(expr, regex) => {
                 let val = Self::evaluate(expr, context)?;
                 let str_val = val.to_string();
@@ -163,13 +346,62 @@ impl ExpressionExecutor {
                     Ok(ExpressionValue::Null)
                 }
             }
+            CompiledExpression::RegexCaptureNamed(expr, regex, name) => {
+                let val = Self::evaluate(expr, context)?;
+                let str_val = val.to_string();
+                Ok(regex
+                    .captures(&str_val)
+                    .and_then(|captures| captures.name(name))
+                    .map_or(ExpressionValue::Null, |group| {
+                        ExpressionValue::String(group.as_str().to_string())
+                    }))
+            }
 
             // Value operations
+            CompiledExpression::Round(expr, multiple) => {
+                let val = Self::evaluate(expr, context)?;
+                Ok(Self::round_to(&val, *multiple, f64::round))
+            }
+            CompiledExpression::Floor(expr, multiple) => {
+                let val = Self::evaluate(expr, context)?;
+                Ok(Self::round_to(&val, *multiple, f64::floor))
+            }
+            CompiledExpression::Ceil(expr, multiple) => {
+                let val = Self::evaluate(expr, context)?;
+                Ok(Self::round_to(&val, *multiple, f64::ceil))
+            }
+            CompiledExpression::Add(left, right) => {
+                Self::arithmetic(left, right, context, |a, b| a + b)
+            }
+            CompiledExpression::Subtract(left, right) => {
+                Self::arithmetic(left, right, context, |a, b| a - b)
+            }
+            CompiledExpression::Multiply(left, right) => {
+                Self::arithmetic(left, right, context, |a, b| a * b)
+            }
+            CompiledExpression::Divide(left, right) => {
+                Self::arithmetic(left, right, context, |a, b| a / b)
+            }
+            CompiledExpression::Power(left, right) => {
+                Self::arithmetic(left, right, context, f64::powf)
+            }
             CompiledExpression::Boolean(expr) => {
                 let val = Self::evaluate(expr, context)?;
-                Ok(ExpressionValue::Boolean(val.to_bool()))
+                Ok(ExpressionValue::Boolean(Self::truthy(&val, context)))
             }
             CompiledExpression::Literal(value) => Ok(value.clone()),
+            CompiledExpression::ParseJson(expr) => match Self::evaluate(expr, context)? {
+                ExpressionValue::String(s) => Ok(serde_json::from_str(&s)
+                    .map(|json| ExpressionValue::from_json_value(&json))
+                    .unwrap_or(ExpressionValue::Null)),
+                _ => Ok(ExpressionValue::Null),
+            },
+            CompiledExpression::IsJson(expr) => match Self::evaluate(expr, context)? {
+                ExpressionValue::String(s) => Ok(ExpressionValue::Boolean(
+                    serde_json::from_str::<serde_json::Value>(&s).is_ok(),
+                )),
+                _ => Ok(ExpressionValue::Boolean(false)),
+            },
 
             // Context operations
             CompiledExpression::Tag(tag_name) => {
@@ -186,6 +418,39 @@ impl ExpressionExecutor {
                     Ok(ExpressionValue::Null)
                 }
             }
+            CompiledExpression::PointInRegion(lon, lat) => {
+                let lon = Self::as_f64(&Self::evaluate(lon, context)?);
+                let lat = Self::as_f64(&Self::evaluate(lat, context)?);
+                let inside = match (lon, lat, &context.region) {
+                    (Some(lon), Some(lat), Some(region)) => region.contains(&Point::new(lon, lat)),
+                    _ => false,
+                };
+                Ok(ExpressionValue::Boolean(inside))
+            }
+            CompiledExpression::DistanceToRegion => {
+                Ok(match (&context.geometry, &context.region) {
+                    (Some(geometry), Some(region)) => {
+                        ExpressionValue::Float(distance_to_boundary(geometry, region).to_string())
+                    }
+                    _ => ExpressionValue::Null,
+                })
+            }
+            CompiledExpression::ValueType => {
+                let value_type = match &context.current_value {
+                    Some(v) if v.string_value.is_some() => "string",
+                    Some(v)
+                        if v.int_value.is_some()
+                            || v.uint_value.is_some()
+                            || v.sint_value.is_some() =>
+                    {
+                        "number"
+                    }
+                    Some(v) if v.float_value.is_some() || v.double_value.is_some() => "float",
+                    Some(v) if v.bool_value.is_some() => "boolean",
+                    _ => "null",
+                };
+                Ok(ExpressionValue::String(value_type.to_string()))
+            }
             CompiledExpression::Type => {
                 if let Some(geom_type) = &context.geometry_type {
                     Ok(ExpressionValue::String(geom_type.clone()))
@@ -193,6 +458,131 @@ impl ExpressionExecutor {
                     Ok(ExpressionValue::Null)
                 }
             }
+            CompiledExpression::VertexCount => Ok(match context.vertex_count {
+                Some(count) => ExpressionValue::Number(count as i64),
+                None => ExpressionValue::Null,
+            }),
+            CompiledExpression::CommandCount => Ok(match context.command_count {
+                Some(count) => ExpressionValue::Number(count as i64),
+                None => ExpressionValue::Null,
+            }),
+            CompiledExpression::Zoom => Ok(match context.zoom {
+                Some(zoom) => ExpressionValue::Number(zoom as i64),
+                None => ExpressionValue::Null,
+            }),
+            CompiledExpression::Area => Ok(match &context.geometry {
+                Some(geometry) => ExpressionValue::Float(geodesic_area(geometry).to_string()),
+                None => ExpressionValue::Null,
+            }),
+            CompiledExpression::TagCount => {
+                Ok(ExpressionValue::Number(context.properties.len() as i64))
+            }
+            CompiledExpression::BboxIntersects(bbox) => Ok(match &context.bounding_box {
+                Some(bounding_box) => ExpressionValue::Boolean(rects_overlap(bounding_box, bbox)),
+                None => ExpressionValue::Null,
+            }),
+        }
+    }
+
+    /// Evaluate both operands of a comparison.
+    /// Returns `None` when the comparison is undefined, which only happens in MapLibre
+    /// compatibility mode when the operands have different types.
+    fn compare_operands(
+        left: &CompiledExpression,
+        right: &CompiledExpression,
+        context: &EvaluationContext,
+    ) -> Result<Option<Ordering>> {
+        let left_val = Self::evaluate(left, context)?;
+        let right_val = Self::evaluate(right, context)?;
+        if context.maplibre_compat && !Self::same_type(&left_val, &right_val) {
+            return Ok(None);
+        }
+        Ok(Some(Self::compare_values(&left_val, &right_val)))
+    }
+
+    /// Round a number to a whole `multiple` (1 if not given) with `round`. The result is
+    /// a `Number` when the multiple is whole, a `Float` otherwise; non-numbers give `Null`.
+    fn round_to(
+        value: &ExpressionValue,
+        multiple: Option<f64>,
+        round: fn(f64) -> f64,
+    ) -> ExpressionValue {
+        let value = match value {
+            ExpressionValue::Number(n) => *n as f64,
+            ExpressionValue::Float(f) => match f.parse::<f64>() {
+                Ok(f) => f,
+                Err(_) => return ExpressionValue::Null,
+            },
+            _ => return ExpressionValue::Null,
+        };
+        let multiple = multiple.unwrap_or(1.0);
+        if multiple.fract() == 0.0 {
+            ExpressionValue::Number((round(value / multiple) * multiple) as i64)
+        } else {
+            // Divide by the reciprocal so that e.g. a multiple of 0.1 gives 3.1, not 3.1000000000000001
+            let rounded = round(value / multiple) / multiple.recip();
+            ExpressionValue::Float(rounded.to_string())
+        }
+    }
+
+    /// Apply `op` to two numeric operands. The result is a `Number` when both operands
+    /// are integers and so is the result, a `Float` otherwise; non-numbers, division by
+    /// zero and other non-finite results give `Null`.
+    fn arithmetic(
+        left: &CompiledExpression,
+        right: &CompiledExpression,
+        context: &EvaluationContext,
+        op: fn(f64, f64) -> f64,
+    ) -> Result<ExpressionValue> {
+        let left = Self::evaluate(left, context)?;
+        let right = Self::evaluate(right, context)?;
+        let (Some(a), Some(b)) = (Self::as_f64(&left), Self::as_f64(&right)) else {
+            return Ok(ExpressionValue::Null);
+        };
+        let result = op(a, b);
+        let integers = matches!(
+            (&left, &right),
+            (ExpressionValue::Number(_), ExpressionValue::Number(_))
+        );
+        Ok(if !result.is_finite() {
+            ExpressionValue::Null
+        } else if integers && result.fract() == 0.0 && result.abs() < i64::MAX as f64 {
+            ExpressionValue::Number(result as i64)
+        } else {
+            ExpressionValue::Float(result.to_string())
+        })
+    }
+
+    /// The value of a number, `None` for anything else
+    fn as_f64(value: &ExpressionValue) -> Option<f64> {
+        match value {
+            ExpressionValue::Number(n) => Some(*n as f64),
+            ExpressionValue::Float(f) => f.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Whether two values have the same type, treating integers and floats as one numeric type
+    fn same_type(left: &ExpressionValue, right: &ExpressionValue) -> bool {
+        matches!(
+            (left, right),
+            (ExpressionValue::Null, ExpressionValue::Null)
+                | (ExpressionValue::Boolean(_), ExpressionValue::Boolean(_))
+                | (ExpressionValue::String(_), ExpressionValue::String(_))
+                | (ExpressionValue::Array(_), ExpressionValue::Array(_))
+                | (
+                    ExpressionValue::Number(_) | ExpressionValue::Float(_),
+                    ExpressionValue::Number(_) | ExpressionValue::Float(_)
+                )
+        )
+    }
+
+    /// Convert a value to boolean; MapLibre only treats `true` as true
+    fn truthy(value: &ExpressionValue, context: &EvaluationContext) -> bool {
+        if context.maplibre_compat {
+            matches!(value, ExpressionValue::Boolean(true))
+        } else {
+            value.to_bool()
         }
     }
 
@@ -229,6 +619,21 @@ impl ExpressionExecutor {
             // String comparisons
             (ExpressionValue::String(a), ExpressionValue::String(b)) => a.cmp(b),
 
+            // A number against a numeric string, e.g. a tag stored as text, compares
+            // numerically so that "9" < 10
+            (
+                ExpressionValue::String(s),
+                n @ (ExpressionValue::Number(_) | ExpressionValue::Float(_)),
+            ) if s.trim().parse::<f64>().is_ok() => {
+                let a: f64 = s.trim().parse().unwrap_or(0.0);
+                let b = Self::as_f64(n).unwrap_or(0.0);
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            }
+            (
+                n @ (ExpressionValue::Number(_) | ExpressionValue::Float(_)),
+                ExpressionValue::String(_),
+            ) => Self::compare_values(right, n).reverse(),
+
             // Mixed type comparisons - convert to strings
             _ => left.to_string().cmp(&right.to_string()),
         }
@@ -237,7 +642,7 @@ impl ExpressionExecutor {
     /// Evaluate expression and return boolean result
     pub fn evaluate_bool(expr: &CompiledExpression, context: &EvaluationContext) -> Result<bool> {
         let result = Self::evaluate(expr, context)?;
-        Ok(result.to_bool())
+        Ok(Self::truthy(&result, context))
     }
 }
 
@@ -318,6 +723,46 @@ mod tests {
         assert!(result);
     }
 
+    #[test]
+    fn test_equality_ignore_case() {
+        let context = create_test_context();
+
+        // "==" stays case-sensitive
+        let compiled =
+            ExpressionCompiler::compile(&json!(["==", ["tag", "kind"], "Park"])).unwrap();
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["==i", ["tag", "kind"], "Park"])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["ieq", ["tag", "name"], "CENTRAL park"])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["==i", ["tag", "kind"], "school"])).unwrap();
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+    }
+
+    #[test]
+    fn test_equality_ignore_case_non_string_operands() {
+        let context = create_test_context();
+
+        // Non-string operands are compared by their string forms
+        let compiled =
+            ExpressionCompiler::compile(&json!(["==i", ["tag", "public"], "TRUE"])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["==i", ["tag", "capacity"], "1000"])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["==i", ["tag", "capacity"], 1000])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+    }
+
     #[test]
     fn test_inequality_filter() {
         let context = create_test_context();
@@ -375,6 +820,42 @@ mod tests {
         assert!(result);
     }
 
+    #[test]
+    fn test_if() {
+        let context = create_test_context();
+        let evaluate = |expr| {
+            let compiled = ExpressionCompiler::compile(&expr).unwrap();
+            ExpressionExecutor::evaluate(&compiled, &context).unwrap()
+        };
+
+        assert_eq!(
+            evaluate(json!([
+                "if",
+                ["==", ["tag", "kind"], "park"],
+                "green",
+                "grey"
+            ])),
+            ExpressionValue::String("green".to_string())
+        );
+        assert_eq!(
+            evaluate(json!([
+                "if",
+                ["==", ["tag", "kind"], "school"],
+                "green",
+                "grey"
+            ])),
+            ExpressionValue::String("grey".to_string())
+        );
+        // A missing tag is null, which is false
+        assert_eq!(
+            evaluate(json!(["if", ["tag", "missing"], 1, ["tag", "capacity"]])),
+            ExpressionValue::Number(1000)
+        );
+
+        assert!(ExpressionCompiler::compile(&json!(["if", true, 1])).is_err());
+        assert!(ExpressionCompiler::compile(&json!(["if", true, 1, 2, 3])).is_err());
+    }
+
     #[test]
     fn test_membership_operations() {
         let context = create_test_context();
@@ -398,6 +879,18 @@ mod tests {
         let result = ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap();
         assert!(result);
 
+        // Test NOT-IN synonym
+        let expr_json = json!(["!in", ["get", "kind"], ["literal", ["school", "hospital"]]]);
+        let compiled = ExpressionCompiler::compile(&expr_json).unwrap();
+        let result = ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap();
+        assert!(result);
+
+        // Test HAS / !HAS
+        let compiled = ExpressionCompiler::compile(&json!(["has", "kind"])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+        let compiled = ExpressionCompiler::compile(&json!(["!has", "kind"])).unwrap();
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
         // Test IN operation with null
         let expr_json = json!(["in", null, ["literal", [null, "school", "hospital"]]]);
         let compiled = ExpressionCompiler::compile(&expr_json).unwrap();
@@ -405,6 +898,723 @@ mod tests {
         assert!(result);
     }
 
+    #[test]
+    fn test_parse_json() {
+        let mut context = create_test_context();
+        context.properties.insert(
+            "langs".to_string(),
+            Value {
+                string_value: Some(r#"["en","ja"]"#.to_string()),
+                ..Default::default()
+            },
+        );
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["parse-json", ["tag", "langs"]])).unwrap();
+        assert_eq!(
+            ExpressionExecutor::evaluate(&compiled, &context).unwrap(),
+            ExpressionValue::Array(vec![
+                ExpressionValue::String("en".to_string()),
+                ExpressionValue::String("ja".to_string()),
+            ])
+        );
+
+        // Strings that aren't JSON, and non-string values, parse to null
+        let compiled =
+            ExpressionCompiler::compile(&json!(["parse-json", ["tag", "name"]])).unwrap();
+        assert_eq!(
+            ExpressionExecutor::evaluate(&compiled, &context).unwrap(),
+            ExpressionValue::Null
+        );
+        let compiled =
+            ExpressionCompiler::compile(&json!(["parse-json", ["tag", "capacity"]])).unwrap();
+        assert_eq!(
+            ExpressionExecutor::evaluate(&compiled, &context).unwrap(),
+            ExpressionValue::Null
+        );
+    }
+
+    #[test]
+    fn test_is_json() {
+        let mut context = create_test_context();
+        for (key, value) in [
+            ("langs", r#"["en","ja"]"#),
+            ("extra", r#"{"floors": 3}"#),
+            ("truncated", r#"{"floors": "#),
+        ] {
+            context.properties.insert(
+                key.to_string(),
+                Value {
+                    string_value: Some(value.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+        let is_json = |key: &str| {
+            let compiled = ExpressionCompiler::compile(&json!(["is-json", ["tag", key]])).unwrap();
+            ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap()
+        };
+
+        assert!(is_json("langs"));
+        assert!(is_json("extra"));
+        // Invalid JSON
+        assert!(!is_json("truncated"));
+        assert!(!is_json("name"));
+        // Non-string values and missing tags
+        assert!(!is_json("capacity"));
+        assert!(!is_json("missing"));
+        assert!(ExpressionCompiler::compile(&json!(["is-json"])).is_err());
+    }
+
+    #[test]
+    fn test_membership_in_parsed_array() {
+        let mut context = create_test_context();
+        context.properties.insert(
+            "langs".to_string(),
+            Value {
+                string_value: Some(r#"["en","ja"]"#.to_string()),
+                ..Default::default()
+            },
+        );
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["in", "ja", ["parse-json", ["tag", "langs"]]]))
+                .unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["in", "fr", ["parse-json", ["tag", "langs"]]]))
+                .unwrap();
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["!in", "fr", ["parse-json", ["tag", "langs"]]]))
+                .unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        // A value that doesn't parse to an array contains nothing
+        let compiled =
+            ExpressionCompiler::compile(&json!(["in", "en", ["parse-json", ["tag", "name"]]]))
+                .unwrap();
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        // Literal values other than arrays are still rejected
+        assert!(ExpressionCompiler::compile(&json!(["in", "en", "en"])).is_err());
+    }
+
+    #[test]
+    fn test_split_contains() {
+        let mut context = create_test_context();
+        for (key, value) in [
+            ("cuisine", "italian;pizza"),
+            ("sport", "soccer , tennis,golf "),
+        ] {
+            context.properties.insert(
+                key.to_string(),
+                Value {
+                    string_value: Some(value.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+        let eval = |expr: serde_json::Value| {
+            let compiled = ExpressionCompiler::compile(&expr).unwrap();
+            ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap()
+        };
+
+        assert!(eval(json!([
+            "split-contains",
+            ["tag", "cuisine"],
+            ";",
+            "pizza"
+        ])));
+        assert!(eval(json!([
+            "split-contains",
+            ["tag", "cuisine"],
+            ";",
+            "italian"
+        ])));
+        assert!(!eval(json!([
+            "split-contains",
+            ["tag", "cuisine"],
+            ";",
+            "pizz"
+        ])));
+        assert!(!eval(json!([
+            "split-contains",
+            ["tag", "cuisine"],
+            ",",
+            "pizza"
+        ])));
+
+        // Parts are trimmed
+        assert!(eval(json!([
+            "split-contains",
+            ["tag", "sport"],
+            ",",
+            "soccer"
+        ])));
+        assert!(eval(json!([
+            "split-contains",
+            ["tag", "sport"],
+            ",",
+            "tennis"
+        ])));
+        assert!(eval(json!([
+            "split-contains",
+            ["tag", "sport"],
+            ",",
+            "golf"
+        ])));
+
+        // A missing tag contains nothing
+        assert!(!eval(json!([
+            "split-contains",
+            ["tag", "missing"],
+            ";",
+            "null"
+        ])));
+
+        assert!(
+            ExpressionCompiler::compile(&json!(["split-contains", ["tag", "sport"], "", "golf"]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rounding() {
+        let context = create_test_context();
+        let eval = |expr: serde_json::Value| {
+            let compiled = ExpressionCompiler::compile(&expr).unwrap();
+            ExpressionExecutor::evaluate(&compiled, &context).unwrap()
+        };
+
+        // area is 3.41
+        assert_eq!(
+            eval(json!(["round", ["tag", "area"]])),
+            ExpressionValue::Number(3)
+        );
+        assert_eq!(
+            eval(json!(["floor", ["tag", "area"]])),
+            ExpressionValue::Number(3)
+        );
+        assert_eq!(
+            eval(json!(["ceil", ["tag", "area"]])),
+            ExpressionValue::Number(4)
+        );
+        assert_eq!(eval(json!(["round", -2.5])), ExpressionValue::Number(-3));
+
+        // Integers are unchanged without a multiple
+        assert_eq!(
+            eval(json!(["round", ["tag", "capacity"]])),
+            ExpressionValue::Number(1000)
+        );
+        assert_eq!(eval(json!(["floor", 7])), ExpressionValue::Number(7));
+    }
+
+    #[test]
+    fn test_rounding_to_multiple() {
+        let context = create_test_context();
+        let eval = |expr: serde_json::Value| {
+            let compiled = ExpressionCompiler::compile(&expr).unwrap();
+            ExpressionExecutor::evaluate(&compiled, &context).unwrap()
+        };
+
+        assert_eq!(
+            eval(json!(["round", 123456, 1000])),
+            ExpressionValue::Number(123000)
+        );
+        assert_eq!(
+            eval(json!(["ceil", 123456, 1000])),
+            ExpressionValue::Number(124000)
+        );
+        assert_eq!(
+            eval(json!(["floor", ["tag", "capacity"], 300])),
+            ExpressionValue::Number(900)
+        );
+        assert_eq!(
+            eval(json!(["round", ["tag", "area"], 0.1])),
+            ExpressionValue::Float("3.4".to_string())
+        );
+        assert_eq!(
+            eval(json!(["ceil", ["tag", "area"], 0.25])),
+            ExpressionValue::Float("3.5".to_string())
+        );
+        // Rounding results compare like any number
+        let compiled =
+            ExpressionCompiler::compile(&json!(["==", ["round", 1499, 1000], 1000])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        // Non-numbers round to null
+        assert_eq!(
+            eval(json!(["round", ["tag", "name"]])),
+            ExpressionValue::Null
+        );
+        assert_eq!(
+            eval(json!(["round", ["tag", "missing"], 10])),
+            ExpressionValue::Null
+        );
+
+        assert!(ExpressionCompiler::compile(&json!(["round", 1, 0])).is_err());
+        assert!(ExpressionCompiler::compile(&json!(["round", 1, "10"])).is_err());
+        assert!(ExpressionCompiler::compile(&json!(["round"])).is_err());
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        let mut context = create_test_context();
+        for (key, value) in [
+            ("tabs", "Central\t\tPark"),
+            ("newlines", "Central\nPark\r\n"),
+            ("spaces", "  Central    Park  "),
+            ("mixed", " \tCentral \n\t Park\t"),
+            ("blank", " \t\n "),
+        ] {
+            context.properties.insert(
+                key.to_string(),
+                Value {
+                    string_value: Some(value.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+        let eval = |key: &str| {
+            let compiled =
+                ExpressionCompiler::compile(&json!(["normalize-whitespace", ["tag", key]]))
+                    .unwrap();
+            ExpressionExecutor::evaluate(&compiled, &context).unwrap()
+        };
+        let string = |s: &str| ExpressionValue::String(s.to_string());
+
+        for key in ["tabs", "newlines", "spaces", "mixed"] {
+            assert_eq!(eval(key), string("Central Park"), "{key}");
+        }
+        assert_eq!(eval("blank"), string(""));
+        assert_eq!(eval("missing"), ExpressionValue::Null);
+        assert!(ExpressionCompiler::compile(&json!(["normalize-whitespace"])).is_err());
+    }
+
+    #[test]
+    fn test_substr() {
+        let mut context = create_test_context();
+        for (key, value) in [("lang", "en-US"), ("name:ja", "東京タワー")] {
+            context.properties.insert(
+                key.to_string(),
+                Value {
+                    string_value: Some(value.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+        let eval = |expr: serde_json::Value| {
+            let compiled = ExpressionCompiler::compile(&expr).unwrap();
+            ExpressionExecutor::evaluate(&compiled, &context).unwrap()
+        };
+        let string = |s: &str| ExpressionValue::String(s.to_string());
+
+        assert_eq!(eval(json!(["substr", ["tag", "lang"], 0, 2])), string("en"));
+        assert_eq!(eval(json!(["substr", ["tag", "lang"], 3, 2])), string("US"));
+        // Counted in characters, not bytes
+        assert_eq!(
+            eval(json!(["substr", ["tag", "name:ja"], 0, 2])),
+            string("東京")
+        );
+        assert_eq!(
+            eval(json!(["substr", ["tag", "name:ja"], 2, 3])),
+            string("タワー")
+        );
+        // Out-of-range indices are clamped to the string
+        assert_eq!(
+            eval(json!(["substr", ["tag", "lang"], 3, 100])),
+            string("US")
+        );
+        assert_eq!(eval(json!(["substr", ["tag", "lang"], 10, 2])), string(""));
+        // Non-strings give null
+        assert_eq!(
+            eval(json!(["substr", ["tag", "capacity"], 0, 2])),
+            ExpressionValue::Null
+        );
+        assert_eq!(
+            eval(json!(["substr", ["tag", "missing"], 0, 2])),
+            ExpressionValue::Null
+        );
+
+        assert!(ExpressionCompiler::compile(&json!(["substr", ["tag", "lang"], -1, 2])).is_err());
+        assert!(ExpressionCompiler::compile(&json!(["substr", ["tag", "lang"], 0])).is_err());
+    }
+
+    #[test]
+    fn test_split_and_length() {
+        let mut context = create_test_context();
+        for (key, value) in [("cuisine", "italian; pizza"), ("sport", "soccer")] {
+            context.properties.insert(
+                key.to_string(),
+                Value {
+                    string_value: Some(value.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+        let eval = |expr: serde_json::Value| {
+            let compiled = ExpressionCompiler::compile(&expr).unwrap();
+            ExpressionExecutor::evaluate(&compiled, &context).unwrap()
+        };
+
+        assert_eq!(
+            eval(json!(["split", ["tag", "cuisine"], ";"])),
+            ExpressionValue::Array(vec![
+                ExpressionValue::String("italian".to_string()),
+                ExpressionValue::String("pizza".to_string()),
+            ])
+        );
+        assert_eq!(
+            eval(json!(["length", ["split", ["tag", "cuisine"], ";"]])),
+            ExpressionValue::Number(2)
+        );
+        assert_eq!(
+            eval(json!(["length", ["split", ["tag", "sport"], ";"]])),
+            ExpressionValue::Number(1)
+        );
+        assert_eq!(
+            eval(json!(["length", ["tag", "sport"]])),
+            ExpressionValue::Number(6)
+        );
+        assert_eq!(
+            eval(json!(["split", ["tag", "missing"], ";"])),
+            ExpressionValue::Null
+        );
+        assert_eq!(
+            eval(json!(["length", ["tag", "capacity"]])),
+            ExpressionValue::Null
+        );
+
+        let compiled = ExpressionCompiler::compile(&json!([
+            ">",
+            ["length", ["split", ["tag", "cuisine"], ";"]],
+            1
+        ]))
+        .unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+        let compiled = ExpressionCompiler::compile(&json!([
+            ">",
+            ["length", ["split", ["tag", "sport"], ";"]],
+            1
+        ]))
+        .unwrap();
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+    }
+
+    #[test]
+    fn test_point_in_region() {
+        let mut context = create_test_context();
+        let number = |v: f64| Value {
+            double_value: Some(v),
+            ..Default::default()
+        };
+        let region: Geometry<f64> = geo_types::Rect::new((139.0, 35.0), (140.0, 36.0))
+            .to_polygon()
+            .into();
+        let inside = |lon: Value, lat: Value, context: &mut EvaluationContext| {
+            context.properties.insert("lon".to_string(), lon);
+            context.properties.insert("lat".to_string(), lat);
+            let compiled = ExpressionCompiler::compile(&json!([
+                "point-in-region",
+                ["tag", "lon"],
+                ["tag", "lat"]
+            ]))
+            .unwrap();
+            ExpressionExecutor::evaluate_bool(&compiled, context).unwrap()
+        };
+
+        // Without a filter feature there is no region
+        assert!(!inside(number(139.5), number(35.5), &mut context));
+
+        context = context.with_region(Arc::new(region));
+        assert!(inside(number(139.5), number(35.5), &mut context));
+        assert!(!inside(number(141.0), number(35.5), &mut context));
+        assert!(!inside(number(139.5), number(34.0), &mut context));
+        let integer = Value {
+            int_value: Some(139),
+            ..Default::default()
+        };
+        assert!(!inside(integer, number(35.5), &mut context), "on the edge");
+
+        // Missing or non-numeric coordinates are never inside
+        context.properties.remove("lat");
+        let compiled = ExpressionCompiler::compile(&json!([
+            "point-in-region",
+            ["tag", "lon"],
+            ["tag", "lat"]
+        ]))
+        .unwrap();
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+        let text = Value {
+            string_value: Some("139.5".to_string()),
+            ..Default::default()
+        };
+        assert!(!inside(text, number(35.5), &mut context));
+    }
+
+    #[test]
+    fn test_tag_count() {
+        let count = ExpressionCompiler::compile(&json!(["tag-count"])).unwrap();
+        let over_tagged = ExpressionCompiler::compile(&json!([">", ["tag-count"], 3])).unwrap();
+
+        let context = create_test_context();
+        assert_eq!(
+            ExpressionExecutor::evaluate(&count, &context).unwrap(),
+            ExpressionValue::Number(5)
+        );
+        assert!(ExpressionExecutor::evaluate_bool(&over_tagged, &context).unwrap());
+
+        let properties = HashMap::from([(
+            "kind".to_string(),
+            Value {
+                string_value: Some("park".to_string()),
+                ..Default::default()
+            },
+        )]);
+        let context = EvaluationContext::new("test", properties);
+        assert_eq!(
+            ExpressionExecutor::evaluate(&count, &context).unwrap(),
+            ExpressionValue::Number(1)
+        );
+        assert!(!ExpressionExecutor::evaluate_bool(&over_tagged, &context).unwrap());
+
+        let context = EvaluationContext::new("test", HashMap::new());
+        assert_eq!(
+            ExpressionExecutor::evaluate(&count, &context).unwrap(),
+            ExpressionValue::Number(0)
+        );
+
+        assert!(ExpressionCompiler::compile(&json!(["tag-count", "kind"])).is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let context = create_test_context();
+        let eval = |expr: serde_json::Value| {
+            let compiled = ExpressionCompiler::compile(&expr).unwrap();
+            ExpressionExecutor::evaluate(&compiled, &context).unwrap()
+        };
+
+        assert_eq!(eval(json!(["+", 1, 2, 3])), ExpressionValue::Number(6));
+        assert_eq!(eval(json!(["-", 14, 10])), ExpressionValue::Number(4));
+        assert_eq!(
+            eval(json!(["*", 100, ["^", 2, 4]])),
+            ExpressionValue::Number(1600)
+        );
+        assert_eq!(
+            eval(json!(["*", ["tag", "area"], 2])),
+            ExpressionValue::Float("6.82".to_string())
+        );
+        assert_eq!(
+            eval(json!(["/", 7, 2])),
+            ExpressionValue::Float("3.5".to_string())
+        );
+        assert_eq!(
+            eval(json!(["^", 2, -1])),
+            ExpressionValue::Float("0.5".to_string())
+        );
+        // Non-numbers and division by zero
+        assert_eq!(
+            eval(json!(["+", ["tag", "name"], 1])),
+            ExpressionValue::Null
+        );
+        assert_eq!(eval(json!(["/", 1, 0])), ExpressionValue::Null);
+
+        assert!(ExpressionCompiler::compile(&json!(["+", 1])).is_err());
+        assert!(ExpressionCompiler::compile(&json!(["-", 1, 2, 3])).is_err());
+    }
+
+    #[test]
+    fn test_zoom_and_area() {
+        let zoom = ExpressionCompiler::compile(&json!(["zoom"])).unwrap();
+        let area = ExpressionCompiler::compile(&json!(["area"])).unwrap();
+        let context = create_test_context();
+        assert_eq!(
+            ExpressionExecutor::evaluate(&zoom, &context).unwrap(),
+            ExpressionValue::Null
+        );
+        assert_eq!(
+            ExpressionExecutor::evaluate(&area, &context).unwrap(),
+            ExpressionValue::Null
+        );
+
+        // About 1.11 km by 1.11 km at the equator
+        let square = geo_types::Rect::new((0.0, 0.0), (0.01, 0.01)).to_polygon();
+        let context = create_test_context()
+            .with_zoom(12)
+            .with_geometry(Arc::new(square.clone().into()));
+        assert_eq!(
+            ExpressionExecutor::evaluate(&zoom, &context).unwrap(),
+            ExpressionValue::Number(12)
+        );
+        let value = ExpressionExecutor::evaluate(&area, &context).unwrap();
+        let square_meters = ExpressionExecutor::as_f64(&value).unwrap();
+        assert!(
+            (square_meters - 1_230_900.0).abs() < 1_000.0,
+            "{}",
+            square_meters
+        );
+
+        // The winding of the ring doesn't matter, as after a y-down tile projection
+        let clockwise = square.orient(Direction::Reversed);
+        let context = create_test_context().with_geometry(Arc::new(clockwise.into()));
+        assert_eq!(
+            ExpressionExecutor::evaluate(&area, &context).unwrap(),
+            value
+        );
+
+        // Points and lines have no area
+        let context = create_test_context().with_geometry(Arc::new(Point::new(0.0, 0.0).into()));
+        assert_eq!(
+            ExpressionExecutor::evaluate(&area, &context).unwrap(),
+            ExpressionValue::Float("0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_command_count() {
+        let compiled = ExpressionCompiler::compile(&json!([">", ["command-count"], 100])).unwrap();
+        let count = ExpressionCompiler::compile(&json!(["command-count"])).unwrap();
+
+        // Without an encoded geometry there is no count
+        let context = create_test_context();
+        assert_eq!(
+            ExpressionExecutor::evaluate(&count, &context).unwrap(),
+            ExpressionValue::Null
+        );
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        // A point: MoveTo(1) and one x/y pair
+        let context = create_test_context().with_command_count(3);
+        assert_eq!(
+            ExpressionExecutor::evaluate(&count, &context).unwrap(),
+            ExpressionValue::Number(3)
+        );
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        let context = create_test_context().with_command_count(1000);
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+    }
+
+    #[test]
+    fn test_vertex_count() {
+        use geo::CoordsIter;
+
+        let compiled = ExpressionCompiler::compile(&json!([">", ["vertex-count"], 5])).unwrap();
+        let count = ExpressionCompiler::compile(&json!(["vertex-count"])).unwrap();
+
+        // Without a geometry there is no count
+        let context = create_test_context();
+        assert_eq!(
+            ExpressionExecutor::evaluate(&count, &context).unwrap(),
+            ExpressionValue::Null
+        );
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        // A square ring has 5 coordinates, including the closing one
+        let square = geo_types::Rect::new((0.0, 0.0), (1.0, 1.0)).to_polygon();
+        let geometry: Geometry<f64> = square.clone().into();
+        let context = create_test_context().with_vertex_count(geometry.coords_count());
+        assert_eq!(
+            ExpressionExecutor::evaluate(&count, &context).unwrap(),
+            ExpressionValue::Number(5)
+        );
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+
+        // Multi-geometries sum their parts
+        let geometry: Geometry<f64> =
+            geo_types::MultiPolygon::new(vec![square.clone(), square]).into();
+        let context = create_test_context().with_vertex_count(geometry.coords_count());
+        assert_eq!(
+            ExpressionExecutor::evaluate(&count, &context).unwrap(),
+            ExpressionValue::Number(10)
+        );
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+    }
+
+    #[test]
+    fn test_bbox_intersects() {
+        let compiled =
+            ExpressionCompiler::compile(&json!(["bbox-intersects", 0, 0, 2048, 2048])).unwrap();
+        let context_for = |min: (f64, f64), max: (f64, f64)| {
+            create_test_context().with_bounding_box(Rect::new(min, max))
+        };
+        let intersects = |context: &EvaluationContext| {
+            ExpressionExecutor::evaluate_bool(&compiled, context).unwrap()
+        };
+
+        // Overlapping, contained, and touching at an edge
+        assert!(intersects(&context_for((1000.0, 1000.0), (3000.0, 3000.0))));
+        assert!(intersects(&context_for((10.0, 10.0), (20.0, 20.0))));
+        assert!(intersects(&context_for((2048.0, 0.0), (4096.0, 100.0))));
+        // Apart on one axis only
+        assert!(!intersects(&context_for((3000.0, 0.0), (4096.0, 100.0))));
+        assert!(!intersects(&context_for((0.0, 2049.0), (100.0, 4096.0))));
+
+        // Without a geometry there is no bounding box
+        assert_eq!(
+            ExpressionExecutor::evaluate(&compiled, &create_test_context()).unwrap(),
+            ExpressionValue::Null
+        );
+
+        assert!(ExpressionCompiler::compile(&json!(["bbox-intersects", 0, 0, 1])).is_err());
+        assert!(ExpressionCompiler::compile(&json!(["bbox-intersects", 0, 0, "a", 1])).is_err());
+        assert!(ExpressionCompiler::compile(&json!(["bbox-intersects", 5, 0, 1, 1])).is_err());
+    }
+
+    #[test]
+    fn test_distance_to_region() {
+        let region: Geometry<f64> = geo_types::Rect::new((139.0, 35.0), (140.0, 36.0))
+            .to_polygon()
+            .into();
+        let distance = ExpressionCompiler::compile(&json!(["distance-to-region"])).unwrap();
+        let far =
+            ExpressionCompiler::compile(&json!([">", ["distance-to-region"], 50000])).unwrap();
+        let context_at = |lon: f64, lat: f64| {
+            create_test_context()
+                .with_region(Arc::new(region.clone()))
+                .with_geometry(Arc::new(Point::new(lon, lat).into()))
+        };
+        let meters =
+            |context: &EvaluationContext| match ExpressionExecutor::evaluate(&distance, context)
+                .unwrap()
+            {
+                ExpressionValue::Float(f) => f.parse::<f64>().unwrap(),
+                other => panic!("Expected a distance, got {:?}", other),
+            };
+
+        // Inside, half a degree of longitude from the western and eastern edges
+        let inside = context_at(139.5, 35.5);
+        assert!(
+            (meters(&inside) - 45_263.0).abs() < 1.0,
+            "{}",
+            meters(&inside)
+        );
+        assert!(!ExpressionExecutor::evaluate_bool(&far, &inside).unwrap());
+
+        // Outside, a degree of longitude east of the region
+        let outside = context_at(141.0, 35.5);
+        assert!(
+            (meters(&outside) - 90_526.0).abs() < 1.0,
+            "{}",
+            meters(&outside)
+        );
+        assert!(ExpressionExecutor::evaluate_bool(&far, &outside).unwrap());
+
+        // On the edge
+        assert!(meters(&context_at(140.0, 35.5)) < 1e-6);
+
+        // Without a region or a geometry there is no distance
+        let context = create_test_context().with_geometry(Arc::new(Point::new(0.0, 0.0).into()));
+        assert_eq!(
+            ExpressionExecutor::evaluate(&distance, &context).unwrap(),
+            ExpressionValue::Null
+        );
+    }
+
     #[test]
     fn test_string_operations() {
         let context = create_test_context();
@@ -467,6 +1677,30 @@ mod tests {
         assert!(!result); // Should be false since null != "value"
     }
 
+    #[test]
+    fn test_is_null() {
+        let context = create_test_context();
+        let check = |expr: serde_json::Value| {
+            let compiled = ExpressionCompiler::compile(&expr).unwrap();
+            ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap()
+        };
+
+        // Present tag
+        assert!(!check(json!(["is-null", ["tag", "kind"]])));
+        assert!(check(json!(["is-not-null", ["tag", "kind"]])));
+        // Missing tag
+        assert!(check(json!(["is-null", ["tag", "nonexistent"]])));
+        assert!(!check(json!(["is-not-null", ["tag", "nonexistent"]])));
+        // Literal null
+        assert!(check(json!(["is-null", null])));
+        assert!(!check(json!(["is-not-null", null])));
+        // Falsy values aren't null
+        assert!(!check(json!(["is-null", false])));
+        assert!(!check(json!(["is-null", ""])));
+
+        assert!(ExpressionCompiler::compile(&json!(["is-null"])).is_err());
+    }
+
     #[test]
     fn test_complex_filter_example() {
         let context = create_test_context();
@@ -500,6 +1734,36 @@ mod tests {
         assert_eq!(result, ExpressionValue::String("Central".to_string()));
     }
 
+    #[test]
+    fn test_regex_capture_named() {
+        let context = create_test_context();
+
+        let expr_json = json!([
+            "regex-capture-named",
+            ["tag", "name"],
+            r"^(?<first>\w+)\s+(?<second>\w+)",
+            "second"
+        ]);
+        let compiled = ExpressionCompiler::compile(&expr_json).unwrap();
+        let result = ExpressionExecutor::evaluate(&compiled, &context).unwrap();
+        assert_eq!(result, ExpressionValue::String("Park".to_string()));
+
+        // No match gives null
+        let expr_json = json!([
+            "regex-capture-named",
+            ["tag", "name"],
+            r"^(?<digits>\d+)$",
+            "digits"
+        ]);
+        let compiled = ExpressionCompiler::compile(&expr_json).unwrap();
+        let result = ExpressionExecutor::evaluate(&compiled, &context).unwrap();
+        assert_eq!(result, ExpressionValue::Null);
+
+        // The group must exist in the pattern
+        let expr_json = json!(["regex-capture-named", ["tag", "name"], r"^(\w+)", "first"]);
+        assert!(ExpressionCompiler::compile(&expr_json).is_err());
+    }
+
     #[test]
     fn test_complex_regex_capture_filter() {
         let mut context = create_test_context();
@@ -543,4 +1807,189 @@ mod tests {
         // 3. null is in [null, "ja"] ✗
         assert!(!result);
     }
+
+    #[test]
+    fn test_compare_two_tags() {
+        let string = |s: &str| Value {
+            string_value: Some(s.to_string()),
+            ..Default::default()
+        };
+        let int = |n: i64| Value {
+            int_value: Some(n),
+            ..Default::default()
+        };
+        let properties = HashMap::from([
+            ("min".to_string(), int(3)),
+            (
+                "max".to_string(),
+                Value {
+                    double_value: Some(12.5),
+                    ..Default::default()
+                },
+            ),
+            ("from".to_string(), string("apple")),
+            ("to".to_string(), string("banana")),
+            ("text_nine".to_string(), string("9")),
+            ("text_ten".to_string(), string("10")),
+            ("ten".to_string(), int(10)),
+            (
+                "flag".to_string(),
+                Value {
+                    bool_value: Some(true),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let context = EvaluationContext::new("test", properties);
+        let eval = |expr: serde_json::Value| {
+            let compiled = ExpressionCompiler::compile(&expr).unwrap();
+            ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap()
+        };
+
+        // Two numeric tags, integer against float
+        assert!(eval(json!([">", ["tag", "max"], ["tag", "min"]])));
+        assert!(!eval(json!(["<=", ["tag", "max"], ["tag", "min"]])));
+        // Two string tags compare lexically, even when they hold numbers
+        assert!(eval(json!(["<", ["tag", "from"], ["tag", "to"]])));
+        assert!(eval(json!([
+            ">",
+            ["tag", "text_nine"],
+            ["tag", "text_ten"]
+        ])));
+        // A numeric string against a number compares numerically, from either side
+        assert!(eval(json!(["<", ["tag", "text_nine"], ["tag", "ten"]])));
+        assert!(eval(json!([">", ["tag", "ten"], ["tag", "text_nine"]])));
+        assert!(eval(json!(["==", ["tag", "text_ten"], ["tag", "ten"]])));
+        // Anything else falls back to the string forms: "apple" > "10", "true" > "3"
+        assert!(eval(json!([">", ["tag", "from"], ["tag", "ten"]])));
+        assert!(eval(json!([">", ["tag", "flag"], ["tag", "min"]])));
+        // A missing tag sorts below every value
+        assert!(eval(json!(["<", ["tag", "missing"], ["tag", "min"]])));
+    }
+
+    #[test]
+    fn test_maplibre_compat_comparisons() {
+        let default = create_test_context();
+        let compat = create_test_context().with_maplibre_compat(true);
+
+        // Mixed-type equality compares string forms by default, but is false in MapLibre
+        let compiled =
+            ExpressionCompiler::compile(&json!(["==", ["tag", "capacity"], "1000"])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &default).unwrap());
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &compat).unwrap());
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["!=", ["tag", "capacity"], "1000"])).unwrap();
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &default).unwrap());
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &compat).unwrap());
+
+        // Null sorts below everything by default; ordering against null is false in MapLibre
+        let compiled = ExpressionCompiler::compile(&json!(["<", ["tag", "missing"], 5])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &default).unwrap());
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &compat).unwrap());
+
+        // Integers and floats are still comparable in both modes
+        let compiled = ExpressionCompiler::compile(&json!(["<", ["tag", "area"], 5])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &default).unwrap());
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &compat).unwrap());
+    }
+
+    #[test]
+    fn test_maplibre_compat_truthiness() {
+        let default = create_test_context();
+        let compat = create_test_context().with_maplibre_compat(true);
+
+        // Non-empty strings are truthy by default, but only `true` is truthy in MapLibre
+        let compiled = ExpressionCompiler::compile(&json!(["boolean", ["tag", "kind"]])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &default).unwrap());
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &compat).unwrap());
+
+        let compiled = ExpressionCompiler::compile(&json!(["any", ["tag", "capacity"]])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &default).unwrap());
+        assert!(!ExpressionExecutor::evaluate_bool(&compiled, &compat).unwrap());
+
+        // Missing properties are false in both modes
+        let compiled = ExpressionCompiler::compile(&json!(["!", ["tag", "missing"]])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &default).unwrap());
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &compat).unwrap());
+
+        // Boolean tags behave the same in both modes
+        let compiled = ExpressionCompiler::compile(&json!(["boolean", ["tag", "public"]])).unwrap();
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &default).unwrap());
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &compat).unwrap());
+    }
+
+    #[test]
+    fn test_value_type() {
+        let compiled = ExpressionCompiler::compile(&json!(["value-type"])).unwrap();
+        let cases = [
+            (
+                Value {
+                    string_value: Some("park".to_string()),
+                    ..Default::default()
+                },
+                "string",
+            ),
+            (
+                Value {
+                    int_value: Some(-1),
+                    ..Default::default()
+                },
+                "number",
+            ),
+            (
+                Value {
+                    uint_value: Some(1),
+                    ..Default::default()
+                },
+                "number",
+            ),
+            (
+                Value {
+                    sint_value: Some(1),
+                    ..Default::default()
+                },
+                "number",
+            ),
+            (
+                Value {
+                    float_value: Some(1.5),
+                    ..Default::default()
+                },
+                "float",
+            ),
+            (
+                Value {
+                    double_value: Some(1.5),
+                    ..Default::default()
+                },
+                "float",
+            ),
+            (
+                Value {
+                    bool_value: Some(false),
+                    ..Default::default()
+                },
+                "boolean",
+            ),
+            (Value::default(), "null"),
+        ];
+        for (value, expected) in cases {
+            let context = create_test_context().with_current_value(&value);
+            let result = ExpressionExecutor::evaluate(&compiled, &context).unwrap();
+            assert_eq!(result, ExpressionValue::String(expected.to_string()));
+        }
+
+        // Outside of tag evaluation there is no current value
+        let result = ExpressionExecutor::evaluate(&compiled, &create_test_context()).unwrap();
+        assert_eq!(result, ExpressionValue::String("null".to_string()));
+
+        let compiled =
+            ExpressionCompiler::compile(&json!(["==", ["value-type"], "string"])).unwrap();
+        let context = create_test_context().with_current_value(&Value {
+            string_value: Some("x".to_string()),
+            ..Default::default()
+        });
+        assert!(ExpressionExecutor::evaluate_bool(&compiled, &context).unwrap());
+    }
 }
@@ -1,4 +1,5 @@
 use anyhow::{Result, anyhow};
+use geo_types::Rect;
 use geozero::mvt;
 use regex::Regex;
 use serde_json::Value;
@@ -11,6 +12,7 @@ use super::data::Operator;
 pub enum CompiledExpression {
     // Comparison operations
     Equal(Box<CompiledExpression>, Box<CompiledExpression>),
+    EqualIgnoreCase(Box<CompiledExpression>, Box<CompiledExpression>), // Compares lowercased string forms
     NotEqual(Box<CompiledExpression>, Box<CompiledExpression>),
     LessThan(Box<CompiledExpression>, Box<CompiledExpression>),
     GreaterThan(Box<CompiledExpression>, Box<CompiledExpression>),
@@ -22,24 +24,60 @@ pub enum CompiledExpression {
     All(Vec<CompiledExpression>),
     None(Vec<CompiledExpression>),
     Not(Box<CompiledExpression>),
+    If(
+        Box<CompiledExpression>,
+        Box<CompiledExpression>,
+        Box<CompiledExpression>,
+    ), // Condition, then, else
 
     // Membership operations
     In(Box<CompiledExpression>, HashSet<ExpressionValue>),
+    NotIn(Box<CompiledExpression>, HashSet<ExpressionValue>),
+    InArray(Box<CompiledExpression>, Box<CompiledExpression>), // Membership in an array computed per feature
+    Has(String),                     // Whether the feature has the property
+    IsNull(Box<CompiledExpression>), // Whether the value is null (including a missing tag)
 
     // String operations
     StartsWith(Box<CompiledExpression>, String),
     EndsWith(Box<CompiledExpression>, String),
     RegexMatch(Box<CompiledExpression>, Regex),
     RegexCapture(Box<CompiledExpression>, Regex, usize),
+    RegexCaptureNamed(Box<CompiledExpression>, Regex, String), // Group name
+    SplitContains(Box<CompiledExpression>, String, String),    // Delimiter, target
+    Split(Box<CompiledExpression>, String),                    // Delimiter
+    Length(Box<CompiledExpression>), // Characters of a string or elements of an array
+    Substr(Box<CompiledExpression>, usize, usize), // Start and length, in characters
+    NormalizeWhitespace(Box<CompiledExpression>), // Collapse whitespace runs and trim
+
+    // Math operations, to an optional multiple
+    Round(Box<CompiledExpression>, Option<f64>),
+    Floor(Box<CompiledExpression>, Option<f64>),
+    Ceil(Box<CompiledExpression>, Option<f64>),
+    Add(Box<CompiledExpression>, Box<CompiledExpression>),
+    Subtract(Box<CompiledExpression>, Box<CompiledExpression>),
+    Multiply(Box<CompiledExpression>, Box<CompiledExpression>),
+    Divide(Box<CompiledExpression>, Box<CompiledExpression>),
+    Power(Box<CompiledExpression>, Box<CompiledExpression>),
 
     // Value operations
     Boolean(Box<CompiledExpression>),
     Literal(ExpressionValue),
+    ParseJson(Box<CompiledExpression>), // Parse a JSON-encoded string value
+    IsJson(Box<CompiledExpression>),    // Whether a string value is valid JSON
 
     // Context operations
     Tag(String), // Get feature property by name
     Key,         // Current tag key being processed
+    ValueType,   // Type of the current tag value
+    PointInRegion(Box<CompiledExpression>, Box<CompiledExpression>), // Longitude, latitude
+    DistanceToRegion, // Meters from the feature to the filter feature's region boundary
+    BboxIntersects(Rect<f64>), // Whether the feature's tile-local bounding box overlaps this box
     Type,        // Feature geometry type
+    VertexCount, // Number of coordinates in the feature geometry
+    TagCount,    // Number of tags on the feature
+    CommandCount, // Length of the feature's encoded MVT geometry
+    Zoom,        // Zoom level of the tile
+    Area,        // Area of the feature geometry in square meters
 }
 
 /// Runtime values that expressions can evaluate to
@@ -174,6 +212,13 @@ impl ExpressionCompiler {
                     Box::new(Self::compile(&args[1])?),
                 ))
             }
+            Operator::EqualIgnoreCase => {
+                Self::ensure_arg_count(args, 2)?;
+                Ok(CompiledExpression::EqualIgnoreCase(
+                    Box::new(Self::compile(&args[0])?),
+                    Box::new(Self::compile(&args[1])?),
+                ))
+            }
             Operator::NotEqual => {
                 Self::ensure_arg_count(args, 2)?;
                 Ok(CompiledExpression::NotEqual(
@@ -227,19 +272,42 @@ impl ExpressionCompiler {
                 Self::ensure_arg_count(args, 1)?;
                 Ok(CompiledExpression::Not(Box::new(Self::compile(&args[0])?)))
             }
+            Operator::If => {
+                Self::ensure_arg_count(args, 3)?;
+                Ok(CompiledExpression::If(
+                    Box::new(Self::compile(&args[0])?),
+                    Box::new(Self::compile(&args[1])?),
+                    Box::new(Self::compile(&args[2])?),
+                ))
+            }
 
             // Membership operations
-            Operator::In => {
-                Self::ensure_arg_count(args, 2)?;
-                let expr = Self::compile(&args[0])?;
-                let values = Self::compile(&args[1])?;
-                let values =
-                    if let CompiledExpression::Literal(ExpressionValue::Array(arr)) = values {
-                        arr.into_iter().collect()
-                    } else {
-                        return Err(anyhow!("In operator requires an array of values"));
-                    };
-                Ok(CompiledExpression::In(Box::new(expr), values))
+            Operator::In => Self::compile_membership(args),
+            Operator::NotIn => Ok(match Self::compile_membership(args)? {
+                CompiledExpression::In(expr, values) => CompiledExpression::NotIn(expr, values),
+                membership => CompiledExpression::Not(Box::new(membership)),
+            }),
+            Operator::Has => {
+                Self::ensure_arg_count(args, 1)?;
+                Ok(CompiledExpression::Has(Self::tag_name(args, "Has")?))
+            }
+            Operator::NotHas => {
+                Self::ensure_arg_count(args, 1)?;
+                Ok(CompiledExpression::Not(Box::new(CompiledExpression::Has(
+                    Self::tag_name(args, "NotHas")?,
+                ))))
+            }
+            Operator::IsNull => {
+                Self::ensure_arg_count(args, 1)?;
+                Ok(CompiledExpression::IsNull(Box::new(Self::compile(
+                    &args[0],
+                )?)))
+            }
+            Operator::IsNotNull => {
+                Self::ensure_arg_count(args, 1)?;
+                Ok(CompiledExpression::Not(Box::new(
+                    CompiledExpression::IsNull(Box::new(Self::compile(&args[0])?)),
+                )))
             }
 
             // String operations
@@ -265,6 +333,61 @@ impl ExpressionCompiler {
                     suffix.to_string(),
                 ))
             }
+            Operator::SplitContains => {
+                Self::ensure_arg_count(args, 3)?;
+                let expr = Self::compile(&args[0])?;
+                let (Some(delimiter), Some(target)) = (args[1].as_str(), args[2].as_str()) else {
+                    return Err(anyhow!(
+                        "SplitContains requires string delimiter and target arguments"
+                    ));
+                };
+                if delimiter.is_empty() {
+                    return Err(anyhow!("SplitContains delimiter must not be empty"));
+                }
+                Ok(CompiledExpression::SplitContains(
+                    Box::new(expr),
+                    delimiter.to_string(),
+                    target.to_string(),
+                ))
+            }
+            Operator::Split => {
+                Self::ensure_arg_count(args, 2)?;
+                let expr = Self::compile(&args[0])?;
+                let delimiter = args[1]
+                    .as_str()
+                    .filter(|d| !d.is_empty())
+                    .ok_or_else(|| anyhow!("Split requires a non-empty string delimiter"))?;
+                Ok(CompiledExpression::Split(
+                    Box::new(expr),
+                    delimiter.to_string(),
+                ))
+            }
+            Operator::Substr => {
+                Self::ensure_arg_count(args, 3)?;
+                let expr = Self::compile(&args[0])?;
+                let index = |arg: &Value, name: &str| {
+                    arg.as_u64()
+                        .map(|n| n as usize)
+                        .ok_or_else(|| anyhow!("Substr {} must be a non-negative integer", name))
+                };
+                Ok(CompiledExpression::Substr(
+                    Box::new(expr),
+                    index(&args[1], "start")?,
+                    index(&args[2], "length")?,
+                ))
+            }
+            Operator::NormalizeWhitespace => {
+                Self::ensure_arg_count(args, 1)?;
+                Ok(CompiledExpression::NormalizeWhitespace(Box::new(
+                    Self::compile(&args[0])?,
+                )))
+            }
+            Operator::Length => {
+                Self::ensure_arg_count(args, 1)?;
+                Ok(CompiledExpression::Length(Box::new(Self::compile(
+                    &args[0],
+                )?)))
+            }
             Operator::RegexMatch => {
                 Self::ensure_arg_count(args, 2)?;
                 let expr = Self::compile(&args[0])?;
@@ -293,8 +416,58 @@ impl ExpressionCompiler {
                     group,
                 ))
             }
+            Operator::RegexCaptureNamed => {
+                Self::ensure_arg_count(args, 3)?;
+                let expr = Self::compile(&args[0])?;
+                let pattern = args[1]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("RegexCaptureNamed requires string pattern"))?;
+                let name = args[2]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("RegexCaptureNamed requires string group name"))?;
+                let regex = Regex::new(pattern)
+                    .map_err(|e| anyhow!("Invalid regex pattern '{}': {}", pattern, e))?;
+                if !regex.capture_names().any(|n| n == Some(name)) {
+                    return Err(anyhow!(
+                        "Regex pattern '{}' has no group named '{}'",
+                        pattern,
+                        name
+                    ));
+                }
+                Ok(CompiledExpression::RegexCaptureNamed(
+                    Box::new(expr),
+                    regex,
+                    name.to_string(),
+                ))
+            }
 
             // Value operations
+            Operator::Round => {
+                let (expr, multiple) = Self::compile_rounding(args)?;
+                Ok(CompiledExpression::Round(Box::new(expr), multiple))
+            }
+            Operator::Floor => {
+                let (expr, multiple) = Self::compile_rounding(args)?;
+                Ok(CompiledExpression::Floor(Box::new(expr), multiple))
+            }
+            Operator::Ceil => {
+                let (expr, multiple) = Self::compile_rounding(args)?;
+                Ok(CompiledExpression::Ceil(Box::new(expr), multiple))
+            }
+            Operator::Add => Self::compile_sum(args, CompiledExpression::Add),
+            Operator::Multiply => Self::compile_sum(args, CompiledExpression::Multiply),
+            Operator::Subtract => {
+                let (left, right) = Self::compile_pair(args)?;
+                Ok(CompiledExpression::Subtract(left, right))
+            }
+            Operator::Divide => {
+                let (left, right) = Self::compile_pair(args)?;
+                Ok(CompiledExpression::Divide(left, right))
+            }
+            Operator::Power => {
+                let (left, right) = Self::compile_pair(args)?;
+                Ok(CompiledExpression::Power(left, right))
+            }
             Operator::Boolean => {
                 Self::ensure_arg_count(args, 1)?;
                 Ok(CompiledExpression::Boolean(Box::new(Self::compile(
@@ -307,24 +480,152 @@ impl ExpressionCompiler {
                     ExpressionValue::from_json_value(&args[0]),
                 ))
             }
+            Operator::ParseJson => {
+                Self::ensure_arg_count(args, 1)?;
+                Ok(CompiledExpression::ParseJson(Box::new(Self::compile(
+                    &args[0],
+                )?)))
+            }
+            Operator::IsJson => {
+                Self::ensure_arg_count(args, 1)?;
+                Ok(CompiledExpression::IsJson(Box::new(Self::compile(
+                    &args[0],
+                )?)))
+            }
 
             // Context operations
             Operator::Tag => {
                 Self::ensure_arg_count(args, 1)?;
-                let tag_name = args[0]
-                    .as_str()
-                    .ok_or_else(|| anyhow!("Tag operator requires string argument"))?;
-                Ok(CompiledExpression::Tag(tag_name.to_string()))
+                Ok(CompiledExpression::Tag(Self::tag_name(args, "Tag")?))
             }
             Operator::Key => {
                 Self::ensure_arg_count(args, 0)?;
                 Ok(CompiledExpression::Key)
             }
+            Operator::PointInRegion => {
+                Self::ensure_arg_count(args, 2)?;
+                Ok(CompiledExpression::PointInRegion(
+                    Box::new(Self::compile(&args[0])?),
+                    Box::new(Self::compile(&args[1])?),
+                ))
+            }
+            Operator::DistanceToRegion => {
+                Self::ensure_arg_count(args, 0)?;
+                Ok(CompiledExpression::DistanceToRegion)
+            }
+            Operator::BboxIntersects => {
+                Self::ensure_arg_count(args, 4)?;
+                let bounds = args
+                    .iter()
+                    .map(Value::as_f64)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| anyhow!("BboxIntersects operator requires number arguments"))?;
+                let [min_x, min_y, max_x, max_y] = bounds[..] else {
+                    unreachable!("argument count was checked")
+                };
+                if min_x > max_x || min_y > max_y {
+                    return Err(anyhow!(
+                        "BboxIntersects box must be minx, miny, maxx, maxy with min <= max"
+                    ));
+                }
+                Ok(CompiledExpression::BboxIntersects(Rect::new(
+                    (min_x, min_y),
+                    (max_x, max_y),
+                )))
+            }
+            Operator::ValueType => {
+                Self::ensure_arg_count(args, 0)?;
+                Ok(CompiledExpression::ValueType)
+            }
             Operator::Type => {
                 Self::ensure_arg_count(args, 0)?;
                 Ok(CompiledExpression::Type)
             }
+            Operator::VertexCount => {
+                Self::ensure_arg_count(args, 0)?;
+                Ok(CompiledExpression::VertexCount)
+            }
+            Operator::TagCount => {
+                Self::ensure_arg_count(args, 0)?;
+                Ok(CompiledExpression::TagCount)
+            }
+            Operator::CommandCount => {
+                Self::ensure_arg_count(args, 0)?;
+                Ok(CompiledExpression::CommandCount)
+            }
+            Operator::Zoom => {
+                Self::ensure_arg_count(args, 0)?;
+                Ok(CompiledExpression::Zoom)
+            }
+            Operator::Area => {
+                Self::ensure_arg_count(args, 0)?;
+                Ok(CompiledExpression::Area)
+            }
+        }
+    }
+
+    /// Compile the `[expr, values]` arguments of `in`. A literal array becomes a set up
+    /// front; any other expression (e.g. `parse-json`) is evaluated per feature.
+    fn compile_membership(args: &[Value]) -> Result<CompiledExpression> {
+        Self::ensure_arg_count(args, 2)?;
+        let expr = Box::new(Self::compile(&args[0])?);
+        match Self::compile(&args[1])? {
+            CompiledExpression::Literal(ExpressionValue::Array(arr)) => {
+                Ok(CompiledExpression::In(expr, arr.into_iter().collect()))
+            }
+            CompiledExpression::Literal(_) => {
+                Err(anyhow!("In operator requires an array of values"))
+            }
+            values => Ok(CompiledExpression::InArray(expr, Box::new(values))),
+        }
+    }
+
+    /// Compile the `[expr]` or `[expr, multiple]` arguments shared by rounding operators
+    fn compile_rounding(args: &[Value]) -> Result<(CompiledExpression, Option<f64>)> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(anyhow!("Expected 1 or 2 arguments, got {}", args.len()));
+        }
+        let expr = Self::compile(&args[0])?;
+        let multiple = match args.get(1) {
+            None => None,
+            Some(multiple) => match multiple.as_f64() {
+                Some(m) if m > 0.0 => Some(m),
+                _ => return Err(anyhow!("Rounding multiple must be a positive number")),
+            },
+        };
+        Ok((expr, multiple))
+    }
+
+    /// Compile the two operands of a binary operator
+    fn compile_pair(args: &[Value]) -> Result<(Box<CompiledExpression>, Box<CompiledExpression>)> {
+        Self::ensure_arg_count(args, 2)?;
+        Ok((
+            Box::new(Self::compile(&args[0])?),
+            Box::new(Self::compile(&args[1])?),
+        ))
+    }
+
+    /// Compile `+` or `*` over two or more operands, folded from the left
+    fn compile_sum(
+        args: &[Value],
+        operator: fn(Box<CompiledExpression>, Box<CompiledExpression>) -> CompiledExpression,
+    ) -> Result<CompiledExpression> {
+        if args.len() < 2 {
+            return Err(anyhow!("Expected at least 2 arguments, got {}", args.len()));
+        }
+        let mut expr = Self::compile(&args[0])?;
+        for arg in &args[1..] {
+            expr = operator(Box::new(expr), Box::new(Self::compile(arg)?));
         }
+        Ok(expr)
+    }
+
+    /// Read the tag name argument of a property lookup operator
+    fn tag_name(args: &[Value], operator: &str) -> Result<String> {
+        args[0]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("{} operator requires string argument", operator))
     }
 
     fn ensure_arg_count(args: &[Value], expected: usize) -> Result<()> {
@@ -402,6 +703,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_compile_synonyms() {
+        let compiled = ExpressionCompiler::compile(&json!(["get", "kind"])).unwrap();
+        assert!(matches!(compiled, CompiledExpression::Tag(ref name) if name == "kind"));
+
+        let compiled = ExpressionCompiler::compile(&json!(["geometry-type"])).unwrap();
+        assert!(matches!(compiled, CompiledExpression::Type));
+
+        let values = json!(["literal", ["park", "school"]]);
+        let canonical =
+            ExpressionCompiler::compile(&json!(["not-in", ["tag", "kind"], values])).unwrap();
+        let synonym =
+            ExpressionCompiler::compile(&json!(["!in", ["get", "kind"], values])).unwrap();
+        match (canonical, synonym) {
+            (
+                CompiledExpression::NotIn(a_expr, a_values),
+                CompiledExpression::NotIn(b_expr, b_values),
+            ) => {
+                assert!(matches!(*a_expr, CompiledExpression::Tag(ref name) if name == "kind"));
+                assert!(matches!(*b_expr, CompiledExpression::Tag(ref name) if name == "kind"));
+                assert_eq!(a_values, b_values);
+            }
+            _ => panic!("Expected NotIn expressions"),
+        }
+
+        let compiled = ExpressionCompiler::compile(&json!(["has", "name"])).unwrap();
+        assert!(matches!(compiled, CompiledExpression::Has(ref name) if name == "name"));
+
+        let compiled = ExpressionCompiler::compile(&json!(["!has", "name"])).unwrap();
+        match compiled {
+            CompiledExpression::Not(inner) => {
+                assert!(matches!(*inner, CompiledExpression::Has(ref name) if name == "name"))
+            }
+            _ => panic!("Expected Not(Has) expression"),
+        }
+    }
+
     #[test]
     fn test_compile_regex_match() {
         let expr = json!(["regex-match", ["key"], "^name:.*"]);
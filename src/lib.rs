@@ -1,28 +1,172 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::anyhow;
+use clap::{ArgGroup, Parser, ValueEnum};
+use filtering::data::clipped_geometry_operators;
+use filtering::expression_compiler::ExpressionCompiler;
+use indicatif::{ProgressBar, ProgressStyle};
+use output::{DirectoryWriter, TempFile};
 use pmtiles::AsyncPmTilesReader;
-use std::{fs::File, path::PathBuf};
+use regex::Regex;
+use serde_json::json;
+use std::{
+    fs::File,
+    io::Write as _,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tokio::fs;
 
+mod error;
+mod events;
 mod filtering;
 mod metadata;
+mod output;
 mod processing;
+mod report;
 mod transform;
 
+pub use error::WranglerError;
+pub use filtering::data::{CompileError, CompiledFilterCollection, FilterCollection};
+pub use processing::{ProgressCallback, ProgressEvent, transformed_tiles};
+pub use transform::{
+    AddTag, ClampValues, KeepTopPercent, KeyNormalization, TransformOptions, TransformStats,
+    project_to_tile, tile_bounds, tile_y_to_lat, transform_tile, unproject_from_tile,
+};
+
 #[derive(Parser)]
 #[command(author, version, about)]
+#[command(group(ArgGroup::new("filter_sources").args(["filter", "filter_dir"]).multiple(true)))]
 pub struct Args {
     /// Input PMTiles file
     pub input: PathBuf,
 
-    /// Output PMTiles file (will be overwritten if exists)
+    /// Output PMTiles file (will be overwritten if exists), or a directory to write
+    /// `z/x/y.pbf` tiles and a `metadata.json` into when it ends in `/` or already is one
+    #[arg(
+        required_unless_present = "list_tiles",
+        default_value = "-",
+        hide_default_value = true
+    )]
     pub output: PathBuf,
 
+    /// Write into an output directory that isn't empty. Tiles there that this run
+    /// doesn't write are left in place.
+    #[arg(long)]
+    pub overwrite: bool,
+
+    /// Print every z/x/y in the input, sorted, one per line, and exit without writing
+    /// any output
+    #[arg(long)]
+    pub list_tiles: bool,
+
+    /// With --list-tiles, print only the number of tiles
+    #[arg(long, requires = "list_tiles")]
+    pub count_only: bool,
+
+    /// Additional input PMTiles file to merge into the output (can be repeated).
+    /// Tiles present in several inputs are combined layer by layer.
+    #[arg(long = "merge", value_name = "INPUT")]
+    pub merge: Vec<PathBuf>,
+
     /// Optional? GeoJSON file to filter features. Honestly, why are you using this tool if you don't want to filter?
     /// See FILTERING.md for details on the syntax.
     #[arg(short, long)]
     pub filter: Option<PathBuf>,
 
+    /// Directory of GeoJSON filter files (`*.geojson`), merged with each other and
+    /// with --filter
+    #[arg(long, value_name = "DIR")]
+    pub filter_dir: Option<PathBuf>,
+
+    /// Apply the `*` rules of each --filter-dir file only to the layer named after the
+    /// file, e.g. `roads.geojson` to `roads`
+    #[arg(long, requires = "filter_dir")]
+    pub scope_by_filename: bool,
+
+    /// Only process this layer, e.g. to iterate on its filter rules. Every other layer is
+    /// copied to the output untouched, ignoring filters and transform options.
+    #[arg(long, value_name = "LAYER")]
+    pub layer_filter: Option<String>,
+
+    /// Check the --filter file and --filter-dir files, print every problem found in
+    /// them, and exit without reading the input or writing the output
+    #[arg(long, requires = "filter_sources")]
+    pub validate_filter: bool,
+
+    /// Before processing, decode a sample of tiles (100 unless given) and stop if more
+    /// than 10% of them are not valid MVT
+    #[arg(long, num_args = 0..=1, default_missing_value = "100", value_name = "SAMPLE")]
+    pub validate_tiles: Option<usize>,
+
+    /// Copy tiles that no filter or option would change as they are stored, without
+    /// decoding and re-encoding them. Needs `--drop-empty-layers false`.
+    #[arg(long)]
+    pub passthrough_untouched: bool,
+
+    /// Write the stored bytes of tiles that come out of the transform unchanged, instead
+    /// of compressing them again. Requires the output compression to match the input.
+    #[arg(long)]
+    pub no_recompress: bool,
+
+    /// DANGEROUS: write every tile at y = 2^z - 1 - y, for inputs produced with the
+    /// wrong (TMS vs XYZ) row convention. On a correct input this mirrors the map
+    /// north to south.
+    #[arg(long)]
+    pub flip_y: bool,
+
+    /// Set `vector_layers[].fields` from the attribute types found in the output tiles:
+    /// Number, Boolean or String, with String for keys whose values have mixed types
+    #[arg(long)]
+    pub infer_fields: bool,
+
+    /// Store an xxHash64 of the output tiles under `content_hash` in the metadata.
+    /// The output is written twice to do this.
+    #[arg(long)]
+    pub content_hash: bool,
+
+    /// Set the bounds and center, in the header and metadata, from the extent of the
+    /// written features instead of the input's tile-based bounds. Every output tile is
+    /// decoded once more to do this.
+    #[arg(long)]
+    pub precise_bounds: bool,
+
+    /// Also write a TileJSON file for the output archive to `<output>.json`, with the
+    /// final zoom range, bounds, center and metadata
+    #[arg(long)]
+    pub emit_tilejson: bool,
+
+    /// URL template of the tiles in the --emit-tilejson file
+    /// [default: http://localhost:8080/<output name>/{z}/{x}/{y}.mvt]
+    #[arg(long, value_name = "URL", requires = "emit_tilejson")]
+    pub tiles_url: Option<String>,
+
+    /// Only process the tiles listed in this file, one z/x/y per line. Listed tiles
+    /// missing from the input are skipped with a warning.
+    #[arg(long, value_name = "PATH")]
+    pub tiles_file: Option<PathBuf>,
+
+    /// Extend the archive to this zoom level by subdividing the tiles at the input's
+    /// deepest zoom level, clipping their features into each child tile
+    #[arg(long, value_name = "ZOOM", value_parser = clap::value_parser!(u8).range(..=30))]
+    pub max_zoom_overzoom: Option<u8>,
+
+    /// Write a CSV listing each written tile (z/x/y) with an xxHash64 of its stored
+    /// bytes, for comparing two runs tile by tile
+    #[arg(long, value_name = "PATH")]
+    pub checksum_manifest: Option<PathBuf>,
+
+    /// Fail if no features are left in any output tile, e.g. because a filter removed
+    /// everything
+    #[arg(long)]
+    pub fail_on_empty_output: bool,
+
+    /// Write the features removed by filters to this GeoJSON file, for auditing
+    #[arg(long)]
+    pub removed_output: Option<PathBuf>,
+
+    /// Maximum number of features written to --removed-output
+    #[arg(long, default_value_t = 100_000)]
+    pub removed_limit: usize,
+
     /// Name of the tileset (for PMTiles metadata)
     #[arg(long, short = 'n')]
     pub name: Option<String>,
@@ -34,67 +178,944 @@ pub struct Args {
     /// Attribution information for the tileset (for PMTiles metadata)
     #[arg(long, short = 'A')]
     pub attribution: Option<String>,
+
+    /// Strip the attribution's HTML down to links and simple formatting (`<a href>`,
+    /// `<b>`, `<i>`, ...), for attribution from untrusted sources
+    #[arg(long)]
+    pub sanitize_attribution: bool,
+
+    /// Let `${VAR}` in --name, --description and --attribution expand to nothing when
+    /// `VAR` is not set, instead of failing
+    #[arg(long)]
+    pub allow_undefined_env: bool,
+
+    /// JSON file with transform options (see README.md). Flags given on the command line
+    /// take precedence over the file.
+    #[arg(long)]
+    pub transform_config: Option<PathBuf>,
+
+    /// Rescale every layer's geometry to this extent on output (e.g. 4096 to shrink
+    /// 8192-extent layers), so that all layers share one extent
+    #[arg(long, alias = "normalize-extent", value_parser = clap::value_parser!(u32).range(1..))]
+    pub target_extent: Option<u32>,
+
+    /// Rescale one layer's geometry to its own extent, as `layer=extent` (e.g.
+    /// `roads=8192`). Takes precedence over --target-extent. Can be repeated.
+    #[arg(long, value_parser = parse_layer_extent)]
+    pub layer_extent_override: Vec<(String, u32)>,
+
+    /// Extent assumed for layers that don't declare one (default 4096). Must be a power
+    /// of two from 256 to 65536.
+    #[arg(long, value_parser = parse_default_extent)]
+    pub default_extent: Option<u32>,
+
+    /// Remove everything inside this rectangle (min_lon,min_lat,max_lon,max_lat). Features
+    /// crossing its edge are clipped and tiles entirely inside it are dropped.
+    #[arg(long, value_parser = parse_bbox, allow_hyphen_values = true)]
+    pub exclude_bbox: Option<[f64; 4]>,
+
+    /// Only process tiles overlapping this rectangle (min_lon,min_lat,max_lon,max_lat).
+    /// Other tiles are not read or written; features in the tiles kept are not clipped.
+    #[arg(long, value_parser = parse_bbox, allow_hyphen_values = true)]
+    pub bbox: Option<[f64; 4]>,
+
+    /// Comma-separated keys whose "yes"/"true"/"1" and "no"/"false"/"0" string values are
+    /// rewritten to booleans (before filters are evaluated)
+    #[arg(long, value_delimiter = ',')]
+    pub boolify: Vec<String>,
+
+    /// Pull numeric values of a tag outside `min..=max` to the nearest bound, as
+    /// `layer:key:min:max` (`*` for all layers). Filters see the clamped values. Can be
+    /// repeated.
+    #[arg(long, value_parser = ClampValues::parse, allow_hyphen_values = true)]
+    pub clamp_values: Vec<ClampValues>,
+
+    /// Rewrite tag keys in every layer before filtering, e.g. `lowercase,trim`. When two
+    /// keys of a feature become equal, the last value wins.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub normalize_keys: Vec<KeyNormalization>,
+
+    /// Add a constant tag to every feature of a layer, as `layer:key=value` (`*` for all
+    /// layers). The value is parsed as JSON when possible. Can be repeated.
+    #[arg(long, value_parser = AddTag::parse)]
+    pub add_tag: Vec<AddTag>,
+
+    /// Keep only the top percent of a layer's features in each tile, ranked by a numeric
+    /// tag, as `layer:key:percent` (`*` for all layers). Ties at the cutoff are kept and
+    /// features without the tag rank lowest. Can be repeated.
+    #[arg(long, value_parser = KeepTopPercent::parse)]
+    pub keep_top_percent: Vec<KeepTopPercent>,
+
+    /// Remove consecutive duplicate vertices from geometry, dropping features that
+    /// degenerate (e.g. a line collapsing to a point)
+    #[arg(long)]
+    pub dedupe_vertices: bool,
+
+    /// Round geometry coordinates to multiples of this many tile units (e.g. 4) for
+    /// smaller tiles, dropping features that degenerate
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub snap_grid: Option<u32>,
+
+    /// Remove tags whose value is an empty string, in every layer
+    #[arg(long)]
+    pub strip_empty_values: bool,
+
+    /// Write the layers of each tile in alphabetical order of name
+    #[arg(long)]
+    pub sort_layers: bool,
+
+    /// Make the output byte-for-byte reproducible: implies --sort-layers and writes
+    /// every tile in tile order, so it can't be combined with --fast or --reorder-window
+    #[arg(long, conflicts_with_all = ["fast", "reorder_window"])]
+    pub stable_output: bool,
+
+    /// Decode every re-encoded tile and fail if its features and tags don't match what
+    /// the transform intended. A self-check that slows processing down.
+    #[arg(long)]
+    pub verify_encoding: bool,
+
+    /// Remove features matching this filter expression (JSON) from every layer,
+    /// without spatial scoping, e.g. '["==", ["tag", "kind"], "test"]'
+    #[arg(long = "where", value_name = "EXPRESSION")]
+    pub where_expression: Option<String>,
+
+    /// Drop every layer whose name matches this regex (e.g. '^debug_'), in both the tiles
+    /// and the `vector_layers` metadata. The --layer-filter layer is always kept.
+    #[arg(long, value_name = "REGEX")]
+    pub exclude_layers_regex: Option<String>,
+
+    /// Keep only tags whose key matches this regex, in every layer
+    #[arg(long, value_name = "REGEX")]
+    pub keep_tags_matching: Option<String>,
+
+    /// Remove tags whose key matches this regex (e.g. '^addr:'), in every layer
+    #[arg(long, value_name = "REGEX")]
+    pub drop_tags_matching: Option<String>,
+
+    /// Keep a random fraction (0 to 1) of features. The selection is deterministic for a given --seed.
+    #[arg(long, value_parser = parse_fraction)]
+    pub sample_fraction: Option<f64>,
+
+    /// Seed used by --sample-fraction [default: 0]
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Evaluate filter expressions with MapLibre comparison and truthiness semantics.
+    /// See FILTERING.md for the differences.
+    #[arg(long)]
+    pub maplibre_compat: bool,
+
+    /// Remove layers left without features after filtering (pass `false` to keep them) [default: true]
+    #[arg(long, num_args = 0..=1, default_missing_value = "true", action = clap::ArgAction::Set)]
+    pub drop_empty_layers: Option<bool>,
+
+    /// Scan the input first and keep only layers that have features somewhere, in both
+    /// the tiles and the `vector_layers` metadata
+    #[arg(long)]
+    pub only_layers_with_features: bool,
+
+    /// Compression for output tiles (defaults to the input archive's tile compression)
+    #[arg(long, value_enum)]
+    pub output_compression: Option<Codec>,
+
+    /// Fail on archive problems that are otherwise only warned about
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Warn about output tiles larger than this many bytes (an error with --strict)
+    #[arg(long)]
+    pub max_tile_bytes: Option<usize>,
+
+    /// Write newline-delimited JSON events for each tile to this file (`-` for stderr)
+    #[arg(long)]
+    pub events: Option<PathBuf>,
+
+    /// Hold at most this many finished tiles back to write them in order. Smaller windows
+    /// use less memory but may produce an unclustered archive. Unbounded by default.
+    #[arg(long)]
+    pub reorder_window: Option<usize>,
+
+    /// Write tiles as soon as they are finished instead of in tile order. Uses the least
+    /// memory and latency, but the output archive is unclustered.
+    #[arg(long, conflicts_with = "reorder_window")]
+    pub fast: bool,
+
+    /// Process one zoom level at a time, writing it out completely before starting on
+    /// the next. Bounds memory on huge archives at the cost of some parallelism at the
+    /// end of each level.
+    #[arg(long)]
+    pub batch_by_zoom: bool,
+
+    /// Queue tiles in the order the input lists them instead of sorting the coordinates
+    /// first, for a quicker first write. Pair with --fast to write tiles as they finish;
+    /// the archive may then be stored out of order and lose PMTiles clustering.
+    #[arg(long, conflicts_with_all = ["merge", "max_zoom_overzoom", "batch_by_zoom"])]
+    pub unsorted: bool,
+
+    /// Decompress input tiles with this codec, ignoring the compression declared in the
+    /// input header. Only affects reading; use --output-compression for the output.
+    #[arg(long, value_enum)]
+    pub input_compression: Option<Codec>,
+
+    /// Show a progress bar for each stage (tiles read, transformed and written) instead
+    /// of one overall bar, to see which stage is the bottleneck
+    #[arg(long)]
+    pub detailed_progress: bool,
+
+    /// Number of tiles read and transformed at once (default: the number of CPUs)
+    #[arg(long, value_parser = parse_positive)]
+    pub concurrency: Option<usize>,
+
+    /// Before processing, time the transform of the first N tiles (default 256) at
+    /// several worker counts and process the archive with the fastest
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "256",
+        conflicts_with = "concurrency",
+        value_parser = parse_positive
+    )]
+    pub autotune: Option<usize>,
+
+    /// Split the tiles into N contiguous runs, each transformed and written to its own
+    /// temporary archive in parallel, then merge them into the output in order
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = parse_positive,
+        conflicts_with_all = [
+            "events",
+            "removed_output",
+            "checksum_manifest",
+            "fail_on_empty_output",
+            "autotune",
+            "max_zoom_overzoom",
+            "flip_y",
+            "unsorted",
+            "reorder_window",
+        ]
+    )]
+    pub shards: Option<usize>,
+
+    /// Compression for the output archive's directories and metadata
+    #[arg(long, value_enum, default_value_t = Codec::Gzip)]
+    pub directory_compression: Codec,
+}
+
+/// Tile compression codecs supported on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Codec {
+    None,
+    Gzip,
+}
+
+impl From<Codec> for pmtiles::Compression {
+    fn from(codec: Codec) -> Self {
+        match codec {
+            Codec::None => pmtiles::Compression::None,
+            Codec::Gzip => pmtiles::Compression::Gzip,
+        }
+    }
+}
+
+impl Default for Args {
+    /// The same defaults as the command line, with empty input/output paths
+    fn default() -> Self {
+        let mut args = Args::parse_from(["mvt-wrangler", "-", "-"]);
+        args.input = PathBuf::new();
+        args.output = PathBuf::new();
+        args
+    }
 }
 
-pub async fn run(args: Args) -> Result<()> {
-    // Remove any existing output
-    if args.output.exists() {
-        fs::remove_file(&args.output).await?;
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(format!("{} is not between 0 and 1", value));
     }
+    Ok(value)
+}
+
+fn parse_positive(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>().map_err(|e| format!("{}", e))? {
+        0 => Err("must be at least 1".to_string()),
+        value => Ok(value),
+    }
+}
 
-    let pmtiles_path = args.input;
-    if !pmtiles_path.exists() {
-        panic!("Input file does not exist: {}", pmtiles_path.display());
+fn parse_default_extent(s: &str) -> Result<u32, String> {
+    let value: u32 = s.parse().map_err(|e| format!("{}", e))?;
+    if !value.is_power_of_two() || !(256..=65536).contains(&value) {
+        return Err(format!("{} is not a power of two from 256 to 65536", value));
     }
+    Ok(value)
+}
+
+fn parse_layer_extent(s: &str) -> Result<(String, u32), String> {
+    let (layer, extent) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected layer=extent, got {}", s))?;
+    if layer.is_empty() {
+        return Err(format!("layer must not be empty in {}", s));
+    }
+    let extent: u32 = extent.parse().map_err(|e| format!("{}: {}", extent, e))?;
+    if extent == 0 {
+        return Err("extent must be at least 1".to_string());
+    }
+    Ok((layer.to_string(), extent))
+}
+
+fn parse_bbox(s: &str) -> Result<[f64; 4], String> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<f64>().map_err(|e| format!("{}: {}", v, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let [min_lon, min_lat, max_lon, max_lat]: [f64; 4] = values
+        .try_into()
+        .map_err(|_| "expected min_lon,min_lat,max_lon,max_lat".to_string())?;
+    if min_lon >= max_lon || min_lat >= max_lat {
+        return Err("min values must be less than max values".to_string());
+    }
+    if min_lon < -180.0 || max_lon > 180.0 || min_lat < -90.0 || max_lat > 90.0 {
+        return Err("coordinates must be within -180,-90,180,90".to_string());
+    }
+    Ok([min_lon, min_lat, max_lon, max_lat])
+}
+
+/// Swap reversed min/max pairs of `[min_lon, min_lat, max_lon, max_lat]` and clamp them
+/// to -180,-90,180,90. `None` if the bounds are already sane.
+fn corrected_bounds(bounds: [f32; 4]) -> Option<[f32; 4]> {
+    let [min_lon, min_lat, max_lon, max_lat] = bounds;
+    let (min_lon, max_lon) = (min_lon.min(max_lon), min_lon.max(max_lon));
+    let (min_lat, max_lat) = (min_lat.min(max_lat), min_lat.max(max_lat));
+    let corrected = [
+        min_lon.clamp(-180.0, 180.0),
+        min_lat.clamp(-90.0, 90.0),
+        max_lon.clamp(-180.0, 180.0),
+        max_lat.clamp(-90.0, 90.0),
+    ];
+    (corrected != bounds).then_some(corrected)
+}
+
+/// Load `--transform-config` if given and apply the command line flags on top of it
+async fn transform_options(args: &Args) -> Result<TransformOptions, WranglerError> {
+    let mut options = match &args.transform_config {
+        Some(path) => {
+            let config = fs::read_to_string(path).await?;
+            serde_json::from_str::<TransformOptions>(&config)
+                .map_err(|e| WranglerError::Config(e.into()))?
+        }
+        None => TransformOptions::default(),
+    };
 
-    // Validate filter file if provided
-    let mut fc = None;
+    if args.target_extent.is_some() {
+        options.target_extent = args.target_extent;
+    }
+    options
+        .layer_extent_overrides
+        .extend(args.layer_extent_override.iter().cloned());
+    if let Some(default_extent) = args.default_extent {
+        options.default_extent = default_extent;
+    }
+    if args.sample_fraction.is_some() {
+        options.sample_fraction = args.sample_fraction;
+    }
+    if let Some(seed) = args.seed {
+        options.seed = seed;
+    }
+    if args.maplibre_compat {
+        options.maplibre_compat = true;
+    }
+    if let Some(drop_empty_layers) = args.drop_empty_layers {
+        options.drop_empty_layers = drop_empty_layers;
+    }
+    if args.exclude_bbox.is_some() {
+        options.exclude_bbox = args.exclude_bbox;
+    }
+    if !args.normalize_keys.is_empty() {
+        options.normalize_keys = args.normalize_keys.clone();
+    }
+    if !args.boolify.is_empty() {
+        options.boolify_keys = args.boolify.clone();
+    }
+    if !args.clamp_values.is_empty() {
+        options.clamp_values = args.clamp_values.clone();
+    }
+    if !args.add_tag.is_empty() {
+        options.add_tags = args.add_tag.clone();
+    }
+    if !args.keep_top_percent.is_empty() {
+        options.keep_top_percent = args.keep_top_percent.clone();
+    }
+    if args.dedupe_vertices {
+        options.dedupe_vertices = true;
+    }
+    if args.snap_grid.is_some() {
+        options.snap_grid = args.snap_grid;
+    }
+    if args.strip_empty_values {
+        options.strip_empty_values = true;
+    }
+    if args.sort_layers || args.stable_output {
+        options.sort_layers = true;
+    }
+    options.verify_encoding = args.verify_encoding;
+    options.infer_fields = args.infer_fields;
+    if let Some(layer) = &args.layer_filter {
+        options.filter_layer = Some(layer.clone());
+    }
+    if let Some(expression) = &args.where_expression {
+        let expression =
+            serde_json::from_str(expression).map_err(|e| WranglerError::FilterParse(e.into()))?;
+        let operators = clipped_geometry_operators(&expression);
+        if !operators.is_empty() {
+            eprintln!(
+                "⚠️ --where: {} see(s) the geometry clipped to each tile; \
+                 a feature spanning several tiles is measured per tile",
+                operators.join(", ")
+            );
+        }
+        options.remove_where =
+            Some(ExpressionCompiler::compile(&expression).map_err(WranglerError::FilterParse)?);
+    }
+    if let Some(pattern) = &args.exclude_layers_regex {
+        options.exclude_layers_regex =
+            Some(Regex::new(pattern).map_err(|e| WranglerError::Config(e.into()))?);
+    }
+    if let Some(pattern) = &args.keep_tags_matching {
+        options.keep_tags_matching =
+            Some(Regex::new(pattern).map_err(|e| WranglerError::Config(e.into()))?);
+    }
+    if let Some(pattern) = &args.drop_tags_matching {
+        options.drop_tags_matching =
+            Some(Regex::new(pattern).map_err(|e| WranglerError::Config(e.into()))?);
+    }
+
+    // The command line validates these while parsing; the config file doesn't
+    if let Some(fraction) = options.sample_fraction {
+        parse_fraction(&fraction.to_string()).map_err(|e| WranglerError::Config(anyhow!(e)))?;
+    }
+    if options.snap_grid == Some(0) {
+        return Err(WranglerError::Config(anyhow!(
+            "snap_grid must be at least 1"
+        )));
+    }
+    if options.target_extent == Some(0) {
+        return Err(WranglerError::Config(anyhow!(
+            "target_extent must be at least 1"
+        )));
+    }
+    if let Some(layer) = options
+        .layer_extent_overrides
+        .iter()
+        .find_map(|(layer, extent)| (*extent == 0).then_some(layer))
+    {
+        return Err(WranglerError::Config(anyhow!(
+            "layer_extent_overrides for {} must be at least 1",
+            layer
+        )));
+    }
+    parse_default_extent(&options.default_extent.to_string())
+        .map_err(|e| WranglerError::Config(anyhow!(e)))?;
+    if let Some(bbox) = options.exclude_bbox {
+        let bbox = bbox.map(|v| v.to_string()).join(",");
+        parse_bbox(&bbox).map_err(|e| WranglerError::Config(anyhow!(e)))?;
+    }
+    Ok(options)
+}
+
+/// Whether `output` is a tile directory rather than a PMTiles archive: an existing
+/// directory or a path ending in a separator
+fn is_directory_output(output: &Path) -> bool {
+    output.is_dir()
+        || output
+            .as_os_str()
+            .to_string_lossy()
+            .ends_with(std::path::is_separator)
+}
+
+/// Read and compile a filter file, reporting every compile error found in it. With a
+/// `scope`, the file's `*` rules apply to that layer only.
+async fn load_filter(
+    filter_path: &Path,
+    scope: Option<&str>,
+) -> Result<CompiledFilterCollection, WranglerError> {
+    if !filter_path.exists() {
+        return Err(WranglerError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Filter file does not exist: {}", filter_path.display()),
+        )));
+    }
+    let filter_str = fs::read_to_string(filter_path).await?;
+    let mut filter_json: FilterCollection =
+        serde_json::from_str(&filter_str).map_err(|e| WranglerError::FilterParse(e.into()))?;
+    if let Some(layer) = scope {
+        filter_json
+            .scope_to_layer(layer)
+            .map_err(|e| WranglerError::FilterParse(anyhow!("{}: {e}", filter_path.display())))?;
+    }
+    for warning in filter_json.clipped_geometry_warnings() {
+        eprintln!("⚠️ {}: {warning}", filter_path.display());
+    }
+    filter_json.compile_all().map_err(|errors| {
+        for error in &errors {
+            eprintln!("{}: {error}", filter_path.display());
+        }
+        WranglerError::FilterParse(anyhow!(
+            "{} problem(s) found in {}",
+            errors.len(),
+            filter_path.display()
+        ))
+    })
+}
+
+/// The `*.geojson` files of a filter directory, sorted by name
+fn filter_dir_files(dir: &Path) -> Result<Vec<PathBuf>, WranglerError> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("geojson") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Load the --filter file and every --filter-dir file into one collection, or `None`
+/// without either. Each file is checked on its own, so problems point at their file.
+async fn load_filters(args: &Args) -> Result<Option<CompiledFilterCollection>, WranglerError> {
+    let mut collections = Vec::new();
     if let Some(filter_path) = &args.filter {
-        if !filter_path.exists() {
-            panic!("Filter file does not exist: {}", filter_path.display());
+        collections.push(load_filter(filter_path, None).await?);
+    }
+    if let Some(dir) = &args.filter_dir {
+        for path in filter_dir_files(dir)? {
+            let stem = path.file_stem().and_then(|s| s.to_str());
+            let scope = if args.scope_by_filename { stem } else { None };
+            collections.push(load_filter(&path, scope).await?);
+        }
+    }
+    if collections.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(CompiledFilterCollection::merge(collections)))
+}
+
+/// Options for programs embedding [`run_with_options`], with no command line flag
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    /// Called after each tile is written; replaces the progress bar
+    pub progress: Option<ProgressCallback>,
+}
+
+pub async fn run(args: Args) -> Result<(), WranglerError> {
+    run_with_options(args, RunOptions::default()).await
+}
+
+/// Like [`run`], with the options only embedders can set
+pub async fn run_with_options(args: Args, options: RunOptions) -> Result<(), WranglerError> {
+    if args.validate_filter {
+        load_filters(&args).await?;
+        for path in args.filter.iter().chain(&args.filter_dir) {
+            println!("{}: OK", path.display());
+        }
+        return Ok(());
+    }
+
+    if args.list_tiles {
+        let coords = processing::list_tiles(&args.input).await?;
+        if args.count_only {
+            println!("{}", coords.len());
+        } else {
+            let mut stdout = std::io::stdout().lock();
+            for coord in &coords {
+                writeln!(stdout, "{}", processing::format_tile_coord(coord))?;
+            }
+        }
+        return Ok(());
+    }
+
+    // Existing tile directories are only written into with --overwrite. An existing
+    // output archive is only replaced once the new one is complete.
+    let to_directory = is_directory_output(&args.output);
+
+    let mut transform_options = transform_options(&args).await?;
+
+    // Validate filter files if provided
+    let fc = load_filters(&args).await?;
+
+    let pmtiles_paths = std::iter::once(args.input)
+        .chain(args.merge)
+        .collect::<Vec<_>>();
+    for pmtiles_path in &pmtiles_paths {
+        if !pmtiles_path.exists() {
+            return Err(WranglerError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Input file does not exist: {}", pmtiles_path.display()),
+            )));
         }
-        let filter_str = fs::read_to_string(filter_path).await?;
-        let filter_json: filtering::data::FilterCollection = serde_json::from_str(&filter_str)?;
-        let compiled = filter_json.compile()?;
-        fc = Some(compiled);
     }
 
     // Ensure output has pmtiles extension
-    if args.output.extension().and_then(|s| s.to_str()) != Some("pmtiles") {
-        panic!("Output file must have .pmtiles extension");
+    if !to_directory && args.output.extension().and_then(|s| s.to_str()) != Some("pmtiles") {
+        return Err(WranglerError::Config(anyhow!(
+            "Output file must have .pmtiles extension"
+        )));
+    }
+    // Tiles an earlier run wrote would be served alongside this run's
+    if to_directory
+        && !args.overwrite
+        && std::fs::read_dir(&args.output).is_ok_and(|mut entries| entries.next().is_some())
+    {
+        return Err(WranglerError::Config(anyhow!(
+            "Output directory {} is not empty; pass --overwrite to write into it anyway",
+            args.output.display()
+        )));
     }
 
     // Open input and new output DBs
-    let in_pmt = AsyncPmTilesReader::new_with_path(&pmtiles_path).await?;
-    let out_pmt_f = File::create(&args.output)?;
+    let in_pmt = AsyncPmTilesReader::new_with_path(&pmtiles_paths[0]).await?;
+    let rewrite_metadata = args.content_hash || args.infer_fields || args.precise_bounds;
+    if (rewrite_metadata || args.emit_tilejson || args.shards.is_some()) && to_directory {
+        return Err(WranglerError::Config(anyhow!(
+            "--content-hash, --infer-fields, --precise-bounds, --emit-tilejson and --shards need a \
+             .pmtiles output"
+        )));
+    }
     let header = in_pmt.get_header();
     let in_metadata_str = in_pmt.get_metadata().await?;
+    if let Some(layers) = metadata::vector_layer_ids(&in_metadata_str) {
+        let mut missing = transform_options
+            .layer_extent_overrides
+            .keys()
+            .filter(|layer| !layers.contains(*layer))
+            .collect::<Vec<_>>();
+        missing.sort();
+        for layer in missing {
+            eprintln!(
+                "⚠️ Layer {} has an extent override but isn't in the input's vector_layers",
+                layer
+            );
+        }
+    }
     if header.tile_type != pmtiles::TileType::Mvt {
-        panic!("Unsupported tile type: {:?}", header.tile_type);
+        return Err(WranglerError::UnsupportedTileType(header.tile_type));
+    }
+    // Widen the zoom range and bounds to cover every merged input
+    let (mut min_zoom, mut max_zoom) = (header.min_zoom, header.max_zoom);
+    let (mut min_lon, mut min_lat, mut max_lon, mut max_lat) = (
+        header.min_longitude,
+        header.min_latitude,
+        header.max_longitude,
+        header.max_latitude,
+    );
+    for pmtiles_path in &pmtiles_paths[1..] {
+        let merge_pmt = AsyncPmTilesReader::new_with_path(pmtiles_path).await?;
+        let merge_header = merge_pmt.get_header();
+        if merge_header.tile_type != pmtiles::TileType::Mvt {
+            return Err(WranglerError::UnsupportedTileType(merge_header.tile_type));
+        }
+        min_zoom = min_zoom.min(merge_header.min_zoom);
+        max_zoom = max_zoom.max(merge_header.max_zoom);
+        min_lon = min_lon.min(merge_header.min_longitude);
+        min_lat = min_lat.min(merge_header.min_latitude);
+        max_lon = max_lon.max(merge_header.max_longitude);
+        max_lat = max_lat.max(merge_header.max_latitude);
     }
-    // Build output metadata by merging input metadata with overrides
-    let out_metadata_str = metadata::apply_overrides(
+    if let Some(zoom) = args.max_zoom_overzoom {
+        max_zoom = max_zoom.max(zoom);
+    }
+    let bounds = [min_lon, min_lat, max_lon, max_lat];
+    if let Some(corrected) = corrected_bounds(bounds) {
+        let format = |b: [f32; 4]| b.map(|v| v.to_string()).join(",");
+        let message = format!("Input header bounds {} are malformed", format(bounds));
+        if args.strict {
+            return Err(WranglerError::Other(anyhow!(message)));
+        }
+        eprintln!("⚠️ {}; writing {} instead", message, format(corrected));
+        [min_lon, min_lat, max_lon, max_lat] = corrected;
+    }
+    // Build output metadata by merging input metadata with overrides, expanding
+    // `${VAR}` tokens from the environment
+    let interpolate = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|v| metadata::interpolate_env(v, args.allow_undefined_env))
+            .transpose()
+    };
+    let mut out_metadata_str = metadata::apply_overrides(
         &in_metadata_str,
-        args.name.as_deref(),
-        args.description.as_deref(),
-        args.attribution.as_deref(),
+        interpolate(&args.name)?.as_deref(),
+        interpolate(&args.description)?.as_deref(),
+        interpolate(&args.attribution)?.as_deref(),
     )?;
-    let out_pmt = pmtiles::PmTilesWriter::new(header.tile_type)
-        .tile_compression(header.tile_compression)
-        .min_zoom(header.min_zoom)
-        .max_zoom(header.max_zoom)
-        .bounds(
-            header.min_longitude,
-            header.min_latitude,
-            header.max_longitude,
-            header.max_latitude,
+    if args.sanitize_attribution {
+        out_metadata_str = metadata::sanitize_attribution(&out_metadata_str)?;
+    }
+    if let Some(sample_size) = args.validate_tiles {
+        processing::validate_tiles(
+            &pmtiles_paths,
+            args.input_compression.map(Into::into),
+            sample_size,
+        )
+        .await?;
+    }
+    if args.only_layers_with_features {
+        println!("Scanning input for layers with features...");
+        let layers = processing::layers_with_features(
+            &pmtiles_paths,
+            args.input_compression.map(Into::into),
+        )
+        .await?;
+        out_metadata_str = metadata::retain_vector_layers(&out_metadata_str, &layers)?;
+        transform_options.only_layers = Some(layers);
+    }
+    if transform_options.exclude_layers_regex.is_some()
+        && let Some(mut layers) = metadata::vector_layer_ids(&out_metadata_str)
+    {
+        layers.retain(|layer| !transform_options.excludes_layer(layer));
+        out_metadata_str = metadata::retain_vector_layers(&out_metadata_str, &layers)?;
+    }
+    // Tiles are read decompressed, so the output can use any supported compression
+    let tile_compression = args
+        .output_compression
+        .map(pmtiles::Compression::from)
+        .unwrap_or(header.tile_compression);
+    let writer = |metadata: &str| {
+        pmtiles::PmTilesWriter::new(header.tile_type)
+            .tile_compression(tile_compression)
+            .internal_compression(args.directory_compression.into())
+            .min_zoom(min_zoom)
+            .max_zoom(max_zoom)
+            .bounds(min_lon, min_lat, max_lon, max_lat)
+            .center_zoom(header.center_zoom)
+            .center(header.center_longitude, header.center_latitude)
+            .metadata(metadata)
+    };
+    let process_options = processing::ProcessOptions {
+        strict: args.strict,
+        events: args.events,
+        reorder_window: args.reorder_window,
+        fast: args.fast,
+        batch_by_zoom: args.batch_by_zoom,
+        unsorted: args.unsorted,
+        input_compression: args.input_compression.map(Into::into),
+        max_tile_bytes: args.max_tile_bytes,
+        removed_output: args.removed_output,
+        removed_limit: args.removed_limit,
+        passthrough_untouched: args.passthrough_untouched,
+        no_recompress: args.no_recompress,
+        flip_y: args.flip_y,
+        tiles_file: args.tiles_file,
+        bbox: args.bbox,
+        max_zoom_overzoom: args.max_zoom_overzoom,
+        checksum_manifest: args.checksum_manifest,
+        fail_on_empty_output: args.fail_on_empty_output,
+        progress: options.progress,
+        detailed_progress: args.detailed_progress,
+        concurrency: args.concurrency,
+        autotune: args.autotune,
+        shard: None,
+        quiet: false,
+    };
+
+    // The output header's fields, in metadata terms
+    let mut header_fields = serde_json::Map::from_iter([
+        ("minzoom".to_string(), json!(min_zoom)),
+        ("maxzoom".to_string(), json!(max_zoom)),
+        (
+            "bounds".to_string(),
+            json!([min_lon, min_lat, max_lon, max_lat]),
+        ),
+        (
+            "center".to_string(),
+            json!([
+                header.center_longitude,
+                header.center_latitude,
+                header.center_zoom
+            ]),
+        ),
+    ]);
+
+    if to_directory {
+        // There is no archive header, so its fields go into metadata.json
+        let mut fields = header_fields;
+        fields.insert("format".to_string(), json!("pbf"));
+        fields.insert(
+            "compression".to_string(),
+            json!(tile_compression.content_encoding()),
+        );
+        let metadata = metadata::set_fields(&out_metadata_str, fields)?;
+        let out = DirectoryWriter::new(args.output.clone(), metadata)?;
+        processing::process_tiles(
+            &pmtiles_paths,
+            out,
+            tile_compression,
+            fc,
+            transform_options,
+            process_options,
+        )
+        .await?;
+        println!("✅ Wrote transformed tiles to {}", args.output.display());
+        return Ok(());
+    }
+
+    // The archive is written to `<output>.tmp` and renamed over the output once it is
+    // finalized, so a failed run leaves any previous output intact. With
+    // --content-hash, --infer-fields or --precise-bounds the tiles go to another
+    // temporary archive first, because metadata is written before any tile.
+    let partial_path = TempFile::new(args.output.with_extension("pmtiles.tmp"));
+    let unfinished_path =
+        rewrite_metadata.then(|| TempFile::new(args.output.with_extension("pmtiles.tiles.tmp")));
+    let tiles_path: &Path = unfinished_path.as_deref().unwrap_or(&partial_path);
+    let default_extent = transform_options.default_extent;
+
+    let summary = if let Some(shards) = args.shards.filter(|&shards| shards > 1) {
+        // Shards hold consecutive runs of the sorted tiles, so joining them in order
+        // stores the tiles exactly as a single writer would
+        let shard_paths = (0..shards)
+            .map(|i| TempFile::new(args.output.with_extension(format!("pmtiles.shard{i}.tmp"))))
+            .collect::<Vec<_>>();
+        // Share the workers out, rather than running that many per shard
+        let concurrency = process_options
+            .concurrency
+            .unwrap_or_else(num_cpus::get)
+            .div_ceil(shards);
+        // Each shard's own bar would garble the terminal, so the shards' progress is
+        // added up and drives a single bar, or the embedder's callback
+        let bar = if process_options.progress.is_some() {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(0)
+        };
+        bar.set_style(
+            ProgressStyle::with_template(
+                "[{msg}] {wide_bar} {pos:>7}/{len:7} {elapsed}/{duration} {per_sec:7}",
+            )
+            .map_err(anyhow::Error::from)?,
+        );
+        let progress = process_options.progress.clone().unwrap_or_else(|| {
+            let bar = bar.clone();
+            Arc::new(move |event: ProgressEvent| {
+                bar.set_length(event.total as u64);
+                bar.set_position(event.processed as u64);
+                bar.set_message(processing::format_tile_coord(&event.coord));
+            })
+        });
+        let shard_progress = processing::shard_progress(progress, shards);
+        let mut runs = Vec::with_capacity(shards);
+        for ((i, shard_path), progress) in shard_paths.iter().enumerate().zip(shard_progress) {
+            let out_pmt = writer(&out_metadata_str).create(File::create(shard_path)?)?;
+            let shard_options = processing::ProcessOptions {
+                concurrency: Some(concurrency),
+                progress: Some(progress),
+                shard: Some((i, shards)),
+                // Each shard only sees its own tiles; the whole run is reported once joined
+                quiet: true,
+                ..process_options.clone()
+            };
+            runs.push(processing::process_tiles(
+                &pmtiles_paths,
+                out_pmt,
+                tile_compression,
+                fc.clone(),
+                transform_options.clone(),
+                shard_options,
+            ));
+        }
+        println!("Writing {} shards...", shards);
+        let mut summary = processing::RunSummary::default();
+        for shard_summary in futures::future::try_join_all(runs).await? {
+            summary.add(&shard_summary);
+        }
+        bar.finish_and_clear();
+        println!("Merging {} tiles from {} shards...", summary.tiles, shards);
+        let out_pmt = writer(&out_metadata_str).create(File::create(tiles_path)?)?;
+        processing::join_archives(&shard_paths, out_pmt).await?;
+        summary.print(process_options.max_tile_bytes);
+        summary
+    } else {
+        let out_pmt = writer(&out_metadata_str).create(File::create(tiles_path)?)?;
+        processing::process_tiles(
+            &pmtiles_paths,
+            out_pmt,
+            tile_compression,
+            fc,
+            transform_options,
+            process_options,
         )
-        .center_zoom(header.center_zoom)
-        .center(header.center_longitude, header.center_latitude)
-        .metadata(&out_metadata_str)
-        .create(out_pmt_f)?;
+        .await?
+    };
 
-    processing::process_tiles(&pmtiles_path, out_pmt, header.tile_compression, fc).await?;
+    if rewrite_metadata {
+        let mut metadata = out_metadata_str.clone();
+        if args.infer_fields {
+            metadata = metadata::set_vector_layer_fields(&metadata, summary.layer_fields())?;
+        }
+        if args.content_hash {
+            let hash = processing::content_hash(tiles_path).await?;
+            metadata = metadata::set_content_hash(&metadata, &hash)?;
+            println!("Content hash: {}", hash);
+        }
+        let mut feature_bounds = None;
+        if args.precise_bounds {
+            match processing::feature_bounds(tiles_path, default_extent).await? {
+                Some([min_lon, min_lat, max_lon, max_lat]) => {
+                    let (center_lon, center_lat) =
+                        ((min_lon + max_lon) / 2.0, (min_lat + max_lat) / 2.0);
+                    let fields = serde_json::Map::from_iter([
+                        (
+                            "bounds".to_string(),
+                            json!([min_lon, min_lat, max_lon, max_lat]),
+                        ),
+                        (
+                            "center".to_string(),
+                            json!([center_lon, center_lat, header.center_zoom]),
+                        ),
+                    ]);
+                    metadata = metadata::set_fields(&metadata, fields.clone())?;
+                    header_fields.extend(fields);
+                    println!("Feature bounds: {min_lon},{min_lat},{max_lon},{max_lat}");
+                    feature_bounds = Some((
+                        [min_lon, min_lat, max_lon, max_lat],
+                        [center_lon, center_lat],
+                    ));
+                }
+                None => eprintln!("⚠️ No features were written; keeping the input bounds"),
+            }
+        }
+        let mut out_writer = writer(&metadata);
+        if let Some((bounds, center)) = feature_bounds {
+            let [min_lon, min_lat, max_lon, max_lat] = bounds.map(|v| v as f32);
+            let [center_lon, center_lat] = center.map(|v| v as f32);
+            out_writer = out_writer
+                .bounds(min_lon, min_lat, max_lon, max_lat)
+                .center(center_lon, center_lat);
+        }
+        let out_pmt = out_writer.create(File::create(&partial_path)?)?;
+        processing::copy_tiles(tiles_path, out_pmt).await?;
+        fs::remove_file(tiles_path).await?;
+        out_metadata_str = metadata;
+    }
+    fs::rename(&partial_path, &args.output).await?;
+
+    if args.emit_tilejson {
+        let tiles_url = args.tiles_url.clone().unwrap_or_else(|| {
+            let name = args
+                .output
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            format!("http://localhost:8080/{name}/{{z}}/{{x}}/{{y}}.mvt")
+        });
+        let tilejson = metadata::tilejson(&out_metadata_str, &tiles_url, header_fields)?;
+        let mut tilejson_path = args.output.clone().into_os_string();
+        tilejson_path.push(".json");
+        fs::write(&tilejson_path, tilejson).await?;
+        println!("Wrote TileJSON to {}", Path::new(&tilejson_path).display());
+    }
 
     println!("✅ Wrote transformed tiles to {}", args.output.display());
     Ok(())
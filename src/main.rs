@@ -4,5 +4,5 @@ use mvt_wrangler::{Args, run};
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    run(args).await
+    Ok(run(args).await?)
 }
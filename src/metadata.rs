@@ -1,5 +1,83 @@
 use anyhow::{Result, anyhow};
+use geozero::mvt::{Tile, tile};
+use regex::{Captures, Regex};
 use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+
+/// Attribute type reported in `vector_layers[].fields`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Number,
+    Boolean,
+    String,
+}
+
+impl FieldType {
+    fn of(value: &tile::Value) -> Self {
+        if value.bool_value.is_some() {
+            FieldType::Boolean
+        } else if value.string_value.is_some() {
+            FieldType::String
+        } else {
+            FieldType::Number
+        }
+    }
+
+    /// The type covering values of both types; conflicting types fall back to `String`
+    fn merge(self, other: Self) -> Self {
+        if self == other {
+            self
+        } else {
+            FieldType::String
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            FieldType::Number => "Number",
+            FieldType::Boolean => "Boolean",
+            FieldType::String => "String",
+        }
+    }
+}
+
+/// Field types of each layer, by layer name and then attribute key
+pub type LayerFields = BTreeMap<String, BTreeMap<String, FieldType>>;
+
+/// Add the attribute types seen in `tile` to `fields`
+pub fn infer_fields(tile: &Tile, fields: &mut LayerFields) {
+    for layer in &tile.layers {
+        let layer_fields = fields.entry(layer.name.clone()).or_default();
+        for feature in &layer.features {
+            for pair in feature.tags.chunks_exact(2) {
+                let (Some(key), Some(value)) = (
+                    layer.keys.get(pair[0] as usize),
+                    layer.values.get(pair[1] as usize),
+                ) else {
+                    continue;
+                };
+                let field_type = FieldType::of(value);
+                layer_fields
+                    .entry(key.clone())
+                    .and_modify(|t| *t = t.merge(field_type))
+                    .or_insert(field_type);
+            }
+        }
+    }
+}
+
+/// Add the field types of `other` to `fields`
+pub fn merge_fields(fields: &mut LayerFields, other: &LayerFields) {
+    for (layer, other_fields) in other {
+        let layer_fields = fields.entry(layer.clone()).or_default();
+        for (key, &field_type) in other_fields {
+            layer_fields
+                .entry(key.clone())
+                .and_modify(|t| *t = t.merge(field_type))
+                .or_insert(field_type);
+        }
+    }
+}
 
 /// Apply simple metadata overrides to a PMTiles metadata JSON string.
 /// - Ensures the base is a JSON object (falls back to empty object if invalid)
@@ -33,3 +111,346 @@ pub fn apply_overrides(
 
     Ok(serde_json::to_string(&meta_value)?)
 }
+
+/// Replace `${VAR}` tokens with the value of the environment variable `VAR`.
+/// Undefined variables are an error, or expand to nothing with `allow_undefined`.
+pub fn interpolate_env(value: &str, allow_undefined: bool) -> Result<String> {
+    interpolate(value, |name| std::env::var(name).ok(), allow_undefined)
+}
+
+fn interpolate(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    allow_undefined: bool,
+) -> Result<String> {
+    let token = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    let mut undefined = Vec::new();
+    let interpolated = token.replace_all(value, |caps: &Captures| {
+        lookup(&caps[1]).unwrap_or_else(|| {
+            undefined.push(caps[1].to_string());
+            String::new()
+        })
+    });
+    if !undefined.is_empty() && !allow_undefined {
+        return Err(anyhow!(
+            "Undefined environment variable(s) in {:?}: {}",
+            value,
+            undefined.join(", ")
+        ));
+    }
+    Ok(interpolated.into_owned())
+}
+
+/// Tags kept by [`sanitize_html`], with the attributes each may keep
+const ATTRIBUTION_TAGS: [(&str, &[&str]); 8] = [
+    ("a", &["href", "title"]),
+    ("b", &[]),
+    ("strong", &[]),
+    ("i", &[]),
+    ("em", &[]),
+    ("small", &[]),
+    ("span", &[]),
+    ("br", &[]),
+];
+
+/// Tags removed along with their content by [`sanitize_html`]
+const DROPPED_CONTENT_TAGS: [&str; 2] = ["script", "style"];
+
+/// Sanitize the metadata's `attribution` HTML, whether it came from the input or an
+/// override. Metadata without an `attribution` string is returned unchanged.
+pub fn sanitize_attribution(base_json: &str) -> Result<String> {
+    let mut meta_value: Value = serde_json::from_str(base_json)?;
+    if let Some(Value::String(attribution)) = meta_value.get_mut("attribution") {
+        *attribution = sanitize_html(attribution);
+    }
+    Ok(serde_json::to_string(&meta_value)?)
+}
+
+/// Keep only the tags and attributes in [`ATTRIBUTION_TAGS`], with `href`s limited to
+/// `http`, `https` and `mailto` links. Other tags are removed but their text is kept,
+/// except for [`DROPPED_CONTENT_TAGS`]; stray `<` and `>` are escaped.
+fn sanitize_html(html: &str) -> String {
+    let tag = Regex::new(r"<(/?)([A-Za-z][A-Za-z0-9]*)([^>]*)>").unwrap();
+    let attribute =
+        Regex::new(r#"([A-Za-z][A-Za-z0-9-]*)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap();
+    let escape_text = |text: &str| text.replace('<', "&lt;").replace('>', "&gt;");
+    let escape_value = |value: &str| {
+        value
+            .replace('"', "&quot;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let safe_url = |url: &str| {
+        let url = url.trim().to_ascii_lowercase();
+        ["http://", "https://", "mailto:"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme))
+    };
+
+    let mut sanitized = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(caps) = tag.captures(rest) {
+        let whole = caps.get(0).unwrap();
+        sanitized.push_str(&escape_text(&rest[..whole.start()]));
+        rest = &rest[whole.end()..];
+
+        let closing = !caps[1].is_empty();
+        let name = caps[2].to_ascii_lowercase();
+        if DROPPED_CONTENT_TAGS.contains(&name.as_str()) {
+            if !closing {
+                // Skip to the end of the element, or of the input if it isn't closed
+                let end = Regex::new(&format!(r"(?i)</{}\s*>", name)).unwrap();
+                rest = end.find(rest).map_or("", |m| &rest[m.end()..]);
+            }
+            continue;
+        }
+        let Some((_, allowed)) = ATTRIBUTION_TAGS.iter().find(|(t, _)| *t == name) else {
+            continue;
+        };
+        if closing {
+            sanitized.push_str(&format!("</{}>", name));
+            continue;
+        }
+        sanitized.push('<');
+        sanitized.push_str(&name);
+        for attr in attribute.captures_iter(&caps[3]) {
+            let attr_name = attr[1].to_ascii_lowercase();
+            let value = attr
+                .get(2)
+                .or(attr.get(3))
+                .or(attr.get(4))
+                .map_or("", |m| m.as_str());
+            if !allowed.contains(&attr_name.as_str()) || (attr_name == "href" && !safe_url(value)) {
+                continue;
+            }
+            sanitized.push_str(&format!(" {}=\"{}\"", attr_name, escape_value(value)));
+        }
+        sanitized.push('>');
+    }
+    sanitized.push_str(&escape_text(rest));
+    sanitized
+}
+
+/// Set `content_hash` in the metadata
+pub fn set_content_hash(base_json: &str, hash: &str) -> Result<String> {
+    let mut meta_value: Value = serde_json::from_str(base_json)?;
+    let Value::Object(obj) = &mut meta_value else {
+        return Err(anyhow!("Metadata JSON is not an object"));
+    };
+    obj.insert("content_hash".to_string(), Value::String(hash.to_string()));
+    Ok(serde_json::to_string(&meta_value)?)
+}
+
+/// Set each of `fields` in the metadata, replacing existing values
+pub fn set_fields(base_json: &str, fields: serde_json::Map<String, Value>) -> Result<String> {
+    let mut meta_value: Value = serde_json::from_str(base_json)?;
+    let Value::Object(obj) = &mut meta_value else {
+        return Err(anyhow!("Metadata JSON is not an object"));
+    };
+    obj.extend(fields);
+    Ok(serde_json::to_string(&meta_value)?)
+}
+
+/// A TileJSON 3.0.0 document from the metadata, the archive header `fields` (zoom range,
+/// bounds, center) and the URL template the tiles are served from
+pub fn tilejson(
+    base_json: &str,
+    tiles_url: &str,
+    fields: serde_json::Map<String, Value>,
+) -> Result<String> {
+    let mut meta_value: Value = serde_json::from_str(base_json)?;
+    let Value::Object(obj) = &mut meta_value else {
+        return Err(anyhow!("Metadata JSON is not an object"));
+    };
+    obj.insert("tilejson".to_string(), Value::from("3.0.0"));
+    obj.insert("tiles".to_string(), Value::from(vec![tiles_url]));
+    obj.extend(fields);
+    Ok(serde_json::to_string_pretty(&meta_value)?)
+}
+
+/// Remove `vector_layers` entries whose `id` is not in `layers`.
+/// Metadata without a `vector_layers` array is returned unchanged.
+pub fn retain_vector_layers(base_json: &str, layers: &HashSet<String>) -> Result<String> {
+    let mut meta_value: Value = serde_json::from_str(base_json)?;
+    if let Some(Value::Array(vector_layers)) = meta_value.get_mut("vector_layers") {
+        vector_layers.retain(|layer| {
+            layer
+                .get("id")
+                .and_then(Value::as_str)
+                .is_some_and(|id| layers.contains(id))
+        });
+    }
+    Ok(serde_json::to_string(&meta_value)?)
+}
+
+/// Ids of the `vector_layers` entries, or `None` if the metadata has no such array
+pub fn vector_layer_ids(base_json: &str) -> Option<HashSet<String>> {
+    let meta_value: Value = serde_json::from_str(base_json).ok()?;
+    let vector_layers = meta_value.get("vector_layers")?.as_array()?;
+    Some(
+        vector_layers
+            .iter()
+            .filter_map(|layer| layer.get("id")?.as_str().map(str::to_string))
+            .collect(),
+    )
+}
+
+/// Replace `vector_layers[].fields` with the inferred field types. Layers missing from
+/// `vector_layers` are added with just their `id` and `fields`.
+pub fn set_vector_layer_fields(base_json: &str, fields: &LayerFields) -> Result<String> {
+    let mut meta_value: Value = serde_json::from_str(base_json)?;
+    let Value::Object(obj) = &mut meta_value else {
+        return Err(anyhow!("Metadata JSON is not an object"));
+    };
+    let vector_layers = obj
+        .entry("vector_layers")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    let Value::Array(vector_layers) = vector_layers else {
+        return Err(anyhow!("Metadata vector_layers is not an array"));
+    };
+
+    let fields_value = |layer_fields: &BTreeMap<String, FieldType>| {
+        Value::Object(
+            layer_fields
+                .iter()
+                .map(|(key, t)| (key.clone(), Value::String(t.as_str().to_string())))
+                .collect(),
+        )
+    };
+    let mut missing = fields.keys().collect::<HashSet<_>>();
+    for layer in vector_layers.iter_mut() {
+        let Some(id) = layer.get("id").and_then(Value::as_str) else {
+            continue;
+        };
+        if let Some(layer_fields) = fields.get(id) {
+            missing.remove(&id.to_string());
+            layer["fields"] = fields_value(layer_fields);
+        }
+    }
+    for (id, layer_fields) in fields {
+        if missing.contains(id) {
+            vector_layers.push(serde_json::json!({
+                "id": id,
+                "fields": fields_value(layer_fields),
+            }));
+        }
+    }
+    Ok(serde_json::to_string(&meta_value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geozero::mvt::tile::{Feature, Layer};
+    use serde_json::json;
+
+    fn int_value(v: i64) -> tile::Value {
+        tile::Value {
+            int_value: Some(v),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let lookup = |name: &str| (name == "BUILD_TIME").then(|| "2024-01-01".to_string());
+        assert_eq!(
+            interpolate("Built at ${BUILD_TIME}", lookup, false).unwrap(),
+            "Built at 2024-01-01"
+        );
+        // Only braced names are tokens
+        assert_eq!(
+            interpolate("$BUILD_TIME ${} ${BUILD_TIME", lookup, false).unwrap(),
+            "$BUILD_TIME ${} ${BUILD_TIME"
+        );
+
+        let err = interpolate("${BUILD_TIME} by ${BUILDER}", lookup, false).unwrap_err();
+        assert!(err.to_string().contains("BUILDER"), "{}", err);
+        assert_eq!(
+            interpolate("${BUILD_TIME} by ${BUILDER}", lookup, true).unwrap(),
+            "2024-01-01 by "
+        );
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        // Scripts go, content and all, while links survive
+        assert_eq!(
+            sanitize_html(
+                r#"<a href="https://example.com/">© Example</a><script>alert("x")</script>"#
+            ),
+            r#"<a href="https://example.com/">© Example</a>"#
+        );
+        assert_eq!(
+            sanitize_html("<SCRIPT src=x.js></SCRIPT ><b>OSM</b>"),
+            "<b>OSM</b>"
+        );
+        // Unsafe attributes and URLs are dropped, unknown tags keep their text
+        assert_eq!(
+            sanitize_html(
+                r#"<a href='javascript:alert(1)' onclick="x()" title=OSM>OSM</a> <img src=x onerror=y><u>c</u>"#
+            ),
+            r#"<a title="OSM">OSM</a> c"#
+        );
+        // Stray brackets are escaped
+        assert_eq!(sanitize_html("a < b > c"), "a &lt; b &gt; c");
+
+        let metadata = sanitize_attribution(
+            &json!({ "name": "x", "attribution": "<script>x</script><i>Me</i>" }).to_string(),
+        )
+        .unwrap();
+        let metadata: Value = serde_json::from_str(&metadata).unwrap();
+        assert_eq!(metadata["attribution"], "<i>Me</i>");
+        assert_eq!(metadata["name"], "x");
+    }
+
+    #[test]
+    fn test_infer_fields() {
+        let layer = Layer {
+            version: 2,
+            name: "roads".to_string(),
+            keys: vec!["lanes".to_string(), "ref".to_string()],
+            values: vec![
+                int_value(2),
+                int_value(4),
+                tile::Value {
+                    string_value: Some("A1".to_string()),
+                    ..Default::default()
+                },
+            ],
+            features: vec![
+                Feature {
+                    tags: vec![0, 0, 1, 1],
+                    ..Default::default()
+                },
+                Feature {
+                    tags: vec![0, 1, 1, 2],
+                    ..Default::default()
+                },
+            ],
+            extent: Some(4096),
+        };
+        let mut fields = LayerFields::new();
+        infer_fields(
+            &Tile {
+                layers: vec![layer],
+            },
+            &mut fields,
+        );
+
+        assert_eq!(fields["roads"]["lanes"], FieldType::Number);
+        assert_eq!(fields["roads"]["ref"], FieldType::String);
+
+        let metadata = json!({ "vector_layers": [{ "id": "roads", "minzoom": 0 }] });
+        let metadata = set_vector_layer_fields(&metadata.to_string(), &fields).unwrap();
+        let metadata: Value = serde_json::from_str(&metadata).unwrap();
+        assert_eq!(
+            metadata["vector_layers"],
+            json!([{
+                "id": "roads",
+                "minzoom": 0,
+                "fields": { "lanes": "Number", "ref": "String" }
+            }])
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use pmtiles::{PmTilesStreamWriter, TileCoord};
+use std::{
+    fs::File,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// Destination of the transformed tiles
+pub trait TileWriter: Send {
+    /// Store one tile, already compressed. Empty tiles are skipped.
+    fn write_tile(&mut self, coord: TileCoord, data: &[u8]) -> Result<()>;
+
+    /// Finish the output after the last tile
+    fn finish(self) -> Result<()>;
+}
+
+impl TileWriter for PmTilesStreamWriter<File> {
+    fn write_tile(&mut self, coord: TileCoord, data: &[u8]) -> Result<()> {
+        Ok(self.add_raw_tile(coord, data)?)
+    }
+
+    fn finish(self) -> Result<()> {
+        Ok(self.finalize()?)
+    }
+}
+
+/// Writes each tile to `root/z/x/y.pbf`, for static hosting, and the metadata to
+/// `root/metadata.json` once every tile is written
+pub struct DirectoryWriter {
+    root: PathBuf,
+    metadata: String,
+}
+
+impl DirectoryWriter {
+    pub fn new(root: PathBuf, metadata: String) -> Result<Self> {
+        std::fs::create_dir_all(&root)
+            .with_context(|| format!("failed to create output directory {}", root.display()))?;
+        Ok(Self { root, metadata })
+    }
+
+    fn tile_path(&self, coord: TileCoord) -> PathBuf {
+        self.root
+            .join(coord.z().to_string())
+            .join(coord.x().to_string())
+            .join(format!("{}.pbf", coord.y()))
+    }
+}
+
+impl TileWriter for DirectoryWriter {
+    fn write_tile(&mut self, coord: TileCoord, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let path = self.tile_path(coord);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, data).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    fn finish(self) -> Result<()> {
+        let path = self.root.join("metadata.json");
+        std::fs::write(&path, self.metadata)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// A temporary file that is deleted when dropped, so a run that fails part way leaves
+/// nothing behind. Renaming the file away first keeps it.
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self(path)
+    }
+}
+
+impl Deref for TempFile {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempFile {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // Usually already renamed or removed
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
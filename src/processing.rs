@@ -1,68 +1,1028 @@
-use anyhow::{Context, Result};
-use flate2::{Compression, write::GzEncoder};
-use futures::TryStreamExt as _;
-use indicatif::{ProgressBar, ProgressStyle};
-use pmtiles::{AsyncPmTilesReader, TileCoord, TileId};
+use anyhow::{Context, Result, anyhow};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use futures::{Stream, StreamExt as _, TryStreamExt as _, stream};
+use geo::BoundingRect as _;
+use geozero::mvt::{Message as _, Tile};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use pmtiles::{AsyncPmTilesReader, MmapBackend, TileCoord, TileId};
 use rayon::prelude::*;
-use std::{collections::BTreeMap, path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    hash::Hasher as _,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+};
 use tokio::task::JoinSet;
+use twox_hash::XxHash64;
 
-use crate::{filtering::data::CompiledFilterCollection, transform::transform_tile};
+use crate::{
+    error::WranglerError,
+    events::{self, Event, Outcome},
+    filtering::data::CompiledFilterCollection,
+    metadata,
+    output::TileWriter,
+    report,
+    transform::{
+        TransformOptions, TransformStats, extend_bounds, feature_extent, leaves_tile_unchanged,
+        merge_tiles, overzoom_tile, tile_bounds, transform_tile, transform_tile_reporting_removed,
+    },
+};
 
 const QUEUE_CAPACITY: usize = 2_usize.pow(16);
 
+/// Progress after a tile has been written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressEvent {
+    /// Tiles handled so far, including this one
+    pub processed: usize,
+    /// Tiles read from the inputs so far
+    pub read: usize,
+    /// Tiles transformed so far
+    pub transformed: usize,
+    /// Tiles to handle in total
+    pub total: usize,
+    /// The tile just handled
+    pub coord: TileCoord,
+}
+
+/// Called once per tile, in write order, from a blocking thread
+pub type ProgressCallback = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
+
+/// Split `progress` into one callback per shard, which add up the shards' counts so
+/// that `progress` sees a single run
+pub fn shard_progress(progress: ProgressCallback, shards: usize) -> Vec<ProgressCallback> {
+    // Each shard's last counts and their sums. The lock is held while calling
+    // `progress`, so events stay in order though shards write from their own threads.
+    let counts = Arc::new(Mutex::new((vec![[0; 3]; shards], [0; 3])));
+    (0..shards)
+        .map(|shard| {
+            let (progress, counts) = (progress.clone(), counts.clone());
+            Arc::new(move |event: ProgressEvent| {
+                let mut counts = counts.lock().unwrap();
+                let (last, sums) = &mut *counts;
+                let now = [event.processed, event.read, event.transformed];
+                for stage in 0..3 {
+                    sums[stage] += now[stage] - last[shard][stage];
+                }
+                last[shard] = now;
+                let [processed, read, transformed] = *sums;
+                progress(ProgressEvent {
+                    processed,
+                    read,
+                    transformed,
+                    ..event
+                });
+            }) as ProgressCallback
+        })
+        .collect()
+}
+
+/// Options controlling how the pipeline reads and writes tiles
+#[derive(Clone, Default)]
+pub struct ProcessOptions {
+    /// Turn archive sanity warnings into errors
+    pub strict: bool,
+    /// Write per-tile NDJSON events to this path (`-` for stderr)
+    pub events: Option<PathBuf>,
+    /// Maximum number of tiles held back to write the output in order (unbounded if `None`)
+    pub reorder_window: Option<usize>,
+    /// Write tiles in the order they finish, skipping the reorder buffer entirely
+    pub fast: bool,
+    /// Queue one zoom level at a time, starting the next once it is fully written
+    pub batch_by_zoom: bool,
+    /// Queue tiles in the order the input's directories list them instead of sorting
+    /// them first. Only supported for a single input, without `max_zoom_overzoom` or
+    /// `batch_by_zoom`.
+    pub unsorted: bool,
+    /// Decompress input tiles with this codec instead of the one declared in the header
+    pub input_compression: Option<pmtiles::Compression>,
+    /// Warn about (or under `strict`, reject) output tiles larger than this many bytes
+    pub max_tile_bytes: Option<usize>,
+    /// Write features removed by filters to this GeoJSON file
+    pub removed_output: Option<PathBuf>,
+    /// Maximum number of features in the removed features report
+    pub removed_limit: usize,
+    /// Copy tiles that no filter or option changes without re-encoding them
+    pub passthrough_untouched: bool,
+    /// Write the stored bytes of tiles whose transformed MVT is identical to the input
+    pub no_recompress: bool,
+    /// Write every tile at `y = 2^z - 1 - y`, converting between TMS and XYZ rows
+    pub flip_y: bool,
+    /// Only process the tiles listed in this file, one `z/x/y` per line
+    pub tiles_file: Option<PathBuf>,
+    /// Only process tiles overlapping this `[min_lon, min_lat, max_lon, max_lat]` rectangle
+    pub bbox: Option<[f64; 4]>,
+    /// Synthesize tiles down to this zoom by subdividing the tiles at the input's
+    /// deepest zoom level
+    pub max_zoom_overzoom: Option<u8>,
+    /// Write a CSV of each written tile's hash to this path
+    pub checksum_manifest: Option<PathBuf>,
+    /// Fail with [`WranglerError::EmptyOutput`] if no tile has any features left
+    pub fail_on_empty_output: bool,
+    /// Report progress here instead of drawing a progress bar
+    pub progress: Option<ProgressCallback>,
+    /// Draw a bar for each stage (tiles read, transformed and written) instead of one
+    /// overall bar, to show which stage holds the others back
+    pub detailed_progress: bool,
+    /// Number of reader tasks and transform threads (the number of CPUs if `None`)
+    pub concurrency: Option<usize>,
+    /// Before processing, time the transform of this many tiles at several worker
+    /// counts and use the fastest as `concurrency`
+    pub autotune: Option<usize>,
+    /// Only process the `index`th of `count` contiguous runs of the output tiles, so
+    /// that each run can be written to its own archive and joined with [`join_archives`].
+    /// Progress events count the shard's tiles against the whole run's `total`; see
+    /// [`shard_progress`].
+    pub shard: Option<(usize, usize)>,
+    /// Don't print the tile count or the report at the end, leaving the caller to print
+    /// the returned [`RunSummary`]
+    pub quiet: bool,
+}
+
+/// What [`process_tiles`] did, reported at the end of a run
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    /// Tiles read from the inputs
+    pub tiles: usize,
+    /// Input tiles left out of the output, which the digests don't cover
+    skipped: usize,
+    oversized: usize,
+    stats: TransformStats,
+    input_digest: u64,
+    output_digest: u64,
+}
+
+impl RunSummary {
+    /// Add another run's summary to this one, as if both were one run
+    pub fn add(&mut self, other: &RunSummary) {
+        self.tiles += other.tiles;
+        // Every shard leaves out the same tiles
+        self.skipped = self.skipped.max(other.skipped);
+        self.oversized += other.oversized;
+        self.stats.add(&other.stats);
+        self.input_digest = self.input_digest.wrapping_add(other.input_digest);
+        self.output_digest = self.output_digest.wrapping_add(other.output_digest);
+    }
+
+    /// Attribute types of each layer, under `TransformOptions::infer_fields`
+    pub fn layer_fields(&self) -> &metadata::LayerFields {
+        &self.stats.layer_fields
+    }
+
+    /// Print the warnings, layer sizes and whether anything changed
+    pub fn print(&self, max_tile_bytes: Option<usize>) {
+        if let Some(max) = max_tile_bytes
+            && self.oversized > 0
+        {
+            println!(
+                "⚠️ {} tiles were over the {} byte limit",
+                self.oversized, max
+            );
+        }
+        if self.stats.key_collisions > 0 {
+            println!(
+                "⚠️ {} tags collided with another tag's normalized key; the last value was kept",
+                self.stats.key_collisions
+            );
+        }
+        let layer_lines = report::layer_size_lines(&self.stats.layer_bytes);
+        if !layer_lines.is_empty() {
+            println!("Layer sizes, before tile compression:");
+            for line in layer_lines {
+                println!("  {}", line);
+            }
+        }
+        if self.skipped == 0 && self.input_digest == self.output_digest {
+            println!("Output identical to input: no tile was changed");
+        } else {
+            println!("Output differs from input");
+        }
+        println!("All done.");
+    }
+}
+
 pub fn format_tile_coord(coords: &TileCoord) -> String {
     format!("{}/{}/{}", coords.z(), coords.x(), coords.y())
 }
 
-pub async fn process_tiles(
-    pmtiles_path: &Path,
-    mut out_pmt: pmtiles::PmTilesStreamWriter<std::fs::File>,
-    tile_compression: pmtiles::Compression,
-    filter_collection: Option<CompiledFilterCollection>,
-) -> Result<()> {
-    let concurrency_limit = num_cpus::get();
+/// Mirror a tile's row between the TMS (y up) and XYZ (y down) conventions.
+/// Flipping twice returns the original tile.
+fn flip_y(coord: TileId) -> TileId {
+    let coord = TileCoord::from(coord);
+    let y = (1 << coord.z()) - 1 - coord.y();
+    TileCoord::new(coord.z(), coord.x(), y)
+        .expect("a flipped row is within the zoom level")
+        .into()
+}
 
-    let in_pmt = Arc::new(
-        AsyncPmTilesReader::new_with_path(pmtiles_path)
-            .await
-            .with_context(|| "failed to open input PMTiles")?,
+/// The tile at `zoom` containing `coord`, which must be at `zoom` or deeper
+fn ancestor(coord: TileId, zoom: u8) -> TileId {
+    let coord = TileCoord::from(coord);
+    let shift = coord.z() - zoom;
+    TileCoord::new(zoom, coord.x() >> shift, coord.y() >> shift)
+        .expect("an ancestor is within its zoom level")
+        .into()
+}
+
+/// Add every descendant down to `target_zoom` of the tiles at the deepest zoom level,
+/// read from the same inputs. Returns that zoom level, or `None` if it is already at
+/// or past `target_zoom`.
+fn overzoom_sources(sources: &mut BTreeMap<TileId, Vec<usize>>, target_zoom: u8) -> Option<u8> {
+    let base_zoom = sources.keys().map(|&id| TileCoord::from(id).z()).max()?;
+    if target_zoom <= base_zoom {
+        return None;
+    }
+    let deepest = sources
+        .iter()
+        .filter(|(id, _)| TileCoord::from(**id).z() == base_zoom)
+        .map(|(id, inputs)| (TileCoord::from(*id), inputs.clone()))
+        .collect::<Vec<_>>();
+    for (coord, inputs) in deepest {
+        for zoom in base_zoom + 1..=target_zoom {
+            let scale = 1 << (zoom - base_zoom);
+            for dx in 0..scale {
+                for dy in 0..scale {
+                    let child =
+                        TileCoord::new(zoom, coord.x() * scale + dx, coord.y() * scale + dy)
+                            .expect("a child is within its zoom level");
+                    sources.insert(child.into(), inputs.clone());
+                }
+            }
+        }
+    }
+    Some(base_zoom)
+}
+
+/// Number of tiles in each zoom level, in order. Tile ids sort by zoom first, so
+/// these are consecutive runs of sorted `sources`.
+fn zoom_batch_sizes(sources: &[(TileId, Vec<usize>)]) -> Vec<usize> {
+    let mut sizes = Vec::new();
+    let mut zoom = None;
+    for &(id, _) in sources {
+        let z = TileCoord::from(id).z();
+        if zoom == Some(z) {
+            *sizes.last_mut().unwrap() += 1;
+        } else {
+            sizes.push(1);
+            zoom = Some(z);
+        }
+    }
+    sizes
+}
+
+/// A tile that has been read, transformed and re-compressed, along with its position
+/// in the sorted coordinate list and what transforming it did.
+type ProcessedTile = (usize, TileId, Vec<u8>, TransformStats);
+
+/// A tile as read from the inputs
+enum TileInput {
+    /// The decompressed copies of the tile from every input archive containing it, and
+    /// the stored bytes to reuse if transforming leaves a single copy unchanged
+    Decompressed(Vec<Vec<u8>>, Option<Vec<u8>>),
+    /// The stored bytes of a tile that is copied to the output as-is
+    Raw(Vec<u8>),
+}
+
+type ReadTile = (usize, TileId, TileInput);
+
+/// The reader and transformer stages of the pipeline. Transformed tiles arrive on
+/// `out_rx` in completion order; `tasks` must be joined to surface errors.
+struct TilePipeline {
+    tasks: JoinSet<Result<()>>,
+    out_rx: flume::Receiver<ProcessedTile>,
+    coords_count: usize,
+    /// Tiles in the whole run; more than `coords_count` under `ProcessOptions::shard`
+    run_count: usize,
+    /// Input tiles left out of the whole run by `tiles_file` or `bbox`
+    skipped: usize,
+    /// Number of tiles over `ProcessOptions::max_tile_bytes`, final once `tasks` are joined
+    oversized: Arc<AtomicUsize>,
+    /// Under `ProcessOptions::fail_on_empty_output`, whether any output tile has
+    /// features, final once `tasks` are joined
+    has_features: Arc<AtomicBool>,
+    /// Hashes of the tiles read and written, final once `tasks` are joined
+    digests: Arc<ContentDigests>,
+    /// Under `ProcessOptions::batch_by_zoom`, the number of tiles in each zoom level
+    zoom_batches: Vec<usize>,
+    /// Under `ProcessOptions::batch_by_zoom`, signal here once a zoom level is written to
+    /// let the pipeline start on the next one
+    batch_done: Option<flume::Sender<()>>,
+    /// Tiles read so far, as a hidden bar that can be drawn with the others
+    read: ProgressBar,
+    /// Tiles transformed so far, as a hidden bar that can be drawn with the others
+    transformed: ProgressBar,
+}
+
+/// Order-independent hashes of every tile read and every tile written, each with its
+/// tile id. Different digests mean the run changed a tile.
+///
+/// Each digest is the wrapping sum of the tiles' 64-bit hashes, so equal digests only
+/// hint that nothing changed: changes to several tiles can cancel out in the sum, by
+/// chance or in tiles crafted to collide.
+#[derive(Default)]
+struct ContentDigests {
+    input: AtomicU64,
+    output: AtomicU64,
+}
+
+impl ContentDigests {
+    /// The hash of a tile's decompressed bytes, seeded with its tile id
+    fn tile_digest(coord: TileId, data: &[u8]) -> u64 {
+        XxHash64::oneshot(coord.value(), data)
+    }
+
+    fn add_input(&self, coord: TileId, data: &[u8]) {
+        self.input
+            .fetch_add(Self::tile_digest(coord, data), Ordering::Relaxed);
+    }
+
+    fn add_output(&self, digest: u64) {
+        self.output.fetch_add(digest, Ordering::Relaxed);
+    }
+}
+
+/// Buffers out-of-order tiles and releases them in coordinate order.
+///
+/// The PMTiles writer accepts tiles in any order, but only archives written in
+/// tile id order are clustered (and get run-length deduplication). With a
+/// `window`, at most that many tiles are held back; beyond it the lowest buffered
+/// tile is released early, trading clustering for bounded memory.
+struct ReorderBuffer {
+    next: usize,
+    buf: BTreeMap<usize, Option<(TileId, Vec<u8>)>>,
+    window: Option<usize>,
+    /// Positions past `next` that were already released early
+    released: BTreeSet<usize>,
+}
+
+impl ReorderBuffer {
+    fn new() -> Self {
+        Self::with_window(None)
+    }
+
+    fn with_window(window: Option<usize>) -> Self {
+        Self {
+            next: 0,
+            buf: BTreeMap::new(),
+            window,
+            released: BTreeSet::new(),
+        }
+    }
+
+    /// Whether no tile is held back
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty() && self.released.is_empty()
+    }
+
+    fn insert(&mut self, i: usize, coord: TileId, data: Vec<u8>) {
+        self.buf.insert(i, Some((coord, data)));
+    }
+
+    /// Mark a position as done without a tile
+    fn skip(&mut self, i: usize) {
+        self.buf.insert(i, None);
+    }
+
+    /// Take the next tile in order, if it has arrived, or the lowest buffered
+    /// tile if the window is full
+    fn pop_ready(&mut self) -> Option<(TileId, Vec<u8>)> {
+        loop {
+            if let Some(v) = self.buf.remove(&self.next) {
+                self.next += 1;
+                if v.is_some() {
+                    return v;
+                }
+            } else if self.released.remove(&self.next) {
+                self.next += 1;
+            } else if self.window.is_some_and(|w| self.buf.len() > w) {
+                let (i, v) = self.buf.pop_first()?;
+                self.released.insert(i);
+                if v.is_some() {
+                    return v;
+                }
+            } else {
+                return None;
+            }
+        }
+    }
+}
+
+/// Find tiles whose zoom level is outside the range declared in the header
+fn out_of_range_zooms(coords: &[TileId], min_zoom: u8, max_zoom: u8) -> Vec<TileCoord> {
+    coords
+        .iter()
+        .map(|&id| TileCoord::from(id))
+        .filter(|c| c.z() < min_zoom || c.z() > max_zoom)
+        .collect()
+}
+
+/// Warn about (or under `strict`, reject) tiles outside the header's zoom range,
+/// which usually indicate a corrupt archive
+fn check_zoom_range(coords: &[TileId], min_zoom: u8, max_zoom: u8, strict: bool) -> Result<()> {
+    let invalid = out_of_range_zooms(coords, min_zoom, max_zoom);
+    if invalid.is_empty() {
+        return Ok(());
+    }
+    let examples = invalid
+        .iter()
+        .take(5)
+        .map(format_tile_coord)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let message = format!(
+        "{} tiles are outside the header zoom range {}-{} (e.g. {})",
+        invalid.len(),
+        min_zoom,
+        max_zoom,
+        examples
     );
+    if strict {
+        return Err(anyhow!(message));
+    }
+    eprintln!("⚠️ {}", message);
+    Ok(())
+}
 
-    let entries = in_pmt.clone().entries().try_collect::<Vec<_>>().await?;
+/// Parse a tile list with one `z/x/y` per line. Blank lines are ignored.
+fn parse_tile_list(text: &str) -> Result<BTreeSet<TileId>> {
+    text.lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(n, line)| {
+            let parse = || -> Option<TileCoord> {
+                let mut parts = line.split('/');
+                let z = parts.next()?.parse().ok()?;
+                let x = parts.next()?.parse().ok()?;
+                let y = parts.next()?.parse().ok()?;
+                if parts.next().is_some() {
+                    return None;
+                }
+                TileCoord::new(z, x, y).ok()
+            };
+            parse()
+                .map(TileId::from)
+                .ok_or_else(|| anyhow!("line {}: expected a valid z/x/y, got {}", n, line))
+        })
+        .collect()
+}
+
+/// Keep only the listed tiles. Returns the listed tiles that aren't in any input.
+fn restrict_to_tiles(
+    sources: &mut Vec<(TileId, Vec<usize>)>,
+    tiles: &BTreeSet<TileId>,
+) -> Vec<TileCoord> {
+    sources.retain(|(coord, _)| tiles.contains(coord));
+    let present = sources
+        .iter()
+        .map(|&(coord, _)| coord)
+        .collect::<HashSet<_>>();
+    tiles
+        .iter()
+        .filter(|coord| !present.contains(coord))
+        .map(|&coord| coord.into())
+        .collect()
+}
+
+/// Keep only the tiles overlapping the rectangle. Tiles that merely touch its edge are
+/// dropped.
+fn restrict_to_bbox(sources: &mut Vec<(TileId, Vec<usize>)>, bbox: &[f64; 4]) {
+    let [min_lon, min_lat, max_lon, max_lat] = *bbox;
+    sources.retain(|&(coord, _)| {
+        tile_bounds(&coord.into())
+            .bounding_rect()
+            .is_some_and(|rect| {
+                rect.min().x < max_lon
+                    && rect.max().x > min_lon
+                    && rect.min().y < max_lat
+                    && rect.max().y > min_lat
+            })
+    });
+}
 
+/// Keep only the `index`th of `count` contiguous runs of tiles. The runs differ in
+/// length by at most one tile and, in order, cover every tile exactly once.
+fn restrict_to_shard(sources: &mut Vec<(TileId, Vec<usize>)>, index: usize, count: usize) {
+    let len = sources.len();
+    let (start, end) = (len * index / count, len * (index + 1) / count);
+    sources.truncate(end);
+    sources.drain(..start);
+}
+
+/// Warn about (or under `strict`, reject) a tile over `max_tile_bytes`.
+/// Returns whether the tile is oversized.
+fn check_tile_size(
+    coords: &TileCoord,
+    size: usize,
+    max_tile_bytes: Option<usize>,
+    strict: bool,
+) -> Result<bool> {
+    let Some(max) = max_tile_bytes.filter(|&max| size > max) else {
+        return Ok(false);
+    };
+    let message = format!(
+        "tile {} is {} bytes, over the {} byte limit",
+        format_tile_coord(coords),
+        size,
+        max
+    );
+    if strict {
+        return Err(anyhow!(message));
+    }
+    eprintln!("⚠️ {}", message);
+    Ok(true)
+}
+
+/// Read a tile listed in the archive's directory and decompress it, with
+/// `input_compression` overriding the codec declared in the header
+async fn read_tile(
+    in_pmt: &AsyncPmTilesReader<MmapBackend>,
+    coord: TileId,
+    input_compression: Option<pmtiles::Compression>,
+) -> Result<Vec<u8>> {
+    // Because we're enumerating tile coordinates, get_tile(_decompressed)
+    // should never return a None, unless something is really wrong.
+    Ok(match input_compression {
+        None => in_pmt.get_tile_decompressed(coord).await?.unwrap().to_vec(),
+        Some(compression) => decompress_tile(&in_pmt.get_tile(coord).await?.unwrap(), compression)?,
+    })
+}
+
+/// Every tile listed in an archive's directories, sorted by zoom, column and row
+pub async fn list_tiles(pmtiles_path: &Path) -> Result<Vec<TileCoord>> {
+    let in_pmt = Arc::new(AsyncPmTilesReader::new_with_path(pmtiles_path).await?);
+    let entries = in_pmt.entries().try_collect::<Vec<_>>().await?;
     let mut coords = entries
         .iter()
         .flat_map(|e| e.iter_coords())
+        .map(TileCoord::from)
         .collect::<Vec<_>>();
-    coords.sort_unstable();
-    let coords_count = coords.len();
+    coords.sort_by_key(|c| (c.z(), c.x(), c.y()));
+    Ok(coords)
+}
 
-    println!("Found {} tiles in the input archive", coords_count);
+/// Hash every stored tile of an archive, with its tile id, in tile id order. The result
+/// is the same whenever the tiles are, regardless of the order they were written in.
+pub async fn content_hash(pmtiles_path: &Path) -> Result<String> {
+    let in_pmt = Arc::new(AsyncPmTilesReader::new_with_path(pmtiles_path).await?);
+    let entries = in_pmt.clone().entries().try_collect::<Vec<_>>().await?;
+    let mut hasher = XxHash64::with_seed(0);
+    for coord in entries.iter().flat_map(|e| e.iter_coords()) {
+        hasher.write_u64(coord.value());
+        hasher.write(&in_pmt.get_tile(coord).await?.unwrap());
+    }
+    Ok(format!("{:016x}", hasher.finish()))
+}
 
-    let (in_tx, in_rx) = flume::bounded::<(usize, TileId, Vec<u8>)>(QUEUE_CAPACITY);
+/// The lon/lat rectangle around every feature stored in an archive, as
+/// `[min_lon, min_lat, max_lon, max_lat]`, or `None` if no tile has a feature.
+/// Layers without an extent are taken to have `default_extent`.
+pub async fn feature_bounds(pmtiles_path: &Path, default_extent: u32) -> Result<Option<[f64; 4]>> {
+    let in_pmt = Arc::new(AsyncPmTilesReader::new_with_path(pmtiles_path).await?);
+    let entries = in_pmt.clone().entries().try_collect::<Vec<_>>().await?;
+    let mut bounds: Option<[f64; 4]> = None;
+    for coord in entries.iter().flat_map(|e| e.iter_coords()) {
+        let data = read_tile(&in_pmt, coord, None).await?;
+        let tile = Tile::decode(data.as_slice()).map_err(|source| WranglerError::TileDecode {
+            coord: format_tile_coord(&coord.into()),
+            source,
+        })?;
+        if let Some([min_lon, min_lat, max_lon, max_lat]) =
+            feature_extent(&tile, &coord.into(), default_extent)?
+        {
+            let rect = geo::Rect::new((min_lon, min_lat), (max_lon, max_lat));
+            extend_bounds(&mut bounds, rect);
+        }
+    }
+    Ok(bounds)
+}
+
+/// Copy every stored tile of an archive, as-is and in tile id order, into `out_pmt`
+pub async fn copy_tiles(
+    pmtiles_path: &Path,
+    out_pmt: pmtiles::PmTilesStreamWriter<std::fs::File>,
+) -> Result<()> {
+    join_archives(&[pmtiles_path], out_pmt).await
+}
+
+/// Copy the stored tiles of each archive in turn into `out_pmt`, then finalize it.
+/// Used to join shard archives, which hold consecutive runs of tile ids.
+pub async fn join_archives(
+    pmtiles_paths: &[impl AsRef<Path>],
+    mut out_pmt: pmtiles::PmTilesStreamWriter<std::fs::File>,
+) -> Result<()> {
+    for pmtiles_path in pmtiles_paths {
+        let pmtiles_path = pmtiles_path.as_ref();
+        let in_pmt = Arc::new(AsyncPmTilesReader::new_with_path(pmtiles_path).await?);
+        let entries = in_pmt.clone().entries().try_collect::<Vec<_>>().await?;
+        for coord in entries.iter().flat_map(|e| e.iter_coords()) {
+            out_pmt.add_raw_tile(coord.into(), &in_pmt.get_tile(coord).await?.unwrap())?;
+        }
+    }
+    out_pmt.finalize()?;
+    Ok(())
+}
+
+/// Share of sampled tiles that may fail to decode before `validate_tiles` gives up
+const MAX_INVALID_TILE_RATE: f64 = 0.1;
+
+/// Decode an evenly spaced sample of up to `sample_size` tiles from each input and fail
+/// if too many of them aren't valid MVT, before starting a long run on a bad archive
+pub async fn validate_tiles(
+    pmtiles_paths: &[PathBuf],
+    input_compression: Option<pmtiles::Compression>,
+    sample_size: usize,
+) -> Result<()> {
+    for pmtiles_path in pmtiles_paths {
+        let in_pmt = Arc::new(
+            AsyncPmTilesReader::new_with_path(pmtiles_path)
+                .await
+                .with_context(|| {
+                    format!("failed to open input PMTiles {}", pmtiles_path.display())
+                })?,
+        );
+        let entries = in_pmt.clone().entries().try_collect::<Vec<_>>().await?;
+        let coords = entries
+            .iter()
+            .flat_map(|e| e.iter_coords())
+            .collect::<Vec<_>>();
+        let step = coords.len().div_ceil(sample_size.max(1)).max(1);
+        let sample = coords.iter().step_by(step).copied().collect::<Vec<_>>();
+
+        let mut failed = 0;
+        for &coord in &sample {
+            let valid = match read_tile(&in_pmt, coord, input_compression).await {
+                Ok(data) => Tile::decode(data.as_slice()).is_ok(),
+                Err(_) => false,
+            };
+            if !valid {
+                failed += 1;
+            }
+        }
+        println!(
+            "Validated {} sampled tiles of {}: {} invalid",
+            sample.len(),
+            pmtiles_path.display(),
+            failed
+        );
+        if failed as f64 > sample.len() as f64 * MAX_INVALID_TILE_RATE {
+            return Err(WranglerError::InvalidTiles {
+                failed,
+                sampled: sample.len(),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Scan every tile of the inputs for the names of layers that have at least one feature
+pub async fn layers_with_features(
+    pmtiles_paths: &[PathBuf],
+    input_compression: Option<pmtiles::Compression>,
+) -> Result<HashSet<String>> {
+    let mut names = HashSet::new();
+    for pmtiles_path in pmtiles_paths {
+        let in_pmt = Arc::new(
+            AsyncPmTilesReader::new_with_path(pmtiles_path)
+                .await
+                .with_context(|| {
+                    format!("failed to open input PMTiles {}", pmtiles_path.display())
+                })?,
+        );
+        let entries = in_pmt.clone().entries().try_collect::<Vec<_>>().await?;
+        let coords = entries
+            .iter()
+            .flat_map(|e| e.iter_coords())
+            .collect::<Vec<_>>();
+
+        let mut tiles = stream::iter(coords)
+            .map(|coord| {
+                let in_pmt = in_pmt.clone();
+                async move {
+                    Ok::<_, anyhow::Error>((
+                        coord,
+                        read_tile(&in_pmt, coord, input_compression).await?,
+                    ))
+                }
+            })
+            .buffer_unordered(num_cpus::get());
+        while let Some((coord, data)) = tiles.try_next().await? {
+            let tile =
+                Tile::decode(data.as_slice()).map_err(|source| WranglerError::TileDecode {
+                    coord: format_tile_coord(&coord.into()),
+                    source,
+                })?;
+            names.extend(
+                tile.layers
+                    .into_iter()
+                    .filter(|layer| !layer.features.is_empty())
+                    .map(|layer| layer.name),
+            );
+        }
+    }
+    Ok(names)
+}
+
+/// Worker counts tried by [`autotune_concurrency`]: powers of two up to `max`, and `max`
+fn autotune_candidates(max: usize) -> Vec<usize> {
+    let mut candidates = std::iter::successors(Some(1_usize), |n| Some(n * 2))
+        .take_while(|&n| n < max)
+        .collect::<Vec<_>>();
+    candidates.push(max.max(1));
+    candidates
+}
+
+/// The worker count with the shortest time, preferring fewer workers on ties
+fn fastest_concurrency(timings: &[(usize, std::time::Duration)]) -> usize {
+    timings
+        .iter()
+        .min_by_key(|(workers, elapsed)| (*elapsed, *workers))
+        .map_or(1, |(workers, _)| *workers)
+}
+
+/// Transform the first `sample_size` tiles of the first input with each of
+/// [`autotune_candidates`] as the number of threads, and return the fastest count.
+/// The transformed tiles are discarded.
+async fn autotune_concurrency(
+    pmtiles_path: &Path,
+    tile_compression: pmtiles::Compression,
+    filter_collection: Option<&CompiledFilterCollection>,
+    transform_options: &TransformOptions,
+    input_compression: Option<pmtiles::Compression>,
+    sample_size: usize,
+) -> Result<usize> {
+    let in_pmt = Arc::new(AsyncPmTilesReader::new_with_path(pmtiles_path).await?);
+    let entries = in_pmt.clone().entries().try_collect::<Vec<_>>().await?;
+    let mut sample = Vec::with_capacity(sample_size);
+    for coord in entries
+        .iter()
+        .flat_map(|e| e.iter_coords())
+        .take(sample_size)
+    {
+        sample.push((coord, read_tile(&in_pmt, coord, input_compression).await?));
+    }
+
+    let mut timings = Vec::new();
+    for workers in autotune_candidates(num_cpus::get()) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .build()?;
+        let start = std::time::Instant::now();
+        pool.install(|| {
+            sample.par_iter().try_for_each(|(coord, data)| {
+                transform_tile_with_compression(
+                    &(*coord).into(),
+                    std::slice::from_ref(data),
+                    None,
+                    tile_compression,
+                    filter_collection,
+                    transform_options,
+                    None,
+                )
+                .map(drop)
+            })
+        })?;
+        let elapsed = start.elapsed();
+        println!(
+            "Autotune: {} workers transformed {} tiles in {:?}",
+            workers,
+            sample.len(),
+            elapsed
+        );
+        timings.push((workers, elapsed));
+    }
+    Ok(fastest_concurrency(&timings))
+}
+
+async fn spawn_pipeline(
+    pmtiles_paths: &[PathBuf],
+    tile_compression: pmtiles::Compression,
+    filter_collection: Option<CompiledFilterCollection>,
+    transform_options: TransformOptions,
+    process_options: &ProcessOptions,
+) -> Result<TilePipeline> {
+    let concurrency_limit = process_options.concurrency.unwrap_or_else(num_cpus::get);
+    if process_options.unsorted
+        && (pmtiles_paths.len() > 1
+            || process_options.max_zoom_overzoom.is_some()
+            || process_options.batch_by_zoom)
+    {
+        return Err(anyhow!(
+            "unsorted processing needs a single input, without overzooming or batching by zoom"
+        ));
+    }
+
+    // Every output coordinate, along with the inputs that contain it. Under `flip_y`
+    // inputs are read at the flipped coordinate.
+    let flip = process_options.flip_y;
+    let mut sources = BTreeMap::<TileId, Vec<usize>>::new();
+    // Under `unsorted`, the (single) input's tiles in directory order instead
+    let mut enumerated = Vec::new();
+    let mut readers = Vec::with_capacity(pmtiles_paths.len());
+    // Whether each input's stored tiles can be copied as-is: they must already be
+    // in the output compression
+    let mut reusable_inputs = Vec::with_capacity(pmtiles_paths.len());
+    for (index, pmtiles_path) in pmtiles_paths.iter().enumerate() {
+        let in_pmt = Arc::new(
+            AsyncPmTilesReader::new_with_path(pmtiles_path)
+                .await
+                .with_context(|| {
+                    format!("failed to open input PMTiles {}", pmtiles_path.display())
+                })?,
+        );
+
+        let entries = in_pmt.clone().entries().try_collect::<Vec<_>>().await?;
+        let coords = entries
+            .iter()
+            .flat_map(|e| e.iter_coords())
+            .collect::<Vec<_>>();
+
+        let header = in_pmt.get_header();
+        check_zoom_range(
+            &coords,
+            header.min_zoom,
+            header.max_zoom,
+            process_options.strict,
+        )?;
+
+        let stored_compression = process_options
+            .input_compression
+            .unwrap_or(header.tile_compression);
+        reusable_inputs.push(stored_compression == tile_compression);
+
+        for coord in coords {
+            let coord = if flip { flip_y(coord) } else { coord };
+            if process_options.unsorted {
+                enumerated.push((coord, vec![index]));
+            } else {
+                sources.entry(coord).or_default().push(index);
+            }
+        }
+        readers.push(in_pmt);
+    }
+    let overzoom_from = process_options
+        .max_zoom_overzoom
+        .and_then(|zoom| overzoom_sources(&mut sources, zoom));
+    let mut sources = if process_options.unsorted {
+        enumerated
+    } else {
+        sources.into_iter().collect::<Vec<_>>()
+    };
+    let input_count = sources.len();
+    if let Some(path) = &process_options.tiles_file {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read tiles file {}", path.display()))?;
+        let tiles = parse_tile_list(&text)
+            .with_context(|| format!("invalid tiles file {}", path.display()))?;
+        let missing = restrict_to_tiles(&mut sources, &tiles);
+        if !missing.is_empty() {
+            let examples = missing
+                .iter()
+                .take(5)
+                .map(format_tile_coord)
+                .collect::<Vec<_>>()
+                .join(", ");
+            eprintln!(
+                "⚠️ {} listed tiles are not in the input and were skipped (e.g. {})",
+                missing.len(),
+                examples
+            );
+        }
+    }
+    if let Some(bbox) = &process_options.bbox {
+        // Before any tile is read, so tiles outside the rectangle cost no IO
+        restrict_to_bbox(&mut sources, bbox);
+    }
+    let run_count = sources.len();
+    let skipped = input_count - run_count;
+    if let Some((index, count)) = process_options.shard {
+        restrict_to_shard(&mut sources, index, count);
+    }
+    let readers = Arc::new(readers);
+    let reusable_inputs = Arc::new(reusable_inputs);
+    let filter_collection = Arc::new(filter_collection);
+    let transform_options = Arc::new(transform_options);
+    let coords_count = sources.len();
+
+    let (in_tx, in_rx) = flume::bounded::<ReadTile>(QUEUE_CAPACITY);
 
     let mut tasks = JoinSet::new();
 
+    let events = process_options
+        .events
+        .as_deref()
+        .map(|path| events::spawn_writer(path, &mut tasks))
+        .transpose()?;
+    let removed_tx = process_options
+        .removed_output
+        .as_deref()
+        .map(|path| report::spawn_removed_writer(path, process_options.removed_limit, &mut tasks))
+        .transpose()?;
+
+    // Under `batch_by_zoom`, each zoom level is only queued once the previous one has
+    // been written, so at most one level's tiles are in flight
+    let zoom_batches = if process_options.batch_by_zoom {
+        zoom_batch_sizes(&sources)
+    } else {
+        Vec::new()
+    };
+    let (batch_done, batch_done_rx) = if process_options.batch_by_zoom {
+        let (tx, rx) = flume::unbounded::<()>();
+        (Some(tx), Some(rx))
+    } else {
+        (None, None)
+    };
+
     // the async side of processing
-    let (coords_tx, coords_rx) = flume::unbounded::<(usize, TileId)>();
+    let (coords_tx, coords_rx) = flume::unbounded::<(usize, TileId, Vec<usize>)>();
     tasks.spawn(async move {
-        for (i, coord) in coords.into_iter().enumerate() {
-            coords_tx.send((i, coord)).unwrap();
+        let mut zoom = None;
+        for (i, (coord, inputs)) in sources.into_iter().enumerate() {
+            let z = TileCoord::from(coord).z();
+            if let Some(batch_done_rx) = &batch_done_rx
+                && zoom.is_some_and(|zoom| zoom != z)
+                && batch_done_rx.recv_async().await.is_err()
+            {
+                break; // The writer has stopped
+            }
+            zoom = Some(z);
+            coords_tx.send((i, coord, inputs)).unwrap();
         }
         drop(coords_tx); // Close the sender when done
         Ok::<_, anyhow::Error>(())
     });
+    let input_compression = process_options.input_compression;
+    let digests = Arc::new(ContentDigests::default());
+    let read = ProgressBar::hidden();
+    read.set_length(coords_count as u64);
+    let (passthrough_untouched, no_recompress) = (
+        process_options.passthrough_untouched,
+        process_options.no_recompress,
+    );
     for _ in 0..concurrency_limit {
-        let in_pmt = in_pmt.clone();
+        let readers = readers.clone();
+        let reusable_inputs = reusable_inputs.clone();
+        let filter_collection = filter_collection.clone();
+        let transform_options = transform_options.clone();
         let tx = in_tx.clone();
         let coords_rx = coords_rx.clone();
+        let digests = digests.clone();
+        let read = read.clone();
         tasks.spawn(async move {
-            while let Ok((i, coord)) = coords_rx.recv() {
-                // Because we're enumerating tile coordinates, get_tile_decompress
-                // should never return a None, unless something is really wrong.
-                let data = in_pmt.get_tile_decompressed(coord).await?.unwrap();
-                let item = (i, coord, data.to_vec());
+            while let Ok((i, coord, inputs)) = coords_rx.recv_async().await {
+                // Overzoomed tiles are cut out of their ancestor at the deepest input zoom
+                let parent = overzoom_from
+                    .filter(|&zoom| TileCoord::from(coord).z() > zoom)
+                    .map(|zoom| ancestor(coord, zoom));
+                let read_coord = parent.unwrap_or(coord);
+                let source = if flip { flip_y(read_coord) } else { read_coord };
+                let data = match inputs[..] {
+                    [input]
+                        if parent.is_none()
+                            && reusable_inputs[input]
+                            && passthrough_untouched
+                            && leaves_tile_unchanged(
+                                &coord.into(),
+                                filter_collection.as_ref().as_ref(),
+                                &transform_options,
+                            ) =>
+                    {
+                        let stored = readers[input].get_tile(source).await?.unwrap().to_vec();
+                        digests.add_input(source, &stored);
+                        TileInput::Raw(stored)
+                    }
+                    [input] if parent.is_none() && reusable_inputs[input] && no_recompress => {
+                        // Keep the stored bytes; decompress them here instead of reading twice
+                        let stored = readers[input].get_tile(source).await?.unwrap().to_vec();
+                        let data = decompress_tile(&stored, tile_compression)?;
+                        digests.add_input(source, &data);
+                        TileInput::Decompressed(vec![data], Some(stored))
+                    }
+                    _ => {
+                        let mut data = Vec::with_capacity(inputs.len());
+                        for input in inputs {
+                            let tile =
+                                read_tile(&readers[input], source, input_compression).await?;
+                            digests.add_input(source, &tile);
+                            data.push(match parent {
+                                Some(parent) => overzoom_tile(
+                                    &parent.into(),
+                                    &tile,
+                                    &coord.into(),
+                                    transform_options.default_extent,
+                                )?,
+                                None => tile,
+                            });
+                        }
+                        TileInput::Decompressed(data, None)
+                    }
+                };
+                let item = (i, coord, data);
+                read.inc(1);
 
                 tx.send_async(item).await?;
             }
@@ -73,49 +1033,276 @@ pub async fn process_tiles(
     drop(in_tx); // Close the original sender so in_rx can see EOF
 
     // blocking processing
-    let (out_tx, out_rx) = flume::bounded::<(usize, TileId, Vec<u8>)>(QUEUE_CAPACITY);
+    let (out_tx, out_rx) = flume::bounded::<ProcessedTile>(QUEUE_CAPACITY);
+    let oversized = Arc::new(AtomicUsize::new(0));
+    let oversized_count = oversized.clone();
+    let has_features = Arc::new(AtomicBool::new(false));
+    let found_features = process_options
+        .fail_on_empty_output
+        .then(|| has_features.clone());
+    let (max_tile_bytes, strict) = (process_options.max_tile_bytes, process_options.strict);
+    let output_digests = digests.clone();
+    let transformed = ProgressBar::hidden();
+    transformed.set_length(coords_count as u64);
+    let transformed_count = transformed.clone();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(concurrency_limit)
+        .build()?;
 
     tasks.spawn_blocking(move || {
         // in_rx is the receving end of the tile processing channel
         // out_tx is the sending end of the output channel
-        in_rx.into_iter().par_bridge().try_for_each_with(
-            out_tx,
-            |out_tx, (i, coord, input_data)| {
-                let output_data = transform_tile_with_compression(
-                    &coord.into(),
-                    &input_data,
-                    tile_compression,
-                    filter_collection.as_ref(),
-                )?;
-                out_tx.send((i, coord, output_data))?;
-                Ok::<_, anyhow::Error>(())
-            },
-        )?;
+        pool.install(|| {
+            in_rx.into_iter().par_bridge().try_for_each_with(
+                out_tx,
+                |out_tx, (i, coord, input_data)| {
+                    let coords = coord.into();
+                    let mut removed = removed_tx
+                        .as_ref()
+                        .filter(|tx| tx.has_room())
+                        .map(|_| Vec::new());
+                    let (output_data, stats) = match input_data {
+                        TileInput::Raw(data) => {
+                            output_digests.add_output(ContentDigests::tile_digest(coord, &data));
+                            (data, TransformStats::default())
+                        }
+                        TileInput::Decompressed(input_data, stored) => {
+                            let tile = transform_tile_with_compression(
+                                &coords,
+                                &input_data,
+                                stored,
+                                tile_compression,
+                                filter_collection.as_ref().as_ref(),
+                                &transform_options,
+                                removed.as_mut(),
+                            )
+                            .inspect_err(|e| {
+                                events::emit(events.as_ref(), || {
+                                    Event::new(&coords, Outcome::Error)
+                                        .with_message(format!("{:#}", e))
+                                })
+                            })?;
+                            output_digests.add_output(tile.digest);
+                            (tile.data, tile.stats)
+                        }
+                    };
+                    if check_tile_size(&coords, output_data.len(), max_tile_bytes, strict)? {
+                        oversized_count.fetch_add(1, Ordering::Relaxed);
+                    }
+                    if let Some(found) = &found_features
+                        && !found.load(Ordering::Relaxed)
+                        && tile_has_features(&output_data, tile_compression)?
+                    {
+                        found.store(true, Ordering::Relaxed);
+                    }
+                    let outcome = if output_data.is_empty() {
+                        Outcome::Skipped
+                    } else {
+                        Outcome::Processed
+                    };
+                    events::emit(events.as_ref(), || Event::new(&coords, outcome));
+                    if let (Some(tx), Some(removed)) = (&removed_tx, removed)
+                        && !removed.is_empty()
+                    {
+                        tx.send(removed)?;
+                    }
+                    transformed_count.inc(1);
+                    out_tx.send((i, coord, output_data, stats))?;
+                    Ok::<_, anyhow::Error>(())
+                },
+            )
+        })?;
         // The out_tx is automatically dropped when try_for_each_with completes
         Ok::<_, anyhow::Error>(())
     });
 
+    Ok(TilePipeline {
+        tasks,
+        out_rx,
+        coords_count,
+        run_count,
+        skipped,
+        oversized,
+        has_features,
+        digests,
+        zoom_batches,
+        batch_done,
+        read,
+        transformed,
+    })
+}
+
+/// Transform every tile of the input archives into `out`. Tiles present in
+/// more than one input are merged before being transformed.
+/// Returns what the run did, which is also printed unless `quiet` is set.
+pub async fn process_tiles(
+    pmtiles_paths: &[PathBuf],
+    mut out: impl TileWriter + 'static,
+    tile_compression: pmtiles::Compression,
+    filter_collection: Option<CompiledFilterCollection>,
+    transform_options: TransformOptions,
+    mut process_options: ProcessOptions,
+) -> Result<RunSummary> {
+    // These report on, or reorder, the whole run; a shard only sees its own tiles
+    if process_options.shard.is_some()
+        && (process_options.events.is_some()
+            || process_options.removed_output.is_some()
+            || process_options.checksum_manifest.is_some()
+            || process_options.fail_on_empty_output
+            || process_options.autotune.is_some()
+            || process_options.max_zoom_overzoom.is_some()
+            || process_options.flip_y
+            || process_options.unsorted
+            || process_options.reorder_window.is_some())
+    {
+        return Err(WranglerError::Config(anyhow!(
+            "sharded processing can't write events, removed features or a checksum manifest, \
+             fail on empty output, autotune, overzoom, flip y, or process unsorted or with a \
+             reorder window"
+        ))
+        .into());
+    }
+    if let Some(sample_size) = process_options.autotune
+        && process_options.concurrency.is_none()
+    {
+        let workers = autotune_concurrency(
+            &pmtiles_paths[0],
+            tile_compression,
+            filter_collection.as_ref(),
+            &transform_options,
+            process_options.input_compression,
+            sample_size,
+        )
+        .await?;
+        println!("Autotune chose {} workers", workers);
+        process_options.concurrency = Some(workers);
+    }
+    let TilePipeline {
+        mut tasks,
+        out_rx,
+        coords_count,
+        run_count,
+        skipped,
+        oversized,
+        has_features,
+        digests,
+        zoom_batches,
+        batch_done,
+        read,
+        transformed,
+    } = spawn_pipeline(
+        pmtiles_paths,
+        tile_compression,
+        filter_collection,
+        transform_options,
+        &process_options,
+    )
+    .await?;
+
+    if !process_options.quiet {
+        println!("Found {} tiles in the input archive", coords_count);
+    }
+
+    let reorder_window = process_options.reorder_window;
+    let fast = process_options.fast;
+    let progress = process_options.progress.clone();
+    let detailed_progress = process_options.detailed_progress;
+    let quiet = process_options.quiet;
+    let manifest = process_options.checksum_manifest.clone();
+    let (stats_tx, stats_rx) = flume::bounded(1);
+
     tasks.spawn_blocking(move || {
-        let bar = ProgressBar::new(coords_count as u64);
+        let bar = if progress.is_some() {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(coords_count as u64)
+        };
         bar.set_style(ProgressStyle::with_template(
             "[{msg}] {wide_bar} {pos:>7}/{len:7} {elapsed}/{duration} {per_sec:7}",
         )?);
-        let mut next = 0usize;
-        let mut buf = BTreeMap::new();
-        while let Ok((i, coord, res)) = out_rx.recv() {
+        let stages = MultiProgress::new();
+        if detailed_progress && progress.is_none() {
+            let style = ProgressStyle::with_template(
+                "{prefix:>9} {wide_bar} {pos:>7}/{len:7} {per_sec:7}",
+            )?;
+            for (stage_bar, stage) in [
+                (&read, "read"),
+                (&transformed, "transform"),
+                (&bar, "write"),
+            ] {
+                stage_bar.set_style(style.clone());
+                stage_bar.set_prefix(stage);
+                stages.add(stage_bar.clone());
+            }
+        }
+        let mut reorder = ReorderBuffer::with_window(reorder_window);
+        let mut processed = 0;
+        let mut hashes = Vec::new();
+        let mut write = |coord: TileId, data: &[u8]| -> Result<()> {
+            out.write_tile(coord.into(), data)?;
+            // The writer skips empty tiles, so they don't get an entry either
+            if manifest.is_some() && !data.is_empty() {
+                hashes.push((coord, XxHash64::oneshot(0, data)));
+            }
+            bar.inc(1);
+            processed += 1;
+            if let Some(progress) = &progress {
+                progress(ProgressEvent {
+                    processed,
+                    read: read.position() as usize,
+                    transformed: transformed.position() as usize,
+                    total: run_count,
+                    coord: coord.into(),
+                });
+            }
+            Ok(())
+        };
+        let mut zoom_batches = zoom_batches.into_iter();
+        let mut batch_end = zoom_batches.next();
+        let mut received = 0;
+        let mut stats = TransformStats::default();
+        while let Ok((i, coord, res, tile_stats)) = out_rx.recv() {
             bar.set_message(format_tile_coord(&coord.into()));
-            buf.insert(i, (coord, res));
+            received += 1;
+            stats.add(&tile_stats);
+            if fast {
+                write(coord, &res)?;
+            } else {
+                reorder.insert(i, coord, res);
+                while let Some((coord, new_data)) = reorder.pop_ready() {
+                    write(coord, &new_data)?;
+                }
+            }
 
-            while let Some(v) = buf.remove(&next) {
-                let (coord, new_data) = v;
-                out_pmt.add_raw_tile(coord.into(), &new_data)?;
-                bar.inc(1);
-                next += 1;
+            if batch_end == Some(received) {
+                // Every tile of the zoom level has arrived, so none are held back
+                debug_assert!(reorder.is_empty());
+                batch_end = zoom_batches.next().map(|size| received + size);
+                if let Some(batch_done) = &batch_done {
+                    // The pipeline may already be done queueing
+                    let _ = batch_done.send(());
+                }
             }
         }
         bar.finish_and_clear();
-        println!("Finished writing tiles, finalizing archive...");
-        out_pmt.finalize()?;
+        read.finish_and_clear();
+        transformed.finish_and_clear();
+        if received < coords_count {
+            // A worker failed, and its own task reports the error
+            return Ok(());
+        }
+        if !quiet {
+            println!("Finished writing tiles, finalizing output...");
+        }
+        out.finish()?;
+        // Only created now, so a failed run doesn't leave a manifest behind
+        if let Some(path) = manifest {
+            let file = std::fs::File::create(&path).with_context(|| {
+                format!("failed to create checksum manifest {}", path.display())
+            })?;
+            report::write_checksum_manifest(file, hashes)?;
+        }
+        stats_tx.send(stats)?;
 
         Ok::<_, anyhow::Error>(())
     });
@@ -123,18 +1310,158 @@ pub async fn process_tiles(
     while let Some(res) = tasks.join_next().await {
         res??;
     }
-    println!("All done.");
+    if process_options.fail_on_empty_output && !has_features.load(Ordering::Relaxed) {
+        return Err(WranglerError::EmptyOutput.into());
+    }
+    let summary = RunSummary {
+        tiles: coords_count,
+        skipped,
+        oversized: oversized.load(Ordering::Relaxed),
+        stats: stats_rx.recv()?,
+        input_digest: digests.input.load(Ordering::Relaxed),
+        output_digest: digests.output.load(Ordering::Relaxed),
+    };
+    if !process_options.quiet {
+        summary.print(process_options.max_tile_bytes);
+    }
 
-    Ok(())
+    Ok(summary)
+}
+
+/// Read and transform every tile of a PMTiles archive without writing an output archive.
+///
+/// Tiles are yielded with the archive's tile compression applied, exactly as they would be
+/// written by [`crate::run`]. With `ordered` set, tiles are yielded in sorted coordinate order;
+/// otherwise they are yielded as soon as they are transformed.
+pub async fn transformed_tiles(
+    pmtiles_path: &Path,
+    filter_collection: Option<CompiledFilterCollection>,
+    transform_options: TransformOptions,
+    ordered: bool,
+) -> Result<impl Stream<Item = Result<(TileCoord, Vec<u8>), WranglerError>>, WranglerError> {
+    let tile_compression = AsyncPmTilesReader::new_with_path(pmtiles_path)
+        .await
+        .with_context(|| "failed to open input PMTiles")?
+        .get_header()
+        .tile_compression;
+    let pipeline = spawn_pipeline(
+        &[pmtiles_path.to_path_buf()],
+        tile_compression,
+        filter_collection,
+        transform_options,
+        &ProcessOptions::default(),
+    )
+    .await?;
+
+    let state = (pipeline, ReorderBuffer::new(), false);
+    Ok(stream::unfold(
+        state,
+        move |(mut pipeline, mut reorder, done)| async move {
+            if done {
+                return None;
+            }
+            loop {
+                if let Some((coord, data)) = reorder.pop_ready() {
+                    return Some((Ok((coord.into(), data)), (pipeline, reorder, false)));
+                }
+                match pipeline.out_rx.recv_async().await {
+                    // Empty tiles are not written to archives, so don't yield them either
+                    Ok((i, _, data, _)) if data.is_empty() => reorder.skip(i),
+                    Ok((_, coord, data, _)) if !ordered => {
+                        return Some((Ok((coord.into(), data)), (pipeline, reorder, false)));
+                    }
+                    Ok((i, coord, data, _)) => reorder.insert(i, coord, data),
+                    Err(_) => {
+                        // The pipeline is exhausted; surface any error from its tasks
+                        while let Some(res) = pipeline.tasks.join_next().await {
+                            if let Err(e) = res.map_err(anyhow::Error::from).and_then(|r| r) {
+                                return Some((Err(e.into()), (pipeline, reorder, true)));
+                            }
+                        }
+                        return None;
+                    }
+                }
+            }
+        },
+    ))
+}
+
+/// Decompress raw tile bytes with an explicitly chosen codec
+fn decompress_tile(data: &[u8], compression: pmtiles::Compression) -> Result<Vec<u8>> {
+    match compression {
+        pmtiles::Compression::Gzip => {
+            let mut decompressed = Vec::new();
+            std::io::Read::read_to_end(&mut GzDecoder::new(data), &mut decompressed)?;
+            Ok(decompressed)
+        }
+        pmtiles::Compression::None => Ok(data.to_vec()),
+        _ => Err(WranglerError::UnsupportedCompression(compression).into()),
+    }
 }
 
+/// Whether an output tile, compressed with `compression`, has at least one feature
+fn tile_has_features(data: &[u8], compression: pmtiles::Compression) -> Result<bool> {
+    if data.is_empty() {
+        return Ok(false);
+    }
+    let tile = Tile::decode(decompress_tile(data, compression)?.as_slice())?;
+    Ok(tile.layers.iter().any(|layer| !layer.features.is_empty()))
+}
+
+/// A tile transformed by [`transform_tile_with_compression`]
+struct TransformedTile {
+    /// The tile with the archive's tile compression applied
+    data: Vec<u8>,
+    /// [`ContentDigests::tile_digest`] of the tile before compression, so recompressing
+    /// alone doesn't count as a change
+    digest: u64,
+    stats: TransformStats,
+}
+
+/// Merge the copies of a tile read from each input, then transform and compress it.
+/// If a single input is left unchanged, its `stored` bytes are returned instead.
 fn transform_tile_with_compression(
     coords: &TileCoord,
-    data: &[u8],
+    inputs: &[Vec<u8>],
+    stored: Option<Vec<u8>>,
     tile_compression: pmtiles::Compression,
     filter_collection: Option<&CompiledFilterCollection>,
-) -> Result<Vec<u8>> {
-    let bytes = transform_tile(coords, data, filter_collection)?;
+    transform_options: &TransformOptions,
+    removed: Option<&mut Vec<geojson::Feature>>,
+) -> Result<TransformedTile> {
+    let merged;
+    let data = match inputs {
+        [data] => data,
+        _ => {
+            merged = merge_tiles(coords, inputs, transform_options.default_extent)?;
+            &merged
+        }
+    };
+    let (bytes, stats) = match removed {
+        Some(removed) => transform_tile_reporting_removed(
+            coords,
+            data,
+            filter_collection,
+            transform_options,
+            removed,
+        )?,
+        None => transform_tile(coords, data, filter_collection, transform_options)?,
+    };
+    let digest = ContentDigests::tile_digest((*coords).into(), &bytes);
+    let tile = |data| TransformedTile {
+        data,
+        digest,
+        stats,
+    };
+    if bytes.is_empty() {
+        // Empty tiles are skipped by the writer; don't wrap them in a gzip stream
+        return Ok(tile(bytes));
+    }
+    if let Some(stored) = stored
+        && bytes == *data
+    {
+        return Ok(tile(stored));
+    }
     let new_data = match tile_compression {
         pmtiles::Compression::Gzip => {
             let mut compressed = Vec::new();
@@ -146,10 +1473,266 @@ fn transform_tile_with_compression(
             compressed
         }
         pmtiles::Compression::None => bytes,
-        _ => {
-            panic!("Unsupported tile compression: {:?}", tile_compression);
-        }
+        _ => return Err(WranglerError::UnsupportedCompression(tile_compression).into()),
     };
 
-    Ok(new_data)
+    Ok(tile(new_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile_id(z: u8, x: u32, y: u32) -> TileId {
+        TileCoord::new(z, x, y).unwrap().into()
+    }
+
+    /// Feed positions into a reorder buffer and collect the tile ids it releases
+    fn drain_reorder(window: Option<usize>, arrivals: &[usize]) -> Vec<u64> {
+        let mut reorder = ReorderBuffer::with_window(window);
+        let mut released = Vec::new();
+        for &i in arrivals {
+            reorder.insert(i, TileId::new(i as u64).unwrap(), vec![1]);
+            while let Some((id, _)) = reorder.pop_ready() {
+                released.push(id.value());
+            }
+        }
+        released
+    }
+
+    #[test]
+    fn test_reorder_buffer_unbounded_keeps_order() {
+        let released = drain_reorder(None, &[3, 1, 0, 4, 2]);
+        assert_eq!(released, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_reorder_buffer_window_releases_each_tile_once() {
+        let arrivals = [3, 1, 5, 0, 4, 2, 7, 6];
+        for window in [0, 1, 2, 8] {
+            let mut released = drain_reorder(Some(window), &arrivals);
+            released.sort_unstable();
+            assert_eq!(released, (0..8).collect::<Vec<_>>(), "window {}", window);
+        }
+        // A zero window writes tiles as they arrive
+        assert_eq!(
+            drain_reorder(Some(0), &arrivals),
+            vec![3, 1, 5, 0, 4, 2, 7, 6]
+        );
+    }
+
+    #[test]
+    fn test_reorder_buffer_is_empty_after_each_batch() {
+        // Two batches of positions 0..4 and 4..7, each arriving out of order
+        for window in [None, Some(0), Some(2)] {
+            let mut reorder = ReorderBuffer::with_window(window);
+            for batch in [[2, 0, 3, 1].as_slice(), [6, 4, 5].as_slice()] {
+                for &i in batch {
+                    reorder.insert(i, TileId::new(i as u64).unwrap(), vec![1]);
+                    while reorder.pop_ready().is_some() {}
+                }
+                assert!(reorder.is_empty(), "window {:?}", window);
+            }
+        }
+    }
+
+    #[test]
+    fn test_autotune_candidates() {
+        assert_eq!(autotune_candidates(1), vec![1]);
+        assert_eq!(autotune_candidates(6), vec![1, 2, 4, 6]);
+        assert_eq!(autotune_candidates(8), vec![1, 2, 4, 8]);
+    }
+
+    #[test]
+    fn test_fastest_concurrency() {
+        let ms = std::time::Duration::from_millis;
+        assert_eq!(
+            fastest_concurrency(&[(1, ms(90)), (2, ms(50)), (4, ms(60))]),
+            2
+        );
+        // Ties go to fewer workers
+        assert_eq!(fastest_concurrency(&[(1, ms(50)), (2, ms(50))]), 1);
+    }
+
+    #[tokio::test]
+    async fn test_autotune_concurrency_picks_a_candidate() {
+        let path = Path::new("tests/fixtures/input.pmtiles");
+        let header = AsyncPmTilesReader::new_with_path(path)
+            .await
+            .unwrap()
+            .get_header()
+            .tile_compression;
+        let workers =
+            autotune_concurrency(path, header, None, &TransformOptions::default(), None, 16)
+                .await
+                .unwrap();
+        assert!(autotune_candidates(num_cpus::get()).contains(&workers));
+    }
+
+    #[test]
+    fn test_zoom_batch_sizes() {
+        let sources = Vec::from([
+            (tile_id(0, 0, 0), vec![0]),
+            (tile_id(2, 1, 1), vec![0]),
+            (tile_id(2, 3, 0), vec![0]),
+            (tile_id(5, 3, 4), vec![0]),
+        ]);
+        assert_eq!(zoom_batch_sizes(&sources), vec![1, 2, 1]);
+        assert!(zoom_batch_sizes(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_decompress_tile() {
+        let data = b"not really a tile".to_vec();
+        let mut gzipped = Vec::new();
+        {
+            let mut encoder = GzEncoder::new(&mut gzipped, Compression::default());
+            std::io::Write::write_all(&mut encoder, &data).unwrap();
+            encoder.finish().unwrap();
+        }
+
+        assert_eq!(
+            decompress_tile(&gzipped, pmtiles::Compression::Gzip).unwrap(),
+            data
+        );
+        assert_eq!(
+            decompress_tile(&data, pmtiles::Compression::None).unwrap(),
+            data
+        );
+        assert!(decompress_tile(&data, pmtiles::Compression::Gzip).is_err());
+        assert!(decompress_tile(&data, pmtiles::Compression::Zstd).is_err());
+    }
+
+    #[test]
+    fn test_flip_y() {
+        assert_eq!(flip_y(tile_id(3, 5, 0)), tile_id(3, 5, 7));
+        assert_eq!(flip_y(tile_id(3, 5, 7)), tile_id(3, 5, 0));
+        assert_eq!(flip_y(tile_id(10, 1, 0)), tile_id(10, 1, 1023));
+        assert_eq!(flip_y(tile_id(0, 0, 0)), tile_id(0, 0, 0));
+        assert_eq!(flip_y(flip_y(tile_id(6, 12, 20))), tile_id(6, 12, 20));
+    }
+
+    #[test]
+    fn test_parse_tile_list() {
+        let tiles = parse_tile_list("3/1/2\n\n  0/0/0  \n").unwrap();
+        assert_eq!(
+            tiles.into_iter().collect::<Vec<_>>(),
+            vec![tile_id(0, 0, 0), tile_id(3, 1, 2)]
+        );
+
+        for bad in ["3/1", "3/1/2/4", "a/b/c", "1/2/0"] {
+            let err = parse_tile_list(&format!("0/0/0\n{}", bad)).unwrap_err();
+            assert!(err.to_string().starts_with("line 2:"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_restrict_to_tiles() {
+        let mut sources = Vec::from([
+            (tile_id(1, 0, 0), vec![0]),
+            (tile_id(1, 1, 0), vec![0]),
+            (tile_id(1, 1, 1), vec![0]),
+        ]);
+        let tiles = BTreeSet::from([tile_id(1, 0, 0), tile_id(1, 1, 1), tile_id(4, 2, 3)]);
+
+        let missing = restrict_to_tiles(&mut sources, &tiles);
+        assert_eq!(
+            sources
+                .into_iter()
+                .map(|(coord, _)| coord)
+                .collect::<Vec<_>>(),
+            vec![tile_id(1, 0, 0), tile_id(1, 1, 1)]
+        );
+        assert_eq!(missing, vec![TileCoord::new(4, 2, 3).unwrap()]);
+    }
+
+    #[test]
+    fn test_restrict_to_bbox() {
+        let mut sources = Vec::from([
+            (tile_id(0, 0, 0), vec![0]),
+            (tile_id(1, 0, 0), vec![0]),
+            (tile_id(1, 1, 0), vec![0]),
+            (tile_id(1, 0, 1), vec![0]),
+            (tile_id(1, 1, 1), vec![0]),
+        ]);
+        // Only touches the western tiles at the prime meridian, and the southern ones
+        // lie below the equator
+        restrict_to_bbox(&mut sources, &[0.0, 10.0, 20.0, 20.0]);
+        assert_eq!(
+            sources
+                .into_iter()
+                .map(|(coord, _)| coord)
+                .collect::<Vec<_>>(),
+            vec![tile_id(0, 0, 0), tile_id(1, 1, 0)]
+        );
+    }
+
+    #[test]
+    fn test_restrict_to_shard() {
+        let sources = (0..7)
+            .map(|x| (tile_id(3, x, 0), vec![0]))
+            .collect::<Vec<_>>();
+        let shards = (0..3)
+            .map(|index| {
+                let mut shard = sources.clone();
+                restrict_to_shard(&mut shard, index, 3);
+                shard
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            shards.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![2, 2, 3]
+        );
+        assert_eq!(shards.concat(), sources);
+    }
+
+    #[test]
+    fn test_overzoom_sources() {
+        let mut sources =
+            BTreeMap::from([(tile_id(0, 0, 0), vec![0]), (tile_id(1, 1, 0), vec![1])]);
+
+        assert_eq!(overzoom_sources(&mut sources, 1), None);
+        assert_eq!(sources.len(), 2);
+
+        assert_eq!(overzoom_sources(&mut sources, 3), Some(1));
+        // 1/1/0 gains 4 children at z2 and 16 grandchildren at z3
+        assert_eq!(sources.len(), 2 + 4 + 16);
+        assert_eq!(sources[&tile_id(3, 7, 3)], vec![1]);
+        assert!(!sources.contains_key(&tile_id(2, 0, 0)));
+        assert_eq!(ancestor(tile_id(3, 7, 3), 1), tile_id(1, 1, 0));
+    }
+
+    #[test]
+    fn test_check_tile_size() {
+        let coords = TileCoord::new(4, 2, 3).unwrap();
+
+        assert!(!check_tile_size(&coords, 600_000, None, true).unwrap());
+        assert!(!check_tile_size(&coords, 500_000, Some(500_000), true).unwrap());
+        assert!(check_tile_size(&coords, 600_000, Some(500_000), false).unwrap());
+        let err = check_tile_size(&coords, 600_000, Some(500_000), true).unwrap_err();
+        assert!(err.to_string().contains("4/2/3"));
+    }
+
+    #[test]
+    fn test_out_of_range_zooms() {
+        let coords = vec![tile_id(0, 0, 0), tile_id(2, 1, 1), tile_id(5, 3, 4)];
+
+        assert!(out_of_range_zooms(&coords, 0, 5).is_empty());
+
+        let invalid = out_of_range_zooms(&coords, 0, 2);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(format_tile_coord(&invalid[0]), "5/3/4");
+
+        let invalid = out_of_range_zooms(&coords, 1, 5);
+        assert_eq!(format_tile_coord(&invalid[0]), "0/0/0");
+    }
+
+    #[test]
+    fn test_check_zoom_range_strict() {
+        let coords = vec![tile_id(0, 0, 0), tile_id(5, 3, 4)];
+
+        assert!(check_zoom_range(&coords, 0, 2, false).is_ok());
+        let err = check_zoom_range(&coords, 0, 2, true).unwrap_err();
+        assert!(err.to_string().contains("5/3/4"));
+    }
 }
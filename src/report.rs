@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use pmtiles::{TileCoord, TileId};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write as _},
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use tokio::task::JoinSet;
+
+/// Sending side of the removed-features channel. Cheap to clone into workers.
+#[derive(Clone)]
+pub struct RemovedSender {
+    tx: flume::Sender<Vec<geojson::Feature>>,
+    /// Features the report still has room for, shared between clones
+    room: Arc<AtomicUsize>,
+}
+
+impl RemovedSender {
+    /// Whether the report has room left, so removed features are worth describing
+    pub fn has_room(&self) -> bool {
+        self.room.load(Ordering::Relaxed) > 0
+    }
+
+    /// Send a tile's removed features, taking up their room in the report
+    pub fn send(&self, removed: Vec<geojson::Feature>) -> Result<()> {
+        let _ = self
+            .room
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |room| {
+                Some(room.saturating_sub(removed.len()))
+            });
+        self.tx.send(removed)?;
+        Ok(())
+    }
+}
+
+/// Spawn a task collecting removed features into a GeoJSON FeatureCollection at `path`.
+/// At most `limit` features are kept, and workers stop describing them once the limit
+/// is reached; the file is written once every sender has been dropped.
+pub fn spawn_removed_writer(
+    path: &Path,
+    limit: usize,
+    tasks: &mut JoinSet<Result<()>>,
+) -> Result<RemovedSender> {
+    let file = File::create(path)
+        .with_context(|| format!("failed to create removed features file {}", path.display()))?;
+    let (tx, rx) = flume::unbounded::<Vec<geojson::Feature>>();
+    tasks.spawn_blocking(move || {
+        let mut features = Vec::new();
+        for batch in rx.iter() {
+            // Tiles transformed at the same time can overshoot the room left
+            let room = limit - features.len();
+            features.extend(batch.into_iter().take(room));
+        }
+        if limit > 0 && features.len() == limit {
+            eprintln!(
+                "⚠️ The removed features report reached its limit of {} features; any more \
+                 were left out",
+                limit
+            );
+        }
+        let collection = geojson::FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        };
+        serde_json::to_writer(BufWriter::new(file), &collection)?;
+        Ok(())
+    });
+    Ok(RemovedSender {
+        tx,
+        room: Arc::new(AtomicUsize::new(limit)),
+    })
+}
+
+/// Write a CSV manifest with the xxHash64 of each written tile's stored bytes, one
+/// `z/x/y,hash` row per tile in tile id order, for comparing runs tile by tile
+pub fn write_checksum_manifest(file: File, mut hashes: Vec<(TileId, u64)>) -> Result<()> {
+    hashes.sort_unstable_by_key(|&(id, _)| id);
+    let mut writer = BufWriter::new(file);
+    writeln!(writer, "tile,xxhash64")?;
+    for (id, hash) in hashes {
+        let coord = TileCoord::from(id);
+        writeln!(
+            writer,
+            "{}/{}/{},{:016x}",
+            coord.z(),
+            coord.x(),
+            coord.y(),
+            hash
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// One line per layer with its encoded bytes and share of all layers' bytes, largest
+/// first, or nothing if no tile was re-encoded
+pub fn layer_size_lines(layer_bytes: &BTreeMap<String, u64>) -> Vec<String> {
+    let total = layer_bytes.values().sum::<u64>();
+    if total == 0 {
+        return Vec::new();
+    }
+    let mut layers = layer_bytes.iter().collect::<Vec<_>>();
+    // Stable, so equal sizes stay in name order
+    layers.sort_by_key(|&(_, bytes)| std::cmp::Reverse(*bytes));
+    layers
+        .into_iter()
+        .map(|(name, bytes)| {
+            format!(
+                "{}: {} bytes ({:.1}%)",
+                name,
+                bytes,
+                *bytes as f64 / total as f64 * 100.0
+            )
+        })
+        .collect()
+}
@@ -1,26 +1,428 @@
+use crate::error::WranglerError;
 use crate::filtering::EvaluationContext;
 use crate::filtering::data::CompiledFilterCollection;
+use crate::filtering::executor::{ExpressionExecutor, rects_overlap};
+use crate::filtering::expression_compiler::CompiledExpression;
+use crate::metadata::{LayerFields, infer_fields, merge_fields};
 use crate::processing::format_tile_coord;
-use anyhow::{Context, Result};
-use geo::{BoundingRect, Coord, Intersects, MapCoords};
-use geo_types::{Geometry, LineString, Polygon};
-use geozero::ToGeo;
+use anyhow::{Result, ensure};
+use geo::{BooleanOps, BoundingRect, Coord, CoordsIter, Intersects, MapCoords};
+use geo_types::{
+    Geometry, LineString, MultiLineString, MultiPoint, MultiPolygon, Point, Polygon, Rect,
+};
 use geozero::mvt::{
     Tile,
-    tile::{Feature, Value},
+    tile::{Feature, Layer, Value},
 };
+use geozero::{ToGeo, ToMvt};
 use pmtiles::TileCoord;
 use prost::Message as _;
-use std::collections::HashMap;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+/// Options controlling how tiles are rewritten, independent of the filter collection.
+/// Deserializes from the `--transform-config` JSON file; missing fields take their defaults.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransformOptions {
+    /// Rescale every layer's geometry to this extent on output
+    pub target_extent: Option<u32>,
+    /// Rescale these layers' geometry to their own extent instead, by layer name
+    pub layer_extent_overrides: HashMap<String, u32>,
+    /// Extent of layers that don't declare one
+    pub default_extent: u32,
+    /// Keep each feature with this probability (0.0 to 1.0)
+    pub sample_fraction: Option<f64>,
+    /// Seed for feature sampling
+    pub seed: u64,
+    /// Evaluate filter expressions with MapLibre semantics
+    pub maplibre_compat: bool,
+    /// Remove layers that have no features left after processing
+    pub drop_empty_layers: bool,
+    /// Cut this `[min_lon, min_lat, max_lon, max_lat]` rectangle out of every tile
+    pub exclude_bbox: Option<[f64; 4]>,
+    /// Rewrite tag keys, in order, before anything else looks at them
+    pub normalize_keys: Vec<KeyNormalization>,
+    /// Rewrite bool-like string values of these keys to booleans
+    pub boolify_keys: Vec<String>,
+    /// Pull numeric values of a tag that fall outside a range to its nearest bound
+    pub clamp_values: Vec<ClampValues>,
+    /// Constant tags added to every surviving feature
+    pub add_tags: Vec<AddTag>,
+    /// Keep only the features with the highest values of a numeric tag
+    pub keep_top_percent: Vec<KeepTopPercent>,
+    /// Remove consecutive duplicate vertices, dropping features that degenerate
+    pub dedupe_vertices: bool,
+    /// Round coordinates to multiples of this many tile units, dropping features
+    /// that degenerate
+    pub snap_grid: Option<u32>,
+    /// Remove tags whose value is an empty string, or a value with no type set
+    pub strip_empty_values: bool,
+    /// Write each tile's layers in alphabetical order of name
+    pub sort_layers: bool,
+    /// Only process this layer; every other layer is copied through untouched
+    pub filter_layer: Option<String>,
+    /// Drop every layer not in this set
+    #[serde(skip)]
+    pub only_layers: Option<HashSet<String>>,
+    /// Drop every layer whose name matches this pattern, except `filter_layer`
+    #[serde(skip)]
+    pub exclude_layers_regex: Option<Regex>,
+    /// Remove features matching this expression in every layer, wherever they are
+    #[serde(skip)]
+    pub remove_where: Option<CompiledExpression>,
+    /// Keep only tags whose key matches this pattern
+    #[serde(skip)]
+    pub keep_tags_matching: Option<Regex>,
+    /// Remove tags whose key matches this pattern
+    #[serde(skip)]
+    pub drop_tags_matching: Option<Regex>,
+    /// Decode every re-encoded tile and check it holds the intended features and tags
+    #[serde(skip)]
+    pub verify_encoding: bool,
+    /// Record the attribute types of each layer in [`TransformStats::layer_fields`]
+    #[serde(skip)]
+    pub infer_fields: bool,
+}
+
+/// What transforming a tile did besides producing it, added up over a run for the
+/// final report
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransformStats {
+    /// Tags dropped because `normalize_keys` made their key collide with a later tag's
+    pub key_collisions: usize,
+    /// Encoded bytes of each layer, before tile compression
+    pub layer_bytes: BTreeMap<String, u64>,
+    /// Attribute types of each layer, under `infer_fields`
+    pub layer_fields: LayerFields,
+}
+
+impl TransformStats {
+    /// Add another tile's stats to these
+    pub fn add(&mut self, other: &TransformStats) {
+        self.key_collisions += other.key_collisions;
+        for (name, bytes) in &other.layer_bytes {
+            *self.layer_bytes.entry(name.clone()).or_default() += bytes;
+        }
+        merge_fields(&mut self.layer_fields, &other.layer_fields);
+    }
+}
+
+/// A rewrite applied to every tag key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyNormalization {
+    /// Lowercase the key (`Name` becomes `name`)
+    Lowercase,
+    /// Remove leading and trailing whitespace
+    Trim,
+}
+
+/// Apply `normalizations` to `key`, in order
+fn normalize_key(key: &str, normalizations: &[KeyNormalization]) -> String {
+    normalizations
+        .iter()
+        .fold(key.to_string(), |key, normalization| match normalization {
+            KeyNormalization::Lowercase => key.to_lowercase(),
+            KeyNormalization::Trim => key.trim().to_string(),
+        })
+}
+
+/// Extent of layers that don't declare one, per the MVT spec's usual default
+const DEFAULT_EXTENT: u32 = 4096;
+
+/// A constant tag to add to every feature of a layer (`*` for all layers).
+/// Replaces any existing value of the same key.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct AddTag {
+    pub layer: String,
+    pub key: String,
+    pub value: Value,
+}
+
+impl TryFrom<String> for AddTag {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(&s)
+    }
+}
+
+impl AddTag {
+    /// Parse `layer:key=value`. The value is read as JSON if possible (so `1`, `true`
+    /// and `"x"` are typed) and as a plain string otherwise.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (target, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected layer:key=value, got {}", s))?;
+        let (layer, key) = target
+            .split_once(':')
+            .ok_or_else(|| format!("expected layer:key=value, got {}", s))?;
+        if layer.is_empty() || key.is_empty() {
+            return Err(format!("layer and key must not be empty in {}", s));
+        }
+        let value = match serde_json::from_str::<serde_json::Value>(value) {
+            Ok(serde_json::Value::Bool(b)) => Value {
+                bool_value: Some(b),
+                ..Default::default()
+            },
+            Ok(serde_json::Value::Number(n)) => match n.as_i64() {
+                Some(i) => Value {
+                    int_value: Some(i),
+                    ..Default::default()
+                },
+                None => Value {
+                    double_value: n.as_f64(),
+                    ..Default::default()
+                },
+            },
+            Ok(serde_json::Value::String(s)) => Value {
+                string_value: Some(s),
+                ..Default::default()
+            },
+            Ok(other) if !other.is_null() => {
+                return Err(format!("unsupported tag value {}", other));
+            }
+            _ => Value {
+                string_value: Some(value.to_string()),
+                ..Default::default()
+            },
+        };
+        Ok(Self {
+            layer: layer.to_string(),
+            key: key.to_string(),
+            value,
+        })
+    }
+}
+
+/// Keep the top `percent` of a layer's features in each tile (`*` for all layers), ranked
+/// by the numeric value of `key`. Features tied with the last one kept are kept too.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeepTopPercent {
+    pub layer: String,
+    pub key: String,
+    pub percent: f64,
+}
+
+impl TryFrom<String> for KeepTopPercent {
+    type Error = String;
 
-fn project_to_tile(geom: &Geometry<f64>, coords: &TileCoord, extent: u32) -> Geometry<f64> {
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(&s)
+    }
+}
+
+impl KeepTopPercent {
+    /// Parse `layer:key:percent`. The key may itself contain `:`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (target, percent) = s
+            .rsplit_once(':')
+            .ok_or_else(|| format!("expected layer:key:percent, got {}", s))?;
+        let (layer, key) = target
+            .split_once(':')
+            .ok_or_else(|| format!("expected layer:key:percent, got {}", s))?;
+        if layer.is_empty() || key.is_empty() {
+            return Err(format!("layer and key must not be empty in {}", s));
+        }
+        let percent = percent
+            .parse::<f64>()
+            .ok()
+            .filter(|p| *p > 0.0 && *p <= 100.0)
+            .ok_or_else(|| format!("percent must be a number in (0, 100], got {}", percent))?;
+        Ok(Self {
+            layer: layer.to_string(),
+            key: key.to_string(),
+            percent,
+        })
+    }
+
+    /// Which of `features` to keep. Features without a numeric `key` rank lowest.
+    fn select(&self, features: &[Feature], keys: &[String], values: &[Value]) -> Vec<bool> {
+        let key_index = keys.iter().position(|k| *k == self.key);
+        let ranks = features
+            .iter()
+            .map(|feature| {
+                let key_index = key_index? as u32;
+                let kv = feature.tags.chunks_exact(2).find(|kv| kv[0] == key_index)?;
+                numeric_value(&values[kv[1] as usize])
+            })
+            .collect::<Vec<_>>();
+
+        let keep_count = (features.len() as f64 * self.percent / 100.0).ceil() as usize;
+        let mut sorted = ranks.clone();
+        // Descending, with missing values (`None`) last
+        sorted.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        let Some(threshold) = keep_count.checked_sub(1).and_then(|i| sorted.get(i)) else {
+            return vec![true; features.len()];
+        };
+        ranks.iter().map(|rank| rank >= threshold).collect()
+    }
+}
+
+/// Bound the numeric values of `key` in a layer's features (`*` for all layers) to
+/// `min..=max`. Non-numeric values are left alone.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ClampValues {
+    pub layer: String,
+    pub key: String,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl TryFrom<String> for ClampValues {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(&s)
+    }
+}
+
+impl ClampValues {
+    /// Parse `layer:key:min:max`. The key may itself contain `:`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let expected = || format!("expected layer:key:min:max, got {}", s);
+        let (rest, max) = s.rsplit_once(':').ok_or_else(expected)?;
+        let (target, min) = rest.rsplit_once(':').ok_or_else(expected)?;
+        let (layer, key) = target.split_once(':').ok_or_else(expected)?;
+        if layer.is_empty() || key.is_empty() {
+            return Err(format!("layer and key must not be empty in {}", s));
+        }
+        let bound = |b: &str| {
+            b.parse::<f64>()
+                .ok()
+                .filter(|b| b.is_finite())
+                .ok_or_else(|| format!("bounds must be numbers, got {}", b))
+        };
+        let (min, max) = (bound(min)?, bound(max)?);
+        if min > max {
+            return Err(format!("min must not be greater than max in {}", s));
+        }
+        Ok(Self {
+            layer: layer.to_string(),
+            key: key.to_string(),
+            min,
+            max,
+        })
+    }
+
+    /// Whether the rule applies to `key` in `layer`
+    fn applies_to(&self, layer: &str, key: &str) -> bool {
+        (self.layer == "*" || self.layer == layer) && self.key == key
+    }
+
+    /// `value` pulled into range, keeping its type where the bound fits it. `None` if
+    /// the value isn't numeric or is already in range.
+    fn clamp(&self, value: &Value) -> Option<Value> {
+        let number = numeric_value(value)?;
+        let bound = if number < self.min {
+            self.min
+        } else if number > self.max {
+            self.max
+        } else {
+            return None;
+        };
+        let integral = bound.fract() == 0.0;
+        let clamped = if value.float_value.is_some() {
+            Value {
+                float_value: Some(bound as f32),
+                ..Default::default()
+            }
+        } else if value.sint_value.is_some() && integral {
+            Value {
+                sint_value: Some(bound as i64),
+                ..Default::default()
+            }
+        } else if value.uint_value.is_some() && integral && bound >= 0.0 {
+            Value {
+                uint_value: Some(bound as u64),
+                ..Default::default()
+            }
+        } else if value.double_value.is_none() && integral {
+            Value {
+                int_value: Some(bound as i64),
+                ..Default::default()
+            }
+        } else {
+            Value {
+                double_value: Some(bound),
+                ..Default::default()
+            }
+        };
+        Some(clamped)
+    }
+}
+
+/// The value as a number, if it is one
+fn numeric_value(value: &Value) -> Option<f64> {
+    value
+        .double_value
+        .or(value.float_value.map(f64::from))
+        .or(value.int_value.map(|v| v as f64))
+        .or(value.sint_value.map(|v| v as f64))
+        .or(value.uint_value.map(|v| v as f64))
+}
+
+impl TransformOptions {
+    /// Whether `exclude_layers_regex` drops the layer. The layer named by `filter_layer`
+    /// was asked for explicitly, so it is always kept.
+    pub fn excludes_layer(&self, name: &str) -> bool {
+        self.exclude_layers_regex
+            .as_ref()
+            .is_some_and(|regex| regex.is_match(name))
+            && self.filter_layer.as_deref() != Some(name)
+    }
+}
+
+impl Default for TransformOptions {
+    fn default() -> Self {
+        Self {
+            target_extent: None,
+            layer_extent_overrides: HashMap::new(),
+            default_extent: DEFAULT_EXTENT,
+            sample_fraction: None,
+            seed: 0,
+            maplibre_compat: false,
+            drop_empty_layers: true,
+            exclude_bbox: None,
+            normalize_keys: Vec::new(),
+            boolify_keys: Vec::new(),
+            clamp_values: Vec::new(),
+            add_tags: Vec::new(),
+            keep_top_percent: Vec::new(),
+            dedupe_vertices: false,
+            snap_grid: None,
+            strip_empty_values: false,
+            sort_layers: false,
+            filter_layer: None,
+            only_layers: None,
+            exclude_layers_regex: None,
+            remove_where: None,
+            keep_tags_matching: None,
+            drop_tags_matching: None,
+            verify_encoding: false,
+            infer_fields: false,
+        }
+    }
+}
+
+/// Latitude of the top edge of the Web Mercator world (`tile_y_to_lat(0.0, 1.0)`)
+const MAX_MERCATOR_LAT: f64 = 85.051_128_779_806_59;
+
+/// Project a lon/lat geometry into the local coordinates of a tile with the given extent
+/// (Web Mercator, y pointing down). Latitudes beyond ±85.05° are clamped to the edge of
+/// the world, since the projection goes to infinity at the poles.
+pub fn project_to_tile(geom: &Geometry<f64>, coords: &TileCoord, extent: u32) -> Geometry<f64> {
     let n = 2_f64.powi(coords.z() as i32);
     geom.map_coords(|Coord { x, y }| {
         // 1. fractional tile coords
         let x_frac = (x + 180.0) / 360.0 * n;
-        let lat_rad = y.to_radians();
-        let y_frac =
-            (1.0 - (lat_rad.tan() + 1.0 / lat_rad.cos()).ln() / std::f64::consts::PI) / 2.0 * n;
+        let y_frac = lat_to_tile_y(y, n);
         // 2. local tile coords
         let x_local = (x_frac - coords.x() as f64) * extent as f64;
         let y_local = (y_frac - coords.y() as f64) * extent as f64;
@@ -29,27 +431,34 @@ fn project_to_tile(geom: &Geometry<f64>, coords: &TileCoord, extent: u32) -> Geo
 }
 
 fn bbox_intersects_tile(geom: &Geometry<f64>, extent: u32) -> bool {
-    let max = extent as f64;
+    let tile = Rect::new((0.0, 0.0), (extent as f64, extent as f64));
     geom.bounding_rect()
-        .map(|rect| {
-            let min = rect.min();
-            let max_pt = rect.max();
-            // Check for overlap on X and Y axes:
-            // [min.x, max_pt.x] overlaps [0, max]
-            min.x <= max && max_pt.x >= 0.0 &&
-            // [min.y, max_pt.y] overlaps [0, max]
-            min.y <= max && max_pt.y >= 0.0
-        })
+        .map(|rect| rects_overlap(&rect, &tile))
         // If there's no bounding rect (empty geom), treat as “no intersection”
         .unwrap_or(false)
 }
 
-fn tile_y_to_lat(y: f64, n: f64) -> f64 {
+/// Fractional tile row of latitude `lat` at a zoom level with `n` tiles per side.
+/// Latitudes past the edge of the world are clamped to its first or last row.
+fn lat_to_tile_y(lat: f64, n: f64) -> f64 {
+    if lat >= MAX_MERCATOR_LAT {
+        return 0.0;
+    }
+    if lat <= -MAX_MERCATOR_LAT {
+        return n;
+    }
+    let lat_rad = lat.to_radians();
+    (1.0 - (lat_rad.tan() + 1.0 / lat_rad.cos()).ln() / std::f64::consts::PI) / 2.0 * n
+}
+
+/// Latitude of the (fractional) tile row `y` at a zoom level with `n` tiles per side
+pub fn tile_y_to_lat(y: f64, n: f64) -> f64 {
     let radians = std::f64::consts::PI * (1.0 - 2.0 * y / n);
     radians.sinh().atan().to_degrees()
 }
 
-fn tile_bounds(coords: &TileCoord) -> Geometry<f64> {
+/// The area covered by a tile, as a lon/lat polygon
+pub fn tile_bounds(coords: &TileCoord) -> Geometry<f64> {
     let n = 2_f64.powi(coords.z() as i32);
     let x = coords.x() as f64;
     let y = coords.y() as f64;
@@ -70,14 +479,569 @@ fn tile_bounds(coords: &TileCoord) -> Geometry<f64> {
     Geometry::Polygon(Polygon::new(LineString::from(ring), vec![]))
 }
 
+/// Mix a value into a 64-bit hash state (SplitMix64 finalizer)
+fn mix64(state: u64, value: u64) -> u64 {
+    let mut z = (state ^ value).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Decide whether a feature survives sampling.
+/// The decision only depends on the seed, the tile coordinate, and the position of the
+/// feature within the input tile, so the same input and seed always keep the same features.
+fn keep_sampled_feature(
+    seed: u64,
+    coords: &TileCoord,
+    layer_index: usize,
+    feature_index: usize,
+    fraction: f64,
+) -> bool {
+    let mut h = mix64(seed, coords.z() as u64);
+    h = mix64(h, coords.x() as u64);
+    h = mix64(h, coords.y() as u64);
+    h = mix64(h, layer_index as u64);
+    h = mix64(h, feature_index as u64);
+    // use the top 53 bits to build a uniform float in [0, 1)
+    let sample = (h >> 11) as f64 / (1u64 << 53) as f64;
+    sample < fraction
+}
+
+/// Rescale a feature's geometry from one extent to another, rounding to the nearest integer
+fn rescale_feature_geometry(feature: &mut Feature, from_extent: u32, to_extent: u32) -> Result<()> {
+    let scale = to_extent as f64 / from_extent as f64;
+    let geom = feature.to_geo()?;
+    let scaled = geom.map_coords(|Coord { x, y }| {
+        let x = (x * scale).round().clamp(i32::MIN as f64, i32::MAX as f64);
+        let y = (y * scale).round().clamp(i32::MIN as f64, i32::MAX as f64);
+        (x, y).into()
+    });
+    feature.geometry = scaled.to_mvt_unscaled()?.geometry;
+    Ok(())
+}
+
+/// Index of `item` in `items`, appending it if missing
+fn intern<T: PartialEq + Clone>(items: &mut Vec<T>, item: &T) -> u32 {
+    let idx = items.iter().position(|i| i == item).unwrap_or_else(|| {
+        items.push(item.clone());
+        items.len() - 1
+    });
+    idx as u32
+}
+
+/// Append the features of `source` to `target`, rescaling both to the larger extent
+/// and re-indexing tags into `target`'s key/value tables
+fn append_layer(target: &mut Layer, mut source: Layer, default_extent: u32) -> Result<()> {
+    let target_extent = target.extent.unwrap_or(default_extent);
+    let source_extent = source.extent.unwrap_or(default_extent);
+    let extent = target_extent.max(source_extent);
+    if target_extent != extent {
+        for feature in &mut target.features {
+            rescale_feature_geometry(feature, target_extent, extent)?;
+        }
+        target.extent = Some(extent);
+    }
+
+    for mut feature in source.features.drain(..) {
+        if source_extent != extent {
+            rescale_feature_geometry(&mut feature, source_extent, extent)?;
+        }
+        let mut tags = Vec::with_capacity(feature.tags.len());
+        for kv in feature.tags.chunks_exact(2) {
+            tags.push(intern(&mut target.keys, &source.keys[kv[0] as usize]));
+            tags.push(intern(&mut target.values, &source.values[kv[1] as usize]));
+        }
+        feature.tags = tags;
+        target.features.push(feature);
+    }
+    Ok(())
+}
+
+/// Merge decoded copies of the same tile from several archives.
+/// Layers with the same name are combined by concatenating their features.
+pub fn merge_tiles(
+    coords: &TileCoord,
+    tiles: &[Vec<u8>],
+    default_extent: u32,
+) -> Result<Vec<u8>, WranglerError> {
+    let mut merged = Tile::default();
+    for data in tiles {
+        let tile = Tile::decode(data.as_slice()).map_err(|source| WranglerError::TileDecode {
+            coord: format_tile_coord(coords),
+            source,
+        })?;
+        for layer in tile.layers {
+            match merged.layers.iter_mut().find(|l| l.name == layer.name) {
+                Some(target) => append_layer(target, layer, default_extent)?,
+                None => merged.layers.push(layer),
+            }
+        }
+    }
+    Ok(merged.encode_to_vec())
+}
+
+/// Convert `"yes"`/`"true"`/`"1"` and `"no"`/`"false"`/`"0"` string values to booleans.
+/// Other values are returned unchanged.
+fn boolify_value(value: &Value) -> Value {
+    let parsed = match value.string_value.as_deref() {
+        Some("yes" | "true" | "1") => true,
+        Some("no" | "false" | "0") => false,
+        _ => return value.clone(),
+    };
+    Value {
+        bool_value: Some(parsed),
+        ..Default::default()
+    }
+}
+
+/// The `[min_lon, min_lat, max_lon, max_lat]` rectangle as a polygon
+fn bbox_polygon(bbox: &[f64; 4]) -> Polygon<f64> {
+    let [min_lon, min_lat, max_lon, max_lat] = *bbox;
+    Rect::new((min_lon, min_lat), (max_lon, max_lat)).to_polygon()
+}
+
+/// Whether the whole tile lies inside the rectangle
+fn tile_within_bbox(coords: &TileCoord, bbox: &[f64; 4]) -> bool {
+    let [min_lon, min_lat, max_lon, max_lat] = *bbox;
+    tile_bounds(coords).bounding_rect().is_some_and(|rect| {
+        rect.min().x >= min_lon
+            && rect.min().y >= min_lat
+            && rect.max().x <= max_lon
+            && rect.max().y <= max_lat
+    })
+}
+
+/// The parts of `geom` inside `area` (with `keep_inside`) or outside it, in tile
+/// coordinates. `None` if nothing is left.
+fn clip_geometry(
+    geom: Geometry<f64>,
+    area: &Polygon<f64>,
+    keep_inside: bool,
+) -> Option<Geometry<f64>> {
+    let clipped: Geometry<f64> = match geom {
+        Geometry::Point(point) => {
+            return (point.intersects(area) == keep_inside).then_some(point.into());
+        }
+        Geometry::MultiPoint(points) => MultiPoint::new(
+            points
+                .into_iter()
+                .filter(|p| p.intersects(area) == keep_inside)
+                .collect(),
+        )
+        .into(),
+        Geometry::LineString(line) => area
+            .clip(&MultiLineString::new(vec![line]), !keep_inside)
+            .into(),
+        Geometry::MultiLineString(lines) => area.clip(&lines, !keep_inside).into(),
+        Geometry::Polygon(polygon) if keep_inside => polygon.intersection(area).into(),
+        Geometry::Polygon(polygon) => polygon.difference(area).into(),
+        Geometry::MultiPolygon(polygons) => {
+            let area = MultiPolygon::new(vec![area.clone()]);
+            if keep_inside {
+                polygons.intersection(&area).into()
+            } else {
+                polygons.difference(&area).into()
+            }
+        }
+        other => other,
+    };
+    let is_empty = match &clipped {
+        Geometry::MultiPoint(points) => points.0.is_empty(),
+        Geometry::MultiLineString(lines) => lines.0.is_empty(),
+        Geometry::MultiPolygon(polygons) => polygons.0.is_empty(),
+        _ => false,
+    };
+    (!is_empty).then_some(clipped)
+}
+
+/// Replace a feature's geometry with a clipped one
+fn set_clipped_geometry(feature: &mut Feature, clipped: Geometry<f64>) -> Result<()> {
+    // clipping introduces fractional coordinates; snap them back to the tile grid
+    let clipped = clipped.map_coords(|Coord { x, y }| (x.round(), y.round()).into());
+    let encoded = clipped.to_mvt_unscaled()?;
+    feature.geometry = encoded.geometry;
+    feature.r#type = encoded.r#type;
+    Ok(())
+}
+
+/// Remove the parts of a feature's geometry inside `hole` (in tile coordinates).
+/// Returns false if nothing is left of the feature.
+fn cut_feature(feature: &mut Feature, hole: &Polygon<f64>) -> Result<bool> {
+    let geom = feature.to_geo()?;
+    if !geom.intersects(hole) {
+        return Ok(true);
+    }
+    let Some(remaining) = clip_geometry(geom, hole, false) else {
+        return Ok(false);
+    };
+    set_clipped_geometry(feature, remaining)?;
+    Ok(true)
+}
+
+/// Synthesize the tile `child` from its ancestor `parent` at a lower zoom, by scaling
+/// up the parent's features and clipping them to the child's part of it. Points on a
+/// boundary between children go to the child on their right/bottom. Layers left
+/// without features are dropped, and a tile left without layers encodes to nothing.
+pub fn overzoom_tile(
+    parent: &TileCoord,
+    data: &[u8],
+    child: &TileCoord,
+    default_extent: u32,
+) -> Result<Vec<u8>, WranglerError> {
+    let mut tile = Tile::decode(data).map_err(|source| WranglerError::TileDecode {
+        coord: format_tile_coord(parent),
+        source,
+    })?;
+    let scale = 1_u32 << (child.z() - parent.z());
+    let (dx, dy) = (
+        child.x() - parent.x() * scale,
+        child.y() - parent.y() * scale,
+    );
+
+    for layer in &mut tile.layers {
+        let extent = layer.extent.unwrap_or(default_extent) as f64;
+        let (x_offset, y_offset) = (dx as f64 * extent, dy as f64 * extent);
+        let area = Rect::new((0.0, 0.0), (extent, extent)).to_polygon();
+        let inside =
+            |p: &Point<f64>| (0.0..extent).contains(&p.x()) && (0.0..extent).contains(&p.y());
+
+        let mut features = Vec::with_capacity(layer.features.len());
+        for mut feature in layer.features.drain(..) {
+            let geom = feature.to_geo()?.map_coords(|Coord { x, y }| Coord {
+                x: x * scale as f64 - x_offset,
+                y: y * scale as f64 - y_offset,
+            });
+            let clipped = match geom {
+                Geometry::Point(point) => inside(&point).then_some(point.into()),
+                Geometry::MultiPoint(points) => {
+                    let points = points.into_iter().filter(inside).collect::<Vec<_>>();
+                    (!points.is_empty()).then(|| MultiPoint::new(points).into())
+                }
+                geom => clip_geometry(geom, &area, true),
+            };
+            if let Some(clipped) = clipped {
+                set_clipped_geometry(&mut feature, clipped)?;
+                features.push(feature);
+            }
+        }
+        layer.features = features;
+    }
+    tile.layers.retain(|layer| !layer.features.is_empty());
+
+    if tile.layers.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(tile.encode_to_vec())
+}
+
+/// Remove consecutive duplicate vertices from a line or ring. Returns false if fewer
+/// than `min_len` vertices are left.
+fn dedupe_line(line: &mut LineString<f64>, min_len: usize) -> bool {
+    line.0.dedup();
+    line.0.len() >= min_len
+}
+
+/// Dedupe a polygon's rings, dropping degenerate holes. `None` if the exterior degenerates.
+fn dedupe_polygon(polygon: Polygon<f64>) -> Option<Polygon<f64>> {
+    let (mut exterior, mut interiors) = polygon.into_inner();
+    if !dedupe_line(&mut exterior, 4) {
+        return None;
+    }
+    interiors.retain_mut(|ring| dedupe_line(ring, 4));
+    Some(Polygon::new(exterior, interiors))
+}
+
+/// Whether a tag value is an empty string, or carries no value at all
+fn is_empty_value(value: &Value) -> bool {
+    value.string_value.as_deref() == Some("") || *value == Value::default()
+}
+
+/// Remove consecutive duplicate vertices from a geometry. `None` if it degenerates.
+fn dedupe_geometry(geom: Geometry<f64>) -> Option<Geometry<f64>> {
+    Some(match geom {
+        Geometry::LineString(mut line) => {
+            if !dedupe_line(&mut line, 2) {
+                return None;
+            }
+            line.into()
+        }
+        Geometry::MultiLineString(mut lines) => {
+            lines.0.retain_mut(|line| dedupe_line(line, 2));
+            if lines.0.is_empty() {
+                return None;
+            }
+            lines.into()
+        }
+        Geometry::Polygon(polygon) => dedupe_polygon(polygon)?.into(),
+        Geometry::MultiPolygon(polygons) => {
+            let polygons = polygons
+                .into_iter()
+                .filter_map(dedupe_polygon)
+                .collect::<Vec<_>>();
+            if polygons.is_empty() {
+                return None;
+            }
+            MultiPolygon::new(polygons).into()
+        }
+        other => other,
+    })
+}
+
+/// Remove consecutive duplicate vertices (zero-length segments) from a feature's geometry.
+/// Returns false if the geometry degenerates, e.g. a line collapsing to a point.
+fn dedupe_feature_vertices(feature: &mut Feature) -> Result<bool> {
+    let geom = feature.to_geo()?;
+    let Some(deduped) = dedupe_geometry(geom.clone()) else {
+        return Ok(false);
+    };
+    // only re-encode geometry that changed
+    if deduped != geom {
+        let encoded = deduped.to_mvt_unscaled()?;
+        feature.geometry = encoded.geometry;
+        feature.r#type = encoded.r#type;
+    }
+    Ok(true)
+}
+
+/// Round every coordinate of a feature's geometry to the nearest multiple of `grid`
+/// tile units, removing the duplicate vertices this creates. Returns false if the
+/// geometry degenerates, e.g. a short line collapsing to a point.
+fn snap_feature_to_grid(feature: &mut Feature, grid: u32) -> Result<bool> {
+    let grid = grid as f64;
+    let geom = feature.to_geo()?;
+    let snapped = geom.map_coords(|Coord { x, y }| Coord {
+        x: (x / grid).round() * grid,
+        y: (y / grid).round() * grid,
+    });
+    let Some(snapped) = dedupe_geometry(snapped) else {
+        return Ok(false);
+    };
+    if snapped != geom {
+        let encoded = snapped.to_mvt_unscaled()?;
+        feature.geometry = encoded.geometry;
+        feature.r#type = encoded.r#type;
+    }
+    Ok(true)
+}
+
+/// Inverse of [`project_to_tile`]: tile-local coordinates back to lon/lat
+pub fn unproject_from_tile(geom: &Geometry<f64>, coords: &TileCoord, extent: u32) -> Geometry<f64> {
+    let n = 2_f64.powi(coords.z() as i32);
+    geom.map_coords(|Coord { x, y }| {
+        let x_frac = x / extent as f64 + coords.x() as f64;
+        let y_frac = y / extent as f64 + coords.y() as f64;
+        (x_frac / n * 360.0 - 180.0, tile_y_to_lat(y_frac, n)).into()
+    })
+}
+
+/// Grow `[min_x, min_y, max_x, max_y]` bounds to cover `rect`, starting them at `rect`
+pub(crate) fn extend_bounds(bounds: &mut Option<[f64; 4]>, rect: Rect<f64>) {
+    let [min_x, min_y, max_x, max_y] =
+        bounds.get_or_insert([rect.min().x, rect.min().y, rect.max().x, rect.max().y]);
+    *min_x = min_x.min(rect.min().x);
+    *min_y = min_y.min(rect.min().y);
+    *max_x = max_x.max(rect.max().x);
+    *max_y = max_y.max(rect.max().y);
+}
+
+/// The lon/lat rectangle around every feature of a tile, as `[min_lon, min_lat,
+/// max_lon, max_lat]`, or `None` without features. Geometry in the tile's buffer is
+/// left out, as the neighbouring tile covers it. Layers without an extent are taken
+/// to have `default_extent`.
+pub fn feature_extent(
+    tile: &Tile,
+    coords: &TileCoord,
+    default_extent: u32,
+) -> Result<Option<[f64; 4]>> {
+    let mut bounds = None;
+    for layer in &tile.layers {
+        let extent = layer.extent.unwrap_or(default_extent);
+        let size = extent as f64;
+        for feature in &layer.features {
+            let Some(rect) = feature.to_geo()?.bounding_rect() else {
+                continue;
+            };
+            if rect.max().x < 0.0
+                || rect.max().y < 0.0
+                || rect.min().x > size
+                || rect.min().y > size
+            {
+                continue;
+            }
+            let clamp = |c: Coord| Coord {
+                x: c.x.clamp(0.0, size),
+                y: c.y.clamp(0.0, size),
+            };
+            let local = Geometry::Rect(Rect::new(clamp(rect.min()), clamp(rect.max())));
+            let Some(rect) = unproject_from_tile(&local, coords, extent).bounding_rect() else {
+                continue;
+            };
+            extend_bounds(&mut bounds, rect);
+        }
+    }
+    Ok(bounds)
+}
+
+/// Convert an MVT value to JSON for reports
+fn value_to_json(value: &Value) -> serde_json::Value {
+    if let Some(s) = &value.string_value {
+        s.clone().into()
+    } else if let Some(i) = value.int_value.or(value.sint_value) {
+        i.into()
+    } else if let Some(u) = value.uint_value {
+        u.into()
+    } else if let Some(f) = value.float_value {
+        f.into()
+    } else if let Some(d) = value.double_value {
+        d.into()
+    } else if let Some(b) = value.bool_value {
+        b.into()
+    } else {
+        serde_json::Value::Null
+    }
+}
+
+/// Describe a feature removed by a filter as a GeoJSON feature in lon/lat, with its
+/// tags as properties and the layer and tile as foreign members
+fn removed_feature_report(
+    geom: &Geometry<f64>,
+    tags: &[(String, Value)],
+    layer_name: &str,
+    coords: &TileCoord,
+    extent: u32,
+) -> geojson::Feature {
+    let geometry = geojson::Geometry::from(&unproject_from_tile(geom, coords, extent));
+    let properties = tags
+        .iter()
+        .map(|(key, value)| (key.clone(), value_to_json(value)))
+        .collect();
+    let foreign_members = serde_json::Map::from_iter([
+        ("layer".to_string(), layer_name.into()),
+        ("tile".to_string(), format_tile_coord(coords).into()),
+    ]);
+    geojson::Feature {
+        geometry: Some(geometry),
+        properties: Some(properties),
+        foreign_members: Some(foreign_members),
+        ..Default::default()
+    }
+}
+
+/// Whether [`transform_tile`] would leave a tile exactly as it is: no filter reaches
+/// the tile and no option rewrites every tile. Such tiles can be copied without
+/// decoding. `drop_empty_layers` counts as rewriting, as it would remove any empty
+/// layers the tile has, and `infer_fields` too, as it needs the tile decoded.
+pub fn leaves_tile_unchanged(
+    coords: &TileCoord,
+    filter_collection: Option<&CompiledFilterCollection>,
+    options: &TransformOptions,
+) -> bool {
+    // Destructure so that new options have to be considered here
+    let TransformOptions {
+        target_extent,
+        layer_extent_overrides,
+        default_extent: _,
+        sample_fraction,
+        seed: _,
+        maplibre_compat: _,
+        drop_empty_layers,
+        exclude_bbox,
+        normalize_keys,
+        boolify_keys,
+        clamp_values,
+        add_tags,
+        keep_top_percent,
+        dedupe_vertices,
+        snap_grid,
+        strip_empty_values,
+        sort_layers,
+        filter_layer: _,
+        only_layers,
+        exclude_layers_regex,
+        remove_where,
+        keep_tags_matching,
+        drop_tags_matching,
+        verify_encoding: _,
+        infer_fields,
+    } = options;
+    if target_extent.is_some()
+        || !layer_extent_overrides.is_empty()
+        || sample_fraction.is_some()
+        || *drop_empty_layers
+        || *infer_fields
+        || !normalize_keys.is_empty()
+        || !boolify_keys.is_empty()
+        || !clamp_values.is_empty()
+        || !add_tags.is_empty()
+        || !keep_top_percent.is_empty()
+        || *dedupe_vertices
+        || snap_grid.is_some()
+        || *strip_empty_values
+        || *sort_layers
+        || only_layers.is_some()
+        || exclude_layers_regex.is_some()
+        || remove_where.is_some()
+        || keep_tags_matching.is_some()
+        || drop_tags_matching.is_some()
+    {
+        return false;
+    }
+    let bounds = tile_bounds(coords);
+    exclude_bbox.is_none_or(|bbox| !bounds.intersects(&bbox_polygon(&bbox)))
+        && filter_collection.is_none_or(|fc| fc.get_filter_features(&bounds).is_empty())
+}
+
 pub fn transform_tile(
     coords: &TileCoord,
     data: &[u8],
     filter_collection: Option<&CompiledFilterCollection>,
-) -> Result<Vec<u8>> {
+    options: &TransformOptions,
+) -> Result<(Vec<u8>, TransformStats), WranglerError> {
+    transform_tile_inner(coords, data, filter_collection, options, None)
+}
+
+/// Like [`transform_tile`], also appending every feature removed by a filter to
+/// `removed` as a GeoJSON feature in lon/lat
+pub fn transform_tile_reporting_removed(
+    coords: &TileCoord,
+    data: &[u8],
+    filter_collection: Option<&CompiledFilterCollection>,
+    options: &TransformOptions,
+    removed: &mut Vec<geojson::Feature>,
+) -> Result<(Vec<u8>, TransformStats), WranglerError> {
+    transform_tile_inner(coords, data, filter_collection, options, Some(removed))
+}
+
+fn transform_tile_inner(
+    coords: &TileCoord,
+    data: &[u8],
+    filter_collection: Option<&CompiledFilterCollection>,
+    options: &TransformOptions,
+    mut removed: Option<&mut Vec<geojson::Feature>>,
+) -> Result<(Vec<u8>, TransformStats), WranglerError> {
+    let mut stats = TransformStats::default();
     // decode the entire tile from bytes
-    let mut tile = Tile::decode(data)
-        .with_context(|| format!("Failed to decode MVT tile: {}", format_tile_coord(coords)))?;
+    let mut tile = Tile::decode(data).map_err(|source| WranglerError::TileDecode {
+        coord: format_tile_coord(coords),
+        source,
+    })?;
+
+    if let Some(bbox) = &options.exclude_bbox
+        && tile_within_bbox(coords, bbox)
+    {
+        // the whole tile is excluded; empty tiles aren't written
+        return Ok((Vec::new(), stats));
+    }
+    let exclude_polygon = options.exclude_bbox.as_ref().map(bbox_polygon);
+
+    if let Some(only_layers) = &options.only_layers {
+        tile.layers
+            .retain(|layer| only_layers.contains(&layer.name));
+    }
+    if options.exclude_layers_regex.is_some() {
+        tile.layers
+            .retain(|layer| !options.excludes_layer(&layer.name));
+    }
 
     let filter_candidates = if let Some(fc) = filter_collection {
         let bounds = tile_bounds(coords);
@@ -86,12 +1050,28 @@ pub fn transform_tile(
         Vec::new()
     };
 
-    for layer in &mut tile.layers {
+    // With `verify_encoding`, the tags each kept feature should end up with, by layer
+    let mut intended: Vec<IntendedLayer> = Vec::new();
+    let passes_through = |layer: &Layer| {
+        options
+            .filter_layer
+            .as_ref()
+            .is_some_and(|name| *name != layer.name)
+    };
+
+    for (layer_index, layer) in tile.layers.iter_mut().enumerate() {
+        if passes_through(layer) {
+            if options.verify_encoding {
+                intended.push((layer.name.clone(), layer_tags(layer)));
+            }
+            continue;
+        }
+
         // if the filter_geometry is provided, we need to reproject it to tile coordinates
         // let's do a quick check to see if the filter intersects the tile
         // if it doesn't, set the filter_geometry to None
         // we do this per layer because the extent is set per layer.
-        let extent = layer.extent.unwrap_or(4096);
+        let extent = layer.extent.unwrap_or(options.default_extent);
 
         let filter_features = filter_candidates
             .iter()
@@ -107,60 +1087,146 @@ pub fn transform_tile(
             })
             .collect::<Vec<_>>();
 
+        let exclude_hole = exclude_polygon.as_ref().and_then(|polygon| {
+            let hole = project_to_tile(&Geometry::Polygon(polygon.clone()), coords, extent);
+            match hole {
+                Geometry::Polygon(hole) if bbox_intersects_tile(&hole.clone().into(), extent) => {
+                    Some(hole)
+                }
+                _ => None,
+            }
+        });
+
         let mut keys: Vec<String> = Vec::with_capacity(layer.keys.len());
         let mut values: Vec<Value> = Vec::with_capacity(layer.values.len());
         let mut features: Vec<Feature> = Vec::with_capacity(layer.features.len());
+        let mut intended_tags: Vec<Vec<(String, Value)>> = Vec::new();
 
-        for feature in layer.features.drain(..) {
+        for (feature_index, feature) in layer.features.drain(..).enumerate() {
             // remove the feature from the layer
             let mut feature = feature;
 
+            if let Some(fraction) = options.sample_fraction
+                && !keep_sampled_feature(options.seed, coords, layer_index, feature_index, fraction)
+            {
+                continue; // Not sampled
+            }
+
+            if let Some(hole) = &exclude_hole
+                && !cut_feature(&mut feature, hole)?
+            {
+                continue; // Entirely inside the excluded rectangle
+            }
+
+            if let Some(grid) = options.snap_grid
+                && !snap_feature_to_grid(&mut feature, grid)?
+            {
+                continue; // Degenerate once snapped to the grid
+            }
+
+            if options.dedupe_vertices && !dedupe_feature_vertices(&mut feature)? {
+                continue; // Degenerate once duplicate vertices are removed
+            }
+
+            // Tags in their original order. A key repeated within a feature is kept
+            // as-is; filters see its first value.
+            let mut feature_tags: Vec<(String, Value)> = Vec::with_capacity(feature.tags.len() / 2);
             let mut tag_hashmap: HashMap<String, Value> = HashMap::new();
+            // The original key behind each normalized key, to spot collisions
+            let mut original_keys: HashMap<String, &str> = HashMap::new();
             for tags in feature.tags.chunks_exact(2) {
                 let key_index = tags[0] as usize;
                 let value_index = tags[1] as usize;
 
                 // get the key and value from the layer
-                let key = &layer.keys[key_index];
+                let original_key = layer.keys[key_index].as_str();
                 let value = &layer.values[value_index];
 
-                tag_hashmap.insert(key.to_string(), value.clone());
+                let key = &normalize_key(original_key, &options.normalize_keys);
+                if !options.normalize_keys.is_empty() {
+                    // Keys that only became equal through normalization: the last one wins
+                    if original_keys
+                        .insert(key.clone(), original_key)
+                        .is_some_and(|previous| previous != original_key)
+                    {
+                        feature_tags.retain(|(k, _)| k != key);
+                        tag_hashmap.remove(key);
+                        stats.key_collisions += 1;
+                    }
+                }
+
+                let mut value = if options.boolify_keys.contains(key) {
+                    boolify_value(value)
+                } else {
+                    value.clone()
+                };
+                for rule in &options.clamp_values {
+                    if rule.applies_to(&layer.name, key)
+                        && let Some(clamped) = rule.clamp(&value)
+                    {
+                        value = clamped;
+                    }
+                }
+                tag_hashmap
+                    .entry(key.to_string())
+                    .or_insert_with(|| value.clone());
+                feature_tags.push((key.to_string(), value));
             }
 
             let feature_geom = feature.to_geo()?;
-            let feature_geom_shape = match feature_geom {
-                Geometry::Point(_) => "Point",
-                Geometry::MultiPoint(_) => "Point",
-                Geometry::LineString(_) => "LineString",
-                Geometry::MultiLineString(_) => "LineString",
-                Geometry::Polygon(_) => "Polygon",
-                Geometry::MultiPolygon(_) => "Polygon",
-                _ => "Unknown",
-            };
+            let feature_geom_shape = geometry_shape(&feature_geom);
             let intersecting_filters = filter_features
                 .iter()
                 .filter(|f| feature_geom.intersects(&f.geometry))
                 .collect::<Vec<_>>();
 
             let mut ctx = EvaluationContext::new(&layer.name, tag_hashmap.clone())
-                .with_geometry_type(feature_geom_shape);
+                .with_geometry_type(feature_geom_shape)
+                .with_vertex_count(feature_geom.coords_count())
+                .with_command_count(feature.geometry.len())
+                .with_zoom(coords.z())
+                .with_maplibre_compat(options.maplibre_compat);
+            if let Some(bounding_box) = feature_geom.bounding_rect() {
+                ctx = ctx.with_bounding_box(bounding_box);
+            }
+            if !intersecting_filters.is_empty() || options.remove_where.is_some() {
+                // In lon/lat, for operators measuring the feature or comparing it with a
+                // filter region
+                let lonlat = unproject_from_tile(&feature_geom, coords, extent);
+                ctx = ctx.with_geometry(Arc::new(lonlat));
+            }
 
-            let mut should_remove_filter = false;
+            let mut should_remove_filter = match &options.remove_where {
+                Some(expr) => ExpressionExecutor::evaluate_bool(expr, &ctx)?,
+                None => false,
+            };
             for f in &intersecting_filters {
+                ctx = ctx.with_region(f.region.clone());
                 if f.should_remove_feature(&ctx)? {
                     should_remove_filter = true;
                     break;
                 }
             }
             if should_remove_filter {
+                if let Some(removed) = removed.as_deref_mut() {
+                    removed.push(removed_feature_report(
+                        &feature_geom,
+                        &feature_tags,
+                        &layer.name,
+                        coords,
+                        extent,
+                    ));
+                }
                 continue; // Skip this feature
             }
 
             let mut new_tags: Vec<u32> = Vec::with_capacity(feature.tags.len());
-            for (key, value) in &tag_hashmap {
-                ctx = ctx.with_current_key(key);
+            let mut kept_tags = Vec::new();
+            for (key, value) in &feature_tags {
+                ctx = ctx.with_current_key(key).with_current_value(value);
                 let mut should_remove_tag = false;
                 for f in &intersecting_filters {
+                    ctx = ctx.with_region(f.region.clone());
                     if f.should_remove_tag(&ctx)? {
                         should_remove_tag = true;
                         break;
@@ -169,6 +1235,20 @@ pub fn transform_tile(
                 if should_remove_tag {
                     continue; // Skip this tag
                 }
+                if options
+                    .keep_tags_matching
+                    .as_ref()
+                    .is_some_and(|pattern| !pattern.is_match(key))
+                    || options
+                        .drop_tags_matching
+                        .as_ref()
+                        .is_some_and(|pattern| pattern.is_match(key))
+                {
+                    continue; // Removed by key pattern
+                }
+                if options.strip_empty_values && is_empty_value(value) {
+                    continue;
+                }
 
                 // add the key and value to the new vectors
                 let key_idx = {
@@ -189,17 +1269,1796 @@ pub fn transform_tile(
                 };
                 new_tags.push(key_idx as u32);
                 new_tags.push(value_idx as u32);
+                if options.verify_encoding {
+                    kept_tags.push((key.clone(), value.clone()));
+                }
             }
 
             feature.tags = new_tags;
             features.push(feature);
+            if options.verify_encoding {
+                intended_tags.push(kept_tags);
+            }
+        }
+
+        for rule in &options.keep_top_percent {
+            if rule.layer != "*" && rule.layer != layer.name {
+                continue;
+            }
+            let keep = rule.select(&features, &keys, &values);
+            let mut keep_features = keep.iter();
+            features.retain(|_| *keep_features.next().unwrap());
+            let mut keep_tags = keep.iter();
+            intended_tags.retain(|_| *keep_tags.next().unwrap());
+        }
+
+        for add_tag in &options.add_tags {
+            if add_tag.layer != "*" && add_tag.layer != layer.name {
+                continue;
+            }
+            let key_idx = intern(&mut keys, &add_tag.key);
+            let value_idx = intern(&mut values, &add_tag.value);
+            for feature in &mut features {
+                let mut tags = Vec::with_capacity(feature.tags.len() + 2);
+                for kv in feature.tags.chunks_exact(2) {
+                    if kv[0] != key_idx {
+                        tags.extend_from_slice(kv);
+                    }
+                }
+                tags.extend([key_idx, value_idx]);
+                feature.tags = tags;
+            }
+            for tags in &mut intended_tags {
+                tags.retain(|(key, _)| *key != add_tag.key);
+                tags.push((add_tag.key.clone(), add_tag.value.clone()));
+            }
+        }
+
+        let target_extent = options
+            .layer_extent_overrides
+            .get(&layer.name)
+            .copied()
+            .or(options.target_extent);
+        if let Some(target_extent) = target_extent
+            && target_extent != extent
+        {
+            for feature in &mut features {
+                rescale_feature_geometry(feature, extent, target_extent)?;
+            }
+            layer.extent = Some(target_extent);
         }
 
         layer.keys = keys;
         layer.values = values;
         layer.features = features;
+        if options.verify_encoding {
+            intended.push((layer.name.clone(), intended_tags));
+        }
+    }
+
+    if options.drop_empty_layers {
+        // a tile without layers encodes to zero bytes, which the writer skips
+        // Layers passed through untouched are kept even when empty
+        let kept = tile
+            .layers
+            .iter()
+            .map(|layer| !layer.features.is_empty() || passes_through(layer))
+            .collect::<Vec<_>>();
+        let mut kept_layers = kept.iter();
+        tile.layers.retain(|_| *kept_layers.next().unwrap());
+        let mut kept_layers = kept.iter();
+        intended.retain(|_| *kept_layers.next().unwrap());
+    }
+
+    if options.sort_layers {
+        // Both are in the same order, so a stable sort keeps them aligned
+        tile.layers.sort_by(|a, b| a.name.cmp(&b.name));
+        intended.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    for layer in &tile.layers {
+        *stats.layer_bytes.entry(layer.name.clone()).or_default() += layer.encoded_len() as u64;
+    }
+    if options.infer_fields {
+        infer_fields(&tile, &mut stats.layer_fields);
     }
 
     // re-encode to a fresh Vec<u8>
-    Ok(tile.encode_to_vec()) // prost::Message::encode_to_vec
+    let bytes = tile.encode_to_vec(); // prost::Message::encode_to_vec
+    if options.verify_encoding {
+        verify_encoding(&bytes, &intended).map_err(|e| {
+            WranglerError::Other(e.context(format!(
+                "tile {} does not decode to what was encoded",
+                format_tile_coord(coords)
+            )))
+        })?;
+    }
+    Ok((bytes, stats))
+}
+
+/// The geometry type filters see for a geometry: `Point`, `LineString` or `Polygon`.
+/// Multi-geometries count as their single type, and a collection whose members all
+/// share one type counts as that type. Anything else is `Unknown`.
+fn geometry_shape(geom: &Geometry<f64>) -> &'static str {
+    match geom {
+        Geometry::Point(_) | Geometry::MultiPoint(_) => "Point",
+        Geometry::Line(_) | Geometry::LineString(_) | Geometry::MultiLineString(_) => "LineString",
+        Geometry::Polygon(_)
+        | Geometry::MultiPolygon(_)
+        | Geometry::Rect(_)
+        | Geometry::Triangle(_) => "Polygon",
+        Geometry::GeometryCollection(collection) => {
+            let mut shapes = collection.iter().map(geometry_shape);
+            match shapes.next() {
+                Some(first) if shapes.all(|shape| shape == first) => first,
+                _ => "Unknown",
+            }
+        }
+    }
+}
+
+/// The tags of each feature of a layer
+fn layer_tags(layer: &Layer) -> Vec<Vec<(String, Value)>> {
+    layer
+        .features
+        .iter()
+        .map(|feature| {
+            feature
+                .tags
+                .chunks_exact(2)
+                .map(|kv| {
+                    (
+                        layer.keys[kv[0] as usize].clone(),
+                        layer.values[kv[1] as usize].clone(),
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A layer's name and, for each of its features, the tags it should have
+type IntendedLayer = (String, Vec<Vec<(String, Value)>>);
+
+/// Decode an encoded tile and check that it has the `intended` layers, in order
+fn verify_encoding(bytes: &[u8], intended: &[IntendedLayer]) -> Result<()> {
+    let tile = Tile::decode(bytes)?;
+    ensure!(
+        tile.layers.len() == intended.len(),
+        "expected {} layers, decoded {}",
+        intended.len(),
+        tile.layers.len()
+    );
+    for (layer, (name, features)) in tile.layers.iter().zip(intended) {
+        ensure!(
+            layer.name == *name,
+            "expected layer {}, decoded {}",
+            name,
+            layer.name
+        );
+        ensure!(
+            layer.features.len() == features.len(),
+            "layer {}: expected {} features, decoded {}",
+            name,
+            features.len(),
+            layer.features.len()
+        );
+        for (index, (feature, tags)) in layer.features.iter().zip(features).enumerate() {
+            ensure!(
+                feature.tags.len() == tags.len() * 2,
+                "layer {} feature {}: expected {} tags, decoded {} indices",
+                name,
+                index,
+                tags.len(),
+                feature.tags.len()
+            );
+            for (kv, (key, value)) in feature.tags.chunks_exact(2).zip(tags) {
+                let decoded = (
+                    layer.keys.get(kv[0] as usize),
+                    layer.values.get(kv[1] as usize),
+                );
+                ensure!(
+                    decoded == (Some(key), Some(value)),
+                    "layer {} feature {}: expected {}={:?}, decoded {:?}",
+                    name,
+                    index,
+                    key,
+                    value,
+                    decoded
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::{Point, line_string};
+    use geozero::mvt::tile::Layer;
+
+    fn string_value(s: &str) -> Value {
+        Value {
+            string_value: Some(s.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn make_feature(geom: Geometry<f64>, tags: Vec<u32>) -> Feature {
+        let mut feature = geom.to_mvt_unscaled().unwrap();
+        feature.tags = tags;
+        feature
+    }
+
+    fn make_layer(name: &str, extent: u32, features: Vec<Feature>) -> Layer {
+        Layer {
+            version: 2,
+            name: name.to_string(),
+            features,
+            keys: vec!["kind".to_string()],
+            values: vec![string_value("park")],
+            extent: Some(extent),
+        }
+    }
+
+    fn run_transform(layers: Vec<Layer>, options: &TransformOptions) -> Tile {
+        run_transform_with_stats(layers, options).0
+    }
+
+    fn run_transform_with_stats(
+        layers: Vec<Layer>,
+        options: &TransformOptions,
+    ) -> (Tile, TransformStats) {
+        let tile = Tile { layers };
+        let coords = TileCoord::new(0, 0, 0).unwrap();
+        let (bytes, stats) = transform_tile(&coords, &tile.encode_to_vec(), None, options).unwrap();
+        (Tile::decode(bytes.as_slice()).unwrap(), stats)
+    }
+
+    #[test]
+    fn test_corrupt_tile_is_tile_decode_error() {
+        let coords = TileCoord::new(3, 1, 2).unwrap();
+        let result = transform_tile(
+            &coords,
+            b"\x89PNG not a tile",
+            None,
+            &TransformOptions::default(),
+        );
+        match result {
+            Err(WranglerError::TileDecode { coord, .. }) => assert_eq!(coord, "3/1/2"),
+            other => panic!("Expected TileDecode error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_overzoom_tile_splits_into_children() {
+        use geo::{Area, Euclidean, Length};
+
+        let parent = TileCoord::new(14, 100, 200).unwrap();
+        let points = [
+            (1000.0, 1000.0),
+            (3000.0, 1000.0),
+            (2048.0, 2048.0),
+            (100.0, 3000.0),
+        ];
+        let mut features = points
+            .iter()
+            .map(|&p| make_feature(Point::from(p).into(), vec![0, 0]))
+            .collect::<Vec<_>>();
+        features.push(make_feature(
+            line_string![(x: 1000.0, y: 1024.0), (x: 3000.0, y: 1024.0)].into(),
+            vec![0, 0],
+        ));
+        features.push(make_feature(
+            Rect::new((1024.0, 1024.0), (3072.0, 3072.0)).into(),
+            vec![0, 0],
+        ));
+        let data = Tile {
+            layers: vec![make_layer("pois", 4096, features)],
+        }
+        .encode_to_vec();
+
+        let mut child_points = Vec::new();
+        let (mut line_length, mut polygon_area) = (0.0, 0.0);
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let child = TileCoord::new(15, 200 + dx, 400 + dy).unwrap();
+            let bytes = overzoom_tile(&parent, &data, &child, DEFAULT_EXTENT).unwrap();
+            let tile = Tile::decode(bytes.as_slice()).unwrap();
+            assert_eq!(tile.layers.len(), 1);
+            for feature in &tile.layers[0].features {
+                assert_eq!(feature.tags, vec![0, 0]);
+                // Back to the parent's coordinates
+                let geom = feature
+                    .to_geo()
+                    .unwrap()
+                    .map_coords(|Coord { x, y }| Coord {
+                        x: (x + dx as f64 * 4096.0) / 2.0,
+                        y: (y + dy as f64 * 4096.0) / 2.0,
+                    });
+                match geom {
+                    Geometry::Point(p) => child_points.push((p.x(), p.y())),
+                    Geometry::LineString(line) => line_length += Euclidean.length(&line),
+                    Geometry::MultiLineString(lines) => line_length += Euclidean.length(&lines),
+                    Geometry::Polygon(polygon) => polygon_area += polygon.unsigned_area(),
+                    Geometry::MultiPolygon(polygons) => polygon_area += polygons.unsigned_area(),
+                    other => panic!("Unexpected geometry {:?}", other),
+                }
+            }
+        }
+
+        child_points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut expected_points = points.to_vec();
+        expected_points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(child_points, expected_points);
+        assert_eq!(line_length, 2000.0);
+        assert_eq!(polygon_area, 2048.0 * 2048.0);
+    }
+
+    /// Filters see each tile's clipped copy of a feature, not the whole feature: a
+    /// polygon split between two tiles has half its area in each of them
+    #[test]
+    fn test_feature_spanning_two_tiles_is_measured_per_tile() {
+        use geo::Area;
+
+        let parent = TileCoord::new(14, 100, 200).unwrap();
+        let square = Rect::new((1024.0, 1024.0), (3072.0, 2048.0));
+        let data = Tile {
+            layers: vec![make_layer(
+                "buildings",
+                4096,
+                vec![make_feature(square.into(), vec![0, 0])],
+            )],
+        }
+        .encode_to_vec();
+
+        // The left and right children at the top of the parent split the square in two
+        let areas = [0, 1].map(|dx| {
+            let child = TileCoord::new(15, 200 + dx, 400).unwrap();
+            let bytes = overzoom_tile(&parent, &data, &child, DEFAULT_EXTENT).unwrap();
+            let tile = Tile::decode(bytes.as_slice()).unwrap();
+            assert_eq!(tile.layers[0].features.len(), 1);
+            // In the parent's units
+            tile.layers[0].features[0].to_geo().unwrap().unsigned_area() / 4.0
+        });
+        assert_eq!(areas, [1024.0 * 1024.0; 2]);
+        assert_eq!(areas[0] + areas[1], square.unsigned_area());
+    }
+
+    #[test]
+    fn test_target_extent_rescales_geometry() {
+        let layer = make_layer(
+            "roads",
+            8192,
+            vec![
+                make_feature(Point::new(8000.0, 4000.0).into(), vec![0, 0]),
+                make_feature(
+                    line_string![(x: 0.0, y: 0.0), (x: 8192.0, y: 8192.0)].into(),
+                    vec![0, 0],
+                ),
+            ],
+        );
+        let options = TransformOptions {
+            target_extent: Some(4096),
+            ..Default::default()
+        };
+
+        let tile = run_transform(vec![layer], &options);
+        let layer = &tile.layers[0];
+        assert_eq!(layer.extent, Some(4096));
+
+        let point = layer.features[0].to_geo().unwrap();
+        assert_eq!(point, Geometry::Point(Point::new(4000.0, 2000.0)));
+
+        let line = layer.features[1].to_geo().unwrap();
+        assert_eq!(
+            line,
+            Geometry::LineString(line_string![(x: 0.0, y: 0.0), (x: 4096.0, y: 4096.0)])
+        );
+        assert_eq!(layer.features[1].tags, vec![0, 0]);
+    }
+
+    #[test]
+    fn test_target_extent_normalizes_mixed_layers() {
+        let options = TransformOptions {
+            target_extent: Some(2048),
+            ..Default::default()
+        };
+        let small = make_layer(
+            "pois",
+            4096,
+            vec![make_feature(Point::new(4000.0, 1000.0).into(), vec![0, 0])],
+        );
+        let large = make_layer(
+            "roads",
+            8192,
+            vec![make_feature(
+                line_string![(x: 0.0, y: 8192.0), (x: 8000.0, y: 1000.0)].into(),
+                vec![0, 0],
+            )],
+        );
+
+        let tile = run_transform(vec![small, large], &options);
+        assert!(tile.layers.iter().all(|l| l.extent == Some(2048)));
+        assert_eq!(
+            tile.layers[0].features[0].to_geo().unwrap(),
+            Geometry::Point(Point::new(2000.0, 500.0))
+        );
+        assert_eq!(
+            tile.layers[1].features[0].to_geo().unwrap(),
+            Geometry::LineString(line_string![(x: 0.0, y: 2048.0), (x: 2000.0, y: 250.0)])
+        );
+    }
+
+    #[test]
+    fn test_layer_extent_override_rescales_one_layer() {
+        let options = TransformOptions {
+            target_extent: Some(2048),
+            layer_extent_overrides: HashMap::from([("roads".to_string(), 8192)]),
+            ..Default::default()
+        };
+        let pois = make_layer(
+            "pois",
+            4096,
+            vec![make_feature(Point::new(4000.0, 1000.0).into(), vec![0, 0])],
+        );
+        let roads = make_layer(
+            "roads",
+            4096,
+            vec![make_feature(
+                line_string![(x: 0.0, y: 4096.0), (x: 4000.0, y: 500.0)].into(),
+                vec![0, 0],
+            )],
+        );
+
+        let tile = run_transform(vec![pois.clone(), roads.clone()], &options);
+        assert_eq!(tile.layers[0].extent, Some(2048));
+        assert_eq!(tile.layers[1].extent, Some(8192));
+        assert_eq!(
+            tile.layers[1].features[0].to_geo().unwrap(),
+            Geometry::LineString(line_string![(x: 0.0, y: 8192.0), (x: 8000.0, y: 1000.0)])
+        );
+
+        // Without target_extent the other layers keep their extent and geometry
+        let options = TransformOptions {
+            layer_extent_overrides: HashMap::from([("roads".to_string(), 8192)]),
+            ..Default::default()
+        };
+        let tile = run_transform(vec![pois.clone(), roads], &options);
+        assert_eq!(tile.layers[0], pois);
+        assert_eq!(tile.layers[1].extent, Some(8192));
+    }
+
+    fn numbered_points(count: usize) -> Vec<Feature> {
+        (0..count)
+            .map(|i| make_feature(Point::new(i as f64, i as f64).into(), vec![0, 0]))
+            .collect()
+    }
+
+    fn point_xs(layer: &Layer) -> Vec<f64> {
+        layer
+            .features
+            .iter()
+            .map(|f| match f.to_geo().unwrap() {
+                Geometry::Point(p) => p.x(),
+                other => panic!("Expected point, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_tiles() {
+        let coords = TileCoord::new(0, 0, 0).unwrap();
+        let a = Tile {
+            layers: vec![make_layer("pois", 4096, numbered_points(2))],
+        };
+        let mut school = make_layer(
+            "pois",
+            8192,
+            vec![make_feature(Point::new(100.0, 100.0).into(), vec![0, 0])],
+        );
+        school.values = vec![string_value("school")];
+        let b = Tile {
+            layers: vec![school, make_layer("roads", 4096, numbered_points(1))],
+        };
+
+        let bytes = merge_tiles(
+            &coords,
+            &[a.encode_to_vec(), b.encode_to_vec()],
+            DEFAULT_EXTENT,
+        )
+        .unwrap();
+        let merged = Tile::decode(bytes.as_slice()).unwrap();
+
+        assert_eq!(merged.layers.len(), 2);
+        let pois = &merged.layers[0];
+        assert_eq!(pois.extent, Some(8192));
+        assert_eq!(point_xs(pois), vec![0.0, 2.0, 100.0]);
+        assert_eq!(pois.keys, vec!["kind".to_string()]);
+        assert_eq!(
+            pois.values,
+            vec![string_value("park"), string_value("school")]
+        );
+        assert_eq!(pois.features[2].tags, vec![0, 1]);
+        assert_eq!(merged.layers[1].name, "roads");
+    }
+
+    #[test]
+    fn test_exclude_bbox() {
+        // At 0/0/0 with extent 4096 this is roughly x 2048..3072, y 1190..2906
+        let options = TransformOptions {
+            exclude_bbox: Some([0.0, -60.0, 90.0, 60.0]),
+            ..Default::default()
+        };
+        let inside = make_feature(Point::new(2500.0, 2000.0).into(), vec![0, 0]);
+        let outside = make_feature(Point::new(100.0, 100.0).into(), vec![0, 0]);
+        let straddling = make_feature(
+            line_string![(x: 1000.0, y: 2000.0), (x: 4000.0, y: 2000.0)].into(),
+            vec![0, 0],
+        );
+
+        let tile = run_transform(
+            vec![make_layer("pois", 4096, vec![inside, outside, straddling])],
+            &options,
+        );
+        let features = &tile.layers[0].features;
+        assert_eq!(features.len(), 2, "the feature inside is removed");
+        assert_eq!(
+            features[0].to_geo().unwrap(),
+            Geometry::Point(Point::new(100.0, 100.0))
+        );
+
+        match features[1].to_geo().unwrap() {
+            Geometry::MultiLineString(lines) => {
+                assert_eq!(lines.0.len(), 2, "the line is split around the hole");
+                for coord in lines.0.iter().flat_map(|l| l.0.iter()) {
+                    assert!(coord.x <= 2048.0 || coord.x >= 3072.0, "{:?}", coord);
+                }
+            }
+            other => panic!("Expected a clipped MultiLineString, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_encoding() {
+        let options = TransformOptions {
+            verify_encoding: true,
+            add_tags: vec![AddTag::parse("*:source=test").unwrap()],
+            ..Default::default()
+        };
+        let tile = run_transform(vec![make_layer("pois", 4096, numbered_points(2))], &options);
+        assert_eq!(tile.layers[0].features.len(), 2);
+
+        let intended = vec![(
+            "pois".to_string(),
+            vec![vec![("kind".to_string(), string_value("park"))]],
+        )];
+        let mut layer = make_layer("pois", 4096, numbered_points(1));
+        layer.values.push(string_value("school"));
+        let encode = |layer: &Layer| {
+            Tile {
+                layers: vec![layer.clone()],
+            }
+            .encode_to_vec()
+        };
+        verify_encoding(&encode(&layer), &intended).unwrap();
+
+        // An off-by-one value index points at the wrong value
+        layer.features[0].tags = vec![0, 1];
+        let err = verify_encoding(&encode(&layer), &intended).unwrap_err();
+        assert!(err.to_string().contains("layer pois feature 0"), "{}", err);
+        // or past the end of the value table
+        layer.features[0].tags = vec![0, 2];
+        assert!(verify_encoding(&encode(&layer), &intended).is_err());
+    }
+
+    #[test]
+    fn test_strip_empty_values() {
+        let mut layer = make_layer(
+            "pois",
+            4096,
+            vec![make_feature(
+                Point::new(1.0, 1.0).into(),
+                vec![0, 0, 1, 1, 2, 2],
+            )],
+        );
+        layer.keys = vec!["kind".to_string(), "name".to_string(), "note".to_string()];
+        layer.values = vec![string_value("park"), string_value(""), Value::default()];
+        let options = TransformOptions {
+            strip_empty_values: true,
+            ..Default::default()
+        };
+
+        let tile = run_transform(vec![layer.clone()], &options);
+        let layer_out = &tile.layers[0];
+        assert_eq!(layer_out.keys, vec!["kind"]);
+        assert_eq!(layer_out.values, vec![string_value("park")]);
+        assert_eq!(layer_out.features[0].tags, vec![0, 0]);
+
+        // Kept without the option
+        let tile = run_transform(vec![layer], &TransformOptions::default());
+        assert_eq!(tile.layers[0].keys.len(), 3);
+    }
+
+    #[test]
+    fn test_snap_grid() {
+        // A dense, jittery line: one vertex per tile unit, off by up to 2 units
+        let mut state = 7_u32;
+        let mut jitter = || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            ((state >> 16) % 5) as f64 - 2.0
+        };
+        let dense = LineString::from(
+            (0..1000)
+                .map(|i| (100.0 + i as f64 * 3.0, 2048.0 + jitter()))
+                .collect::<Vec<_>>(),
+        );
+        let layer = make_layer(
+            "roads",
+            4096,
+            vec![
+                make_feature(dense.into(), vec![0, 0]),
+                // Shorter than the grid, so it collapses to a point
+                make_feature(
+                    line_string![(x: 10.0, y: 10.0), (x: 11.0, y: 11.0)].into(),
+                    vec![0, 0],
+                ),
+                make_feature(Point::new(3001.0, 2999.0).into(), vec![0, 0]),
+            ],
+        );
+        let options = TransformOptions {
+            snap_grid: Some(4),
+            ..Default::default()
+        };
+
+        let unsnapped = run_transform(vec![layer.clone()], &TransformOptions::default());
+        let snapped = run_transform(vec![layer], &options);
+        let features = &snapped.layers[0].features;
+        assert_eq!(features.len(), 2, "the short line is dropped");
+        for feature in features {
+            for coord in feature.to_geo().unwrap().coords_iter() {
+                assert_eq!(coord.x % 4.0, 0.0, "{:?}", coord);
+                assert_eq!(coord.y % 4.0, 0.0, "{:?}", coord);
+            }
+        }
+        assert_eq!(
+            features[1].to_geo().unwrap(),
+            Geometry::Point(Point::new(3000.0, 3000.0))
+        );
+        assert!(snapped.encoded_len() < unsnapped.encoded_len());
+    }
+
+    #[test]
+    fn test_dedupe_vertices() {
+        let options = TransformOptions {
+            dedupe_vertices: true,
+            ..Default::default()
+        };
+        let line = make_feature(
+            line_string![
+                (x: 0.0, y: 0.0),
+                (x: 0.0, y: 0.0),
+                (x: 10.0, y: 0.0),
+                (x: 10.0, y: 0.0),
+                (x: 10.0, y: 0.0),
+                (x: 10.0, y: 10.0),
+            ]
+            .into(),
+            vec![0, 0],
+        );
+        let collapsed = make_feature(
+            line_string![(x: 5.0, y: 5.0), (x: 5.0, y: 5.0)].into(),
+            vec![0, 0],
+        );
+        let point = make_feature(Point::new(1.0, 1.0).into(), vec![0, 0]);
+        let vertices = |feature: &Feature| match feature.to_geo().unwrap() {
+            Geometry::LineString(line) => line.0.len(),
+            other => panic!("Expected a LineString, got {:?}", other),
+        };
+        assert_eq!(vertices(&line), 6);
+
+        let tile = run_transform(
+            vec![make_layer("roads", 4096, vec![line, collapsed, point])],
+            &options,
+        );
+        let features = &tile.layers[0].features;
+        assert_eq!(features.len(), 2, "the collapsed line is dropped");
+        assert_eq!(vertices(&features[0]), 3);
+        assert_eq!(
+            features[1].to_geo().unwrap(),
+            Geometry::Point(Point::new(1.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn test_exclude_bbox_drops_contained_tiles() {
+        let options = TransformOptions {
+            exclude_bbox: Some([-180.0, -85.0, 180.0, 85.0]),
+            ..Default::default()
+        };
+        let tile = Tile {
+            layers: vec![make_layer("pois", 4096, numbered_points(3))],
+        };
+        let inside = TileCoord::new(2, 1, 1).unwrap();
+        let (bytes, _) = transform_tile(&inside, &tile.encode_to_vec(), None, &options).unwrap();
+        assert!(bytes.is_empty());
+
+        // 0/0/0 reaches past 85°, so it is only clipped
+        let (bytes, _) = transform_tile(
+            &TileCoord::new(0, 0, 0).unwrap(),
+            &tile.encode_to_vec(),
+            None,
+            &options,
+        )
+        .unwrap();
+        assert!(!bytes.is_empty());
+    }
+
+    #[test]
+    fn test_boolify() {
+        let options = TransformOptions {
+            boolify_keys: vec!["oneway".to_string()],
+            ..Default::default()
+        };
+        let mut layer = make_layer(
+            "roads",
+            4096,
+            vec![
+                make_feature(Point::new(1.0, 1.0).into(), vec![0, 0, 1, 1]),
+                make_feature(Point::new(2.0, 2.0).into(), vec![1, 2]),
+            ],
+        );
+        layer.keys = vec!["kind".to_string(), "oneway".to_string()];
+        layer.values = vec![string_value("yes"), string_value("yes"), string_value("-1")];
+
+        let tile = run_transform(vec![layer], &options);
+        let layer = &tile.layers[0];
+        let tag = |feature: &Feature, key: &str| {
+            feature
+                .tags
+                .chunks_exact(2)
+                .find(|kv| layer.keys[kv[0] as usize] == key)
+                .map(|kv| layer.values[kv[1] as usize].clone())
+                .unwrap()
+        };
+
+        assert_eq!(tag(&layer.features[0], "oneway").bool_value, Some(true));
+        // keys that aren't listed are untouched
+        assert_eq!(tag(&layer.features[0], "kind"), string_value("yes"));
+        // values that aren't bool-like are untouched
+        assert_eq!(tag(&layer.features[1], "oneway"), string_value("-1"));
+    }
+
+    #[test]
+    fn test_clamp_values() {
+        let options = TransformOptions {
+            clamp_values: vec![ClampValues::parse("buildings:height:0:500").unwrap()],
+            ..Default::default()
+        };
+        let heights = [
+            Value {
+                int_value: Some(-3),
+                ..Default::default()
+            },
+            Value {
+                double_value: Some(12.5),
+                ..Default::default()
+            },
+            Value {
+                int_value: Some(999999),
+                ..Default::default()
+            },
+            Value {
+                double_value: Some(750.5),
+                ..Default::default()
+            },
+            string_value("tall"),
+        ];
+        let mut layer = make_layer(
+            "buildings",
+            4096,
+            (0..heights.len() as u32)
+                .map(|i| make_feature(Point::new(i as f64, 1.0).into(), vec![1, i + 1]))
+                .collect(),
+        );
+        layer.keys = vec!["kind".to_string(), "height".to_string()];
+        layer.values = std::iter::once(string_value("park"))
+            .chain(heights.iter().cloned())
+            .collect();
+        let mut other_layer = layer.clone();
+        other_layer.name = "roads".to_string();
+
+        let tile = run_transform(vec![layer, other_layer], &options);
+        let heights_of = |layer: &Layer| {
+            layer
+                .features
+                .iter()
+                .map(|f| layer.values[f.tags[1] as usize].clone())
+                .collect::<Vec<_>>()
+        };
+        let clamped = heights_of(&tile.layers[0]);
+        assert_eq!(clamped[0].int_value, Some(0));
+        assert_eq!(clamped[1].double_value, Some(12.5));
+        assert_eq!(clamped[2].int_value, Some(500));
+        assert_eq!(clamped[3].double_value, Some(500.0));
+        // non-numeric values are untouched
+        assert_eq!(clamped[4], string_value("tall"));
+        // other layers are untouched
+        assert_eq!(heights_of(&tile.layers[1]), heights.to_vec());
+    }
+
+    #[test]
+    fn test_parse_clamp_values() {
+        let rule = ClampValues::parse("*:height:m:-10.5:800").unwrap();
+        assert_eq!(rule.layer, "*");
+        assert_eq!(rule.key, "height:m");
+        assert_eq!((rule.min, rule.max), (-10.5, 800.0));
+        assert!(ClampValues::parse("buildings:height:0").is_err());
+        assert!(ClampValues::parse("buildings:height:500:0").is_err());
+        assert!(ClampValues::parse("buildings:height:low:500").is_err());
+        assert!(ClampValues::parse("buildings::0:500").is_err());
+    }
+
+    #[test]
+    fn test_normalize_keys_merges_collisions() {
+        let mut layer = make_layer(
+            "places",
+            4096,
+            vec![
+                make_feature(Point::new(1.0, 1.0).into(), vec![0, 0, 1, 1, 2, 2]),
+                make_feature(Point::new(2.0, 2.0).into(), vec![1, 1]),
+            ],
+        );
+        layer.keys = vec!["Name".to_string(), "name".to_string(), " Kind ".to_string()];
+        layer.values = vec![
+            string_value("Old"),
+            string_value("New"),
+            string_value("park"),
+        ];
+        let options = TransformOptions {
+            normalize_keys: vec![KeyNormalization::Lowercase, KeyNormalization::Trim],
+            boolify_keys: vec!["kind".to_string()],
+            verify_encoding: true,
+            ..Default::default()
+        };
+
+        let (tile, stats) = run_transform_with_stats(vec![layer], &options);
+        let layer = &tile.layers[0];
+        assert_eq!(layer.keys, vec!["name", "kind"]);
+        let tags = |feature: &Feature| {
+            feature
+                .tags
+                .chunks_exact(2)
+                .map(|kv| {
+                    (
+                        layer.keys[kv[0] as usize].as_str(),
+                        layer.values[kv[1] as usize].clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tags(&layer.features[0]),
+            vec![
+                ("name", string_value("New")),
+                ("kind", string_value("park"))
+            ]
+        );
+        assert_eq!(
+            tags(&layer.features[1]),
+            vec![("name", string_value("New"))]
+        );
+        assert_eq!(stats.key_collisions, 1);
+    }
+
+    #[test]
+    fn test_add_tag() {
+        let options = TransformOptions {
+            add_tags: vec![
+                AddTag::parse("*:source=myproduct").unwrap(),
+                AddTag::parse("roads:kind=\"road\"").unwrap(),
+                AddTag::parse("pois:rank=3").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let tile = run_transform(
+            vec![
+                make_layer("roads", 4096, numbered_points(2)),
+                make_layer("parks", 4096, numbered_points(1)),
+            ],
+            &options,
+        );
+
+        for layer in &tile.layers {
+            for feature in &layer.features {
+                let tags = feature
+                    .tags
+                    .chunks_exact(2)
+                    .map(|kv| {
+                        (
+                            layer.keys[kv[0] as usize].as_str(),
+                            layer.values[kv[1] as usize].clone(),
+                        )
+                    })
+                    .collect::<HashMap<_, _>>();
+                assert_eq!(tags["source"], string_value("myproduct"));
+                // existing keys are overwritten, not duplicated
+                assert_eq!(feature.tags.len(), 4);
+                let kind = if layer.name == "roads" {
+                    "road"
+                } else {
+                    "park"
+                };
+                assert_eq!(tags["kind"], string_value(kind));
+                assert!(!tags.contains_key("rank"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove_by_vertex_count() {
+        let expression = serde_json::json!([">", ["vertex-count"], 20]);
+        let options = TransformOptions {
+            remove_where: Some(
+                crate::filtering::expression_compiler::ExpressionCompiler::compile(&expression)
+                    .unwrap(),
+            ),
+            ..Default::default()
+        };
+        let square = Rect::new((100.0, 100.0), (200.0, 200.0)).to_polygon();
+        let circle = Polygon::new(
+            LineString::from(
+                (0..64)
+                    .map(|i| {
+                        let angle = i as f64 * std::f64::consts::TAU / 64.0;
+                        (2048.0 + 1000.0 * angle.cos(), 2048.0 + 1000.0 * angle.sin())
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            vec![],
+        );
+        let layer = make_layer(
+            "buildings",
+            4096,
+            vec![
+                make_feature(square.into(), vec![0, 0]),
+                make_feature(circle.into(), vec![0, 0]),
+            ],
+        );
+
+        let tile = run_transform(vec![layer], &options);
+        let features = &tile.layers[0].features;
+        assert_eq!(features.len(), 1);
+        assert!(matches!(
+            features[0].to_geo().unwrap(),
+            Geometry::Polygon(p) if p.exterior().0.len() == 5
+        ));
+    }
+
+    #[test]
+    fn test_remove_where_zoom_scaled_area() {
+        // Remove polygons smaller than 100 m² at z14, doubling the threshold per zoom out
+        let expression = serde_json::json!([
+            "all",
+            ["==", ["type"], "Polygon"],
+            ["<", ["area"], ["*", 100, ["^", 2, ["-", 14, ["zoom"]]]]]
+        ]);
+        let options = TransformOptions {
+            remove_where: Some(
+                crate::filtering::expression_compiler::ExpressionCompiler::compile(&expression)
+                    .unwrap(),
+            ),
+            ..Default::default()
+        };
+        let square = |side: f64| {
+            let polygon = geo_types::Rect::new((100.0, 100.0), (100.0 + side, 100.0 + side));
+            make_feature(polygon.to_polygon().into(), vec![0, 0])
+        };
+        // Squares of tile units just south of the equator, where a unit at zoom z is
+        // 40075 km / (2^z * 4096) wide: 0.6 m at z14, 9.6 m at z10
+        let kept_sides = |z: u8, sides: &[f64]| {
+            let n = 1 << (z - 1);
+            let coords = TileCoord::new(z, n, n).unwrap();
+            let features = sides.iter().map(|&side| square(side)).collect();
+            let tile = Tile {
+                layers: vec![make_layer("buildings", 4096, features)],
+            };
+            let (bytes, _) =
+                transform_tile(&coords, &tile.encode_to_vec(), None, &options).unwrap();
+            let tile = Tile::decode(bytes.as_slice()).unwrap();
+            tile.layers
+                .iter()
+                .flat_map(|layer| &layer.features)
+                .map(|f| {
+                    let rect = f.to_geo().unwrap().bounding_rect().unwrap();
+                    rect.width()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // z14, threshold 100 m²: 10 units is 36 m², 53 units is 1000 m²
+        assert_eq!(kept_sides(14, &[10.0, 53.0]), vec![53.0]);
+        // z10, threshold 1600 m²: 3 units is 820 m², 5 units is 2280 m²
+        assert_eq!(kept_sides(10, &[3.0, 5.0]), vec![5.0]);
+    }
+
+    #[test]
+    fn test_remove_where_command_count() {
+        let expression = serde_json::json!([">", ["command-count"], 8]);
+        let options = TransformOptions {
+            remove_where: Some(
+                crate::filtering::expression_compiler::ExpressionCompiler::compile(&expression)
+                    .unwrap(),
+            ),
+            ..Default::default()
+        };
+        let features = vec![
+            // MoveTo(1) x y: 3
+            make_feature(Point::new(1.0, 1.0).into(), vec![0, 0]),
+            // MoveTo(1) x y, LineTo(2) x y x y: 8
+            make_feature(
+                line_string![(x: 0.0, y: 0.0), (x: 5.0, y: 0.0), (x: 5.0, y: 5.0)].into(),
+                vec![0, 0],
+            ),
+            // MoveTo(1) x y, LineTo(3) x y x y x y: 10
+            make_feature(
+                line_string![(x: 0.0, y: 0.0), (x: 5.0, y: 0.0), (x: 5.0, y: 5.0), (x: 0.0, y: 5.0)]
+                    .into(),
+                vec![0, 0],
+            ),
+        ];
+        let lengths = features
+            .iter()
+            .map(|f| f.geometry.len())
+            .collect::<Vec<_>>();
+        assert_eq!(lengths, vec![3, 8, 10]);
+
+        let tile = run_transform(vec![make_layer("roads", 4096, features)], &options);
+        let lengths = tile.layers[0]
+            .features
+            .iter()
+            .map(|f| f.geometry.len())
+            .collect::<Vec<_>>();
+        assert_eq!(lengths, vec![3, 8]);
+    }
+
+    #[test]
+    fn test_remove_where() {
+        let expression = serde_json::json!(["==", ["tag", "kind"], "test"]);
+        let options = TransformOptions {
+            remove_where: Some(
+                crate::filtering::expression_compiler::ExpressionCompiler::compile(&expression)
+                    .unwrap(),
+            ),
+            ..Default::default()
+        };
+        let mixed = |name: &str| {
+            let mut layer = make_layer(
+                name,
+                4096,
+                vec![
+                    make_feature(Point::new(1.0, 1.0).into(), vec![0, 0]),
+                    make_feature(Point::new(2.0, 2.0).into(), vec![0, 1]),
+                ],
+            );
+            layer.values = vec![string_value("test"), string_value("park")];
+            layer
+        };
+        let mut only_test = make_layer(
+            "debug",
+            4096,
+            vec![make_feature(Point::new(3.0, 3.0).into(), vec![0, 0])],
+        );
+        only_test.values = vec![string_value("test")];
+
+        let tile = run_transform(vec![mixed("pois"), mixed("roads"), only_test], &options);
+
+        // Applies to every layer; layers left empty are dropped
+        let names = tile
+            .layers
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["pois", "roads"]);
+        for layer in &tile.layers {
+            assert_eq!(layer.features.len(), 1);
+            let value = layer.features[0].tags[1] as usize;
+            assert_eq!(layer.values[value], string_value("park"));
+        }
+        assert!(!leaves_tile_unchanged(
+            &TileCoord::new(0, 0, 0).unwrap(),
+            None,
+            &options
+        ));
+    }
+
+    #[test]
+    fn test_tags_matching() {
+        let mut layer = make_layer(
+            "pois",
+            4096,
+            vec![make_feature(
+                Point::new(1.0, 1.0).into(),
+                vec![0, 0, 1, 0, 2, 0, 3, 0],
+            )],
+        );
+        layer.keys = vec![
+            "name".to_string(),
+            "name:en".to_string(),
+            "addr:city".to_string(),
+            "addr:street".to_string(),
+        ];
+        let keys = |tile: &Tile| {
+            let layer = &tile.layers[0];
+            layer.features[0]
+                .tags
+                .chunks_exact(2)
+                .map(|kv| layer.keys[kv[0] as usize].clone())
+                .collect::<Vec<_>>()
+        };
+
+        let drop = TransformOptions {
+            drop_tags_matching: Some(Regex::new("^addr:").unwrap()),
+            ..Default::default()
+        };
+        let tile = run_transform(vec![layer.clone()], &drop);
+        assert_eq!(keys(&tile), vec!["name", "name:en"]);
+
+        let keep = TransformOptions {
+            keep_tags_matching: Some(Regex::new("^name").unwrap()),
+            drop_tags_matching: Some(Regex::new(":en$").unwrap()),
+            ..Default::default()
+        };
+        let tile = run_transform(vec![layer], &keep);
+        assert_eq!(keys(&tile), vec!["name"]);
+    }
+
+    #[test]
+    fn test_geometry_shape() {
+        use geo_types::{GeometryCollection, Line, Triangle};
+
+        let point = Point::new(0.0, 0.0);
+        let line = line_string![(x: 0.0, y: 0.0), (x: 1.0, y: 1.0)];
+        let square = Rect::new((0.0, 0.0), (1.0, 1.0));
+        let cases: Vec<(Geometry<f64>, &str)> = vec![
+            (point.into(), "Point"),
+            (MultiPoint::from(vec![point]).into(), "Point"),
+            (Line::new((0.0, 0.0), (1.0, 1.0)).into(), "LineString"),
+            (line.clone().into(), "LineString"),
+            (
+                MultiLineString::new(vec![line.clone()]).into(),
+                "LineString",
+            ),
+            (square.to_polygon().into(), "Polygon"),
+            (
+                MultiPolygon::new(vec![square.to_polygon()]).into(),
+                "Polygon",
+            ),
+            (square.into(), "Polygon"),
+            (
+                Triangle::new((0.0, 0.0).into(), (1.0, 0.0).into(), (0.0, 1.0).into()).into(),
+                "Polygon",
+            ),
+            (
+                Geometry::GeometryCollection(GeometryCollection::new_from(vec![
+                    point.into(),
+                    MultiPoint::from(vec![point]).into(),
+                ])),
+                "Point",
+            ),
+            (
+                Geometry::GeometryCollection(GeometryCollection::new_from(vec![
+                    point.into(),
+                    line.into(),
+                ])),
+                "Unknown",
+            ),
+            (
+                Geometry::GeometryCollection(GeometryCollection::new_from(vec![])),
+                "Unknown",
+            ),
+        ];
+        for (geom, shape) in cases {
+            assert_eq!(geometry_shape(&geom), shape, "{:?}", geom);
+        }
+    }
+
+    #[test]
+    fn test_exclude_layers_regex() {
+        let layers = [
+            "roads",
+            "debug_tiles",
+            "pois",
+            "debug_labels",
+            "roads_debug",
+        ]
+        .map(|name| make_layer(name, 4096, numbered_points(1)))
+        .to_vec();
+        let names = |tile: &Tile| {
+            tile.layers
+                .iter()
+                .map(|layer| layer.name.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        let options = TransformOptions {
+            exclude_layers_regex: Some(Regex::new("^debug_").unwrap()),
+            ..Default::default()
+        };
+        let tile = run_transform(layers.clone(), &options);
+        assert_eq!(names(&tile), "roads,pois,roads_debug");
+
+        // The layer named explicitly is kept even though the pattern matches it
+        let options = TransformOptions {
+            filter_layer: Some("debug_labels".to_string()),
+            ..options
+        };
+        let tile = run_transform(layers, &options);
+        assert_eq!(names(&tile), "roads,pois,debug_labels,roads_debug");
+    }
+
+    #[test]
+    fn test_layer_bytes() {
+        let layers = vec![
+            make_layer("roads", 4096, numbered_points(1)),
+            make_layer("pois", 4096, numbered_points(3)),
+        ];
+        let options = TransformOptions::default();
+        // Two tiles' worth of the same layers
+        let (_, mut stats) = run_transform_with_stats(layers.clone(), &options);
+        let (tile, second) = run_transform_with_stats(layers, &options);
+        stats.add(&second);
+        let [roads, pois] = [0, 1].map(|i| tile.layers[i].encoded_len() as u64);
+        assert!(pois > roads);
+
+        let layer_bytes = stats.layer_bytes;
+        assert_eq!(
+            layer_bytes,
+            BTreeMap::from([
+                ("pois".to_string(), 2 * pois),
+                ("roads".to_string(), 2 * roads),
+            ])
+        );
+        let share = |bytes: u64| bytes as f64 / (pois + roads) as f64 * 100.0;
+        assert_eq!(
+            crate::report::layer_size_lines(&layer_bytes),
+            vec![
+                format!("pois: {} bytes ({:.1}%)", 2 * pois, share(pois)),
+                format!("roads: {} bytes ({:.1}%)", 2 * roads, share(roads)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infer_fields() {
+        let layers = vec![
+            make_layer("roads", 4096, numbered_points(1)),
+            make_layer("pois", 4096, Vec::new()),
+        ];
+        let (_, stats) = run_transform_with_stats(layers.clone(), &TransformOptions::default());
+        assert!(stats.layer_fields.is_empty());
+
+        let options = TransformOptions {
+            infer_fields: true,
+            ..Default::default()
+        };
+        let (_, stats) = run_transform_with_stats(layers, &options);
+        // Empty layers are dropped before the fields are read
+        assert_eq!(
+            stats.layer_fields,
+            LayerFields::from([(
+                "roads".to_string(),
+                BTreeMap::from([("kind".to_string(), crate::metadata::FieldType::String)])
+            )])
+        );
+    }
+
+    #[test]
+    fn test_sort_layers() {
+        let layers = vec![
+            make_layer("roads", 4096, numbered_points(1)),
+            make_layer("buildings", 4096, numbered_points(2)),
+            make_layer("pois", 4096, numbered_points(3)),
+        ];
+        let names = |tile: &Tile| {
+            tile.layers
+                .iter()
+                .map(|layer| (layer.name.clone(), layer.features.len()))
+                .collect::<Vec<_>>()
+        };
+
+        let tile = run_transform(layers.clone(), &TransformOptions::default());
+        assert_eq!(names(&tile)[0].0, "roads");
+
+        let options = TransformOptions {
+            sort_layers: true,
+            verify_encoding: true,
+            ..Default::default()
+        };
+        let tile = run_transform(layers, &options);
+        assert_eq!(
+            names(&tile),
+            vec![
+                ("buildings".to_string(), 2),
+                ("pois".to_string(), 3),
+                ("roads".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_top_percent() {
+        let int_value = |v: i64| Value {
+            int_value: Some(v),
+            ..Default::default()
+        };
+        // population 10, 20, 20, 20, 40 and one feature without it
+        let mut features = [1, 2, 2, 2, 3]
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| make_feature(Point::new(i as f64, i as f64).into(), vec![0, 0, 1, v]))
+            .collect::<Vec<_>>();
+        features.push(make_feature(Point::new(5.0, 5.0).into(), vec![0, 0]));
+        let mut layer = make_layer("places", 4096, features);
+        layer.keys.push("population".to_string());
+        layer
+            .values
+            .extend([int_value(10), int_value(20), int_value(40)]);
+
+        let options = TransformOptions {
+            keep_top_percent: vec![KeepTopPercent::parse("places:population:50").unwrap()],
+            ..Default::default()
+        };
+        let tile = run_transform(vec![layer.clone()], &options);
+        // the 3rd highest value is 20, and every feature tied with it is kept
+        assert_eq!(point_xs(&tile.layers[0]), vec![1.0, 2.0, 3.0, 4.0]);
+
+        let options = TransformOptions {
+            keep_top_percent: vec![KeepTopPercent::parse("places:population:100").unwrap()],
+            ..Default::default()
+        };
+        let tile = run_transform(vec![layer.clone()], &options);
+        assert_eq!(tile.layers[0].features.len(), 6);
+
+        let options = TransformOptions {
+            keep_top_percent: vec![KeepTopPercent::parse("roads:population:10").unwrap()],
+            ..Default::default()
+        };
+        let tile = run_transform(vec![layer], &options);
+        assert_eq!(tile.layers[0].features.len(), 6);
+    }
+
+    #[test]
+    fn test_parse_keep_top_percent() {
+        let rule = KeepTopPercent::parse("places:name:en:12.5").unwrap();
+        assert_eq!(rule.layer, "places");
+        assert_eq!(rule.key, "name:en");
+        assert_eq!(rule.percent, 12.5);
+        assert!(KeepTopPercent::parse("places:population").is_err());
+        assert!(KeepTopPercent::parse("places:population:0").is_err());
+        assert!(KeepTopPercent::parse("places:population:101").is_err());
+        assert!(KeepTopPercent::parse("places::50").is_err());
+    }
+
+    #[test]
+    fn test_parse_add_tag() {
+        assert_eq!(
+            AddTag::parse("pois:rank=3").unwrap().value.int_value,
+            Some(3)
+        );
+        assert_eq!(
+            AddTag::parse("*:x=1.5").unwrap().value.double_value,
+            Some(1.5)
+        );
+        assert_eq!(
+            AddTag::parse("*:x=true").unwrap().value.bool_value,
+            Some(true)
+        );
+        assert_eq!(AddTag::parse("*:x=a=b").unwrap().value, string_value("a=b"));
+        assert!(AddTag::parse("source=x").is_err());
+        assert!(AddTag::parse("*:x=[1]").is_err());
+    }
+
+    #[test]
+    fn test_duplicate_keys_are_preserved() {
+        let mut layer = make_layer(
+            "pois",
+            4096,
+            vec![make_feature(Point::new(1.0, 1.0).into(), vec![0, 0, 0, 1])],
+        );
+        layer.values = vec![string_value("park"), string_value("school")];
+
+        let tile = run_transform(vec![layer], &TransformOptions::default());
+        let layer = &tile.layers[0];
+        let tags = layer.features[0]
+            .tags
+            .chunks_exact(2)
+            .map(|kv| {
+                (
+                    layer.keys[kv[0] as usize].as_str(),
+                    layer.values[kv[1] as usize].clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            vec![
+                ("kind", string_value("park")),
+                ("kind", string_value("school"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deserialize_options() {
+        let options: TransformOptions = serde_json::from_str(
+            r#"{"target_extent": 4096, "add_tags": ["*:source=myproduct"], "boolify_keys": ["oneway"]}"#,
+        )
+        .unwrap();
+        assert_eq!(options.target_extent, Some(4096));
+        assert_eq!(
+            options.add_tags,
+            vec![AddTag::parse("*:source=myproduct").unwrap()]
+        );
+        assert_eq!(options.boolify_keys, vec!["oneway".to_string()]);
+        // unspecified fields keep their defaults
+        assert!(options.drop_empty_layers);
+
+        assert!(serde_json::from_str::<TransformOptions>(r#"{"add_tags": ["nope"]}"#).is_err());
+        assert!(serde_json::from_str::<TransformOptions>(r#"{"unknown": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_sampling_is_reproducible() {
+        let options = TransformOptions {
+            sample_fraction: Some(0.5),
+            seed: 42,
+            ..Default::default()
+        };
+
+        let first = run_transform(
+            vec![make_layer("pois", 4096, numbered_points(200))],
+            &options,
+        );
+        let second = run_transform(
+            vec![make_layer("pois", 4096, numbered_points(200))],
+            &options,
+        );
+        let first_kept = point_xs(&first.layers[0]);
+        assert_eq!(first_kept, point_xs(&second.layers[0]));
+        assert!(first_kept.len() > 50 && first_kept.len() < 150);
+
+        let other_seed = TransformOptions {
+            seed: 7,
+            ..options.clone()
+        };
+        let third = run_transform(
+            vec![make_layer("pois", 4096, numbered_points(200))],
+            &other_seed,
+        );
+        assert_ne!(first_kept, point_xs(&third.layers[0]));
+    }
+
+    #[test]
+    fn test_project_unproject_round_trip() {
+        let geom: Geometry<f64> =
+            line_string![(x: 139.69, y: 35.69), (x: 139.75, y: 35.66), (x: 139.8, y: 35.7)].into();
+        for (coords, extent) in [
+            (TileCoord::new(0, 0, 0).unwrap(), 4096),
+            (TileCoord::new(12, 3638, 1613).unwrap(), 4096),
+            (TileCoord::new(14, 14552, 6451).unwrap(), 8192),
+        ] {
+            let projected = project_to_tile(&geom, &coords, extent);
+            let round_trip = unproject_from_tile(&projected, &coords, extent);
+            let (Geometry::LineString(expected), Geometry::LineString(actual)) =
+                (&geom, &round_trip)
+            else {
+                panic!("Expected line strings, got {:?}", round_trip);
+            };
+            for (a, b) in expected.coords().zip(actual.coords()) {
+                assert!((a.x - b.x).abs() < 1e-9, "{:?} != {:?}", a, b);
+                assert!((a.y - b.y).abs() < 1e-9, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_project_near_pole_at_z0() {
+        let extent = 4096;
+        let world: Geometry<f64> = Rect::new((-180.0, -90.0), (180.0, 90.0)).into();
+        let projected = project_to_tile(&world, &TileCoord::new(0, 0, 0).unwrap(), extent);
+
+        let rect = projected.bounding_rect().unwrap();
+        for value in [rect.min().x, rect.min().y, rect.max().x, rect.max().y] {
+            assert!(value.is_finite(), "{:?}", rect);
+        }
+        assert_eq!((rect.min().x, rect.max().x), (0.0, extent as f64));
+        assert_eq!((rect.min().y, rect.max().y), (0.0, extent as f64));
+
+        // The clamp is the edge of the world as used by tile_bounds
+        assert!((tile_y_to_lat(0.0, 1.0) - MAX_MERCATOR_LAT).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tile_bounds() {
+        let Geometry::Polygon(bounds) = tile_bounds(&TileCoord::new(1, 1, 0).unwrap()) else {
+            panic!("Expected a polygon");
+        };
+        let rect = bounds.bounding_rect().unwrap();
+        assert_eq!((rect.min().x, rect.max().x), (0.0, 180.0));
+        assert!(rect.min().y.abs() < 1e-9);
+        assert!((rect.max().y - 85.051_128_78).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_feature_extent_default_extent() {
+        let mut layer = make_layer(
+            "pois",
+            4096,
+            vec![
+                make_feature(Point::new(128.0, 128.0).into(), vec![0, 0]),
+                make_feature(Point::new(384.0, 384.0).into(), vec![0, 0]),
+            ],
+        );
+        layer.extent = None;
+        let tile = Tile {
+            layers: vec![layer],
+        };
+        let coords = TileCoord::new(0, 0, 0).unwrap();
+
+        // The middle half of a 512 unit tile
+        let [min_lon, min_lat, max_lon, max_lat] =
+            feature_extent(&tile, &coords, 512).unwrap().unwrap();
+        assert!((min_lon + 90.0).abs() < 1e-9);
+        assert!((max_lon - 90.0).abs() < 1e-9);
+        assert!(max_lat > 0.0);
+        assert!((min_lat + max_lat).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_leaves_tile_unchanged() {
+        let filter: crate::filtering::data::FilterCollection =
+            serde_json::from_value(serde_json::json!({
+                "type": "FeatureCollection",
+                "features": [{
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[139, 35], [140, 35], [140, 36], [139, 36], [139, 35]]]
+                    },
+                    "properties": { "layers": { "*": { "feature": ["literal", true] } } }
+                }]
+            }))
+            .unwrap();
+        let filter = filter.compile().unwrap();
+        let tokyo = TileCoord::new(8, 227, 100).unwrap();
+        let london = TileCoord::new(8, 127, 85).unwrap();
+        // Dropping empty layers could change any tile
+        assert!(!leaves_tile_unchanged(
+            &london,
+            None,
+            &TransformOptions::default()
+        ));
+        let options = TransformOptions {
+            drop_empty_layers: false,
+            ..Default::default()
+        };
+
+        assert!(leaves_tile_unchanged(&tokyo, None, &options));
+        assert!(!leaves_tile_unchanged(&tokyo, Some(&filter), &options));
+        assert!(leaves_tile_unchanged(&london, Some(&filter), &options));
+
+        let rescale = TransformOptions {
+            target_extent: Some(4096),
+            ..options.clone()
+        };
+        assert!(!leaves_tile_unchanged(&london, Some(&filter), &rescale));
+        let exclude_tokyo = TransformOptions {
+            exclude_bbox: Some([139.0, 35.0, 140.0, 36.0]),
+            ..options.clone()
+        };
+        assert!(!leaves_tile_unchanged(&tokyo, None, &exclude_tokyo));
+        assert!(leaves_tile_unchanged(&london, None, &exclude_tokyo));
+    }
+
+    #[test]
+    fn test_removed_features_report() {
+        let filter: crate::filtering::data::FilterCollection = serde_json::from_value(serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[-180, -89], [180, -89], [180, 89], [-180, 89], [-180, -89]]]
+                },
+                "properties": {
+                    "layers": { "*": { "feature": ["==", ["tag", "kind"], "school"] } }
+                }
+            }]
+        }))
+        .unwrap();
+        let filter = filter.compile().unwrap();
+        let mut layer = make_layer(
+            "pois",
+            4096,
+            vec![
+                make_feature(Point::new(2048.0, 2048.0).into(), vec![0, 0]),
+                make_feature(Point::new(1024.0, 1024.0).into(), vec![0, 1]),
+            ],
+        );
+        layer.values = vec![string_value("park"), string_value("school")];
+        let data = Tile {
+            layers: vec![layer],
+        }
+        .encode_to_vec();
+        let coords = TileCoord::new(0, 0, 0).unwrap();
+
+        let mut removed = Vec::new();
+        let (bytes, _) = transform_tile_reporting_removed(
+            &coords,
+            &data,
+            Some(&filter),
+            &TransformOptions::default(),
+            &mut removed,
+        )
+        .unwrap();
+        assert_eq!(
+            Tile::decode(bytes.as_slice()).unwrap().layers[0]
+                .features
+                .len(),
+            1
+        );
+
+        assert_eq!(removed.len(), 1);
+        let report = &removed[0];
+        assert_eq!(report.property("kind"), Some(&serde_json::json!("school")));
+        assert_eq!(report.foreign_members.as_ref().unwrap()["layer"], "pois");
+        match &report.geometry.as_ref().unwrap().value {
+            geojson::Value::Point(position) => {
+                // a quarter of the way into the world tile
+                assert!((position[0] - -90.0).abs() < 1e-9);
+                assert!((position[1] - 66.513_260_443).abs() < 1e-6);
+            }
+            other => panic!("Expected a point, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_filter_layer_passes_other_layers_through() {
+        let filter: crate::filtering::data::FilterCollection =
+            serde_json::from_value(serde_json::json!({
+                "type": "FeatureCollection",
+                "features": [{
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[-180, -89], [180, -89], [180, 89], [-180, 89], [-180, -89]]]
+                    },
+                    "properties": {
+                        "layers": { "*": { "feature": ["==", ["tag", "kind"], "park"] } }
+                    }
+                }]
+            }))
+            .unwrap();
+        let filter = filter.compile().unwrap();
+        let pois = make_layer("pois", 512, numbered_points(3));
+        let data = Tile {
+            layers: vec![make_layer("roads", 4096, numbered_points(3)), pois.clone()],
+        }
+        .encode_to_vec();
+        let options = TransformOptions {
+            filter_layer: Some("roads".to_string()),
+            target_extent: Some(4096),
+            verify_encoding: true,
+            ..Default::default()
+        };
+
+        let coords = TileCoord::new(0, 0, 0).unwrap();
+        let (bytes, _) = transform_tile(&coords, &data, Some(&filter), &options).unwrap();
+        let tile = Tile::decode(bytes.as_slice()).unwrap();
+        // Every road is a park, so the layer is filtered away; pois are left as they were
+        assert_eq!(tile.layers.len(), 1);
+        assert_eq!(tile.layers[0].encode_to_vec(), pois.encode_to_vec());
+    }
+
+    #[test]
+    fn test_default_extent_is_used_for_projection() {
+        // Removes parks in the western half of the world
+        let filter: crate::filtering::data::FilterCollection =
+            serde_json::from_value(serde_json::json!({
+                "type": "FeatureCollection",
+                "features": [{
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[-180, -89], [0, -89], [0, 89], [-180, 89], [-180, -89]]]
+                    },
+                    "properties": {
+                        "layers": { "*": { "feature": ["==", ["tag", "kind"], "park"] } }
+                    }
+                }]
+            }))
+            .unwrap();
+        let filter = filter.compile().unwrap();
+        // x = 300 is in the eastern half of a 512-extent tile, but the western half of
+        // a 4096-extent one
+        let mut layer = make_layer(
+            "pois",
+            4096,
+            vec![make_feature(Point::new(300.0, 100.0).into(), vec![0, 0])],
+        );
+        layer.extent = None;
+        let data = Tile {
+            layers: vec![layer],
+        }
+        .encode_to_vec();
+        let coords = TileCoord::new(0, 0, 0).unwrap();
+        let remaining = |options: &TransformOptions| {
+            let (bytes, _) = transform_tile(&coords, &data, Some(&filter), options).unwrap();
+            let tile = Tile::decode(bytes.as_slice()).unwrap();
+            tile.layers.iter().map(|l| l.features.len()).sum::<usize>()
+        };
+
+        assert_eq!(remaining(&TransformOptions::default()), 0);
+        let options = TransformOptions {
+            default_extent: 512,
+            ..Default::default()
+        };
+        assert_eq!(remaining(&options), 1);
+    }
+
+    #[test]
+    fn test_drop_empty_layers() {
+        let filter: crate::filtering::data::FilterCollection = serde_json::from_value(serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[-180, -89], [180, -89], [180, 89], [-180, 89], [-180, -89]]]
+                },
+                "properties": {
+                    "layers": { "roads": { "feature": ["==", ["tag", "kind"], "park"] } }
+                }
+            }]
+        }))
+        .unwrap();
+        let filter = filter.compile().unwrap();
+        let layers = || {
+            vec![
+                make_layer("roads", 4096, numbered_points(3)),
+                make_layer("pois", 4096, numbered_points(3)),
+            ]
+        };
+        let coords = TileCoord::new(0, 0, 0).unwrap();
+
+        let options = TransformOptions::default();
+        let data = Tile { layers: layers() }.encode_to_vec();
+        let (bytes, _) = transform_tile(&coords, &data, Some(&filter), &options).unwrap();
+        let tile = Tile::decode(bytes.as_slice()).unwrap();
+        let names = tile
+            .layers
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["pois"]);
+
+        let keep_empty = TransformOptions {
+            drop_empty_layers: false,
+            ..Default::default()
+        };
+        let (kept, _) = transform_tile(&coords, &data, Some(&filter), &keep_empty).unwrap();
+        let tile = Tile::decode(kept.as_slice()).unwrap();
+        assert_eq!(tile.layers.len(), 2);
+        assert!(tile.layers[0].features.is_empty());
+
+        // a tile that loses every layer encodes to nothing
+        let data = Tile {
+            layers: vec![make_layer("roads", 4096, numbered_points(3))],
+        }
+        .encode_to_vec();
+        let (bytes, _) = transform_tile(&coords, &data, Some(&filter), &options).unwrap();
+        assert!(bytes.is_empty());
+    }
 }
@@ -1,10 +1,22 @@
 use anyhow::Result;
+use futures::TryStreamExt as _;
+use geo::BoundingRect as _;
+use geozero::mvt::tile::{self, Feature, GeomType, Layer};
+use geozero::mvt::{Message as _, Tile};
+use pmtiles::{AsyncPmTilesReader, PmTilesWriter, TileCoord, TileId, TileType};
 use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
+use std::io::Read as _;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{Arc, Mutex};
 
-use mvt_wrangler::{Args, run};
+use mvt_wrangler::{
+    AddTag, Args, Codec, FilterCollection, ProgressCallback, ProgressEvent, RunOptions,
+    TransformOptions, WranglerError, run, run_with_options, tile_bounds, transformed_tiles,
+    unproject_from_tile,
+};
 
 /// Helper function to export PMTiles to GeoJSON using ogr2ogr
 async fn export_pmtiles_to_geojson(
@@ -89,9 +101,7 @@ async fn test_mvt_filtering_integration() {
         input: PathBuf::from(input_pmtiles_path),
         output: PathBuf::from(output_path),
         filter: Some(PathBuf::from(filter_geojson_path)),
-        name: None,
-        description: None,
-        attribution: None,
+        ..Default::default()
     };
     let result = run(args).await;
     assert!(result.is_ok(), "Integration test failed: {:?}", result);
@@ -135,3 +145,2040 @@ async fn test_mvt_filtering_integration() {
         verification_result
     );
 }
+
+/// A feature's encoded geometry and its resolved tags
+type DecodedFeature = (Vec<u32>, BTreeMap<String, String>);
+
+/// Decode a gzipped MVT tile into a comparable form, independent of tag table ordering
+fn decoded_tile(data: &[u8]) -> Vec<(String, Vec<DecodedFeature>)> {
+    let mut raw = Vec::new();
+    flate2::read::GzDecoder::new(data)
+        .read_to_end(&mut raw)
+        .unwrap();
+    let tile = Tile::decode(raw.as_slice()).unwrap();
+    tile.layers
+        .iter()
+        .map(|layer| {
+            let features = layer
+                .features
+                .iter()
+                .map(|f| {
+                    let tags = f
+                        .tags
+                        .chunks_exact(2)
+                        .map(|kv| {
+                            (
+                                layer.keys[kv[0] as usize].clone(),
+                                format!("{:?}", layer.values[kv[1] as usize]),
+                            )
+                        })
+                        .collect();
+                    (f.geometry.clone(), tags)
+                })
+                .collect();
+            (layer.name.clone(), features)
+        })
+        .collect()
+}
+
+fn load_filter(path: &str) -> FilterCollection {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_transformed_tiles_stream_matches_run() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let filter_geojson_path = "tests/fixtures/filter.geojson";
+    let output_path = std::env::temp_dir().join("mvt_wrangler_stream_compare.pmtiles");
+
+    let args = Args {
+        input: PathBuf::from(input_pmtiles_path),
+        output: output_path.clone(),
+        filter: Some(PathBuf::from(filter_geojson_path)),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+
+    for ordered in [true, false] {
+        let filter = load_filter(filter_geojson_path).compile().unwrap();
+        let stream = transformed_tiles(
+            Path::new(input_pmtiles_path),
+            Some(filter),
+            TransformOptions::default(),
+            ordered,
+        )
+        .await
+        .unwrap();
+        let tiles = stream
+            .map_ok(|(coord, data)| ((coord.z(), coord.x(), coord.y()), (coord, data)))
+            .try_collect::<BTreeMap<_, _>>()
+            .await
+            .unwrap();
+
+        assert!(!tiles.is_empty());
+        for (coord, data) in tiles.values() {
+            let written = out_pmt.get_tile(*coord).await.unwrap().unwrap();
+            assert_eq!(decoded_tile(&written), decoded_tile(data));
+        }
+    }
+
+    fs::remove_file(&output_path).unwrap();
+}
+
+/// Encode a tile with a single point feature in the given layer
+fn point_tile(layer_name: &str) -> Vec<u8> {
+    Tile {
+        layers: vec![Layer {
+            version: 2,
+            name: layer_name.to_string(),
+            features: vec![Feature {
+                id: None,
+                tags: vec![0, 0],
+                r#type: Some(GeomType::Point as i32),
+                // MoveTo(1) (10, 10)
+                geometry: vec![9, 20, 20],
+            }],
+            keys: vec!["kind".to_string()],
+            values: vec![tile::Value {
+                string_value: Some("park".to_string()),
+                ..Default::default()
+            }],
+            extent: Some(4096),
+        }],
+    }
+    .encode_to_vec()
+}
+
+/// Write a synthetic archive with the given (already compressed) tile bytes
+fn write_archive(path: &Path, builder: PmTilesWriter, tiles: &[(TileCoord, Vec<u8>)]) {
+    let file = fs::File::create(path).unwrap();
+    let mut writer = builder.create(file).unwrap();
+    for (coord, data) in tiles {
+        writer.add_raw_tile(*coord, data).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+#[tokio::test]
+async fn test_corrupt_tile_returns_tile_decode_error() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_corrupt_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_corrupt_output.pmtiles");
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &[(TileCoord::new(0, 0, 0).unwrap(), b"\x89PNG\r\n".to_vec())],
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        ..Default::default()
+    };
+    match run(args).await {
+        Err(WranglerError::TileDecode { coord, .. }) => assert_eq!(coord, "0/0/0"),
+        other => panic!("Expected TileDecode error, got {:?}", other),
+    }
+
+    fs::remove_file(&input_path).unwrap();
+    let _ = fs::remove_file(&output_path);
+}
+
+#[tokio::test]
+async fn test_failed_run_keeps_previous_output() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_atomic_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_atomic_output.pmtiles");
+    let partial_path = output_path.with_extension("pmtiles.tmp");
+    // The second tile can't be decoded, so the run fails after writing the first
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &[
+            (TileCoord::new(1, 0, 0).unwrap(), point_tile("pois")),
+            (TileCoord::new(1, 1, 0).unwrap(), b"\x89PNG\r\n".to_vec()),
+        ],
+    );
+    fs::write(&output_path, b"previous output").unwrap();
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        ..Default::default()
+    };
+    assert!(run(args).await.is_err());
+    assert_eq!(fs::read(&output_path).unwrap(), b"previous output");
+    assert!(!partial_path.exists(), "the partial archive is removed");
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_output_compression_none_from_gzip_input() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let output_path = std::env::temp_dir().join("mvt_wrangler_uncompressed.pmtiles");
+
+    let in_pmt = AsyncPmTilesReader::new_with_path(input_pmtiles_path)
+        .await
+        .unwrap();
+    assert_eq!(
+        in_pmt.get_header().tile_compression,
+        pmtiles::Compression::Gzip
+    );
+
+    let args = Args {
+        input: PathBuf::from(input_pmtiles_path),
+        output: output_path.clone(),
+        output_compression: Some(Codec::None),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = Arc::new(
+        AsyncPmTilesReader::new_with_path(&output_path)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(
+        out_pmt.get_header().tile_compression,
+        pmtiles::Compression::None
+    );
+
+    let entries = out_pmt
+        .clone()
+        .entries()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    let coord = entries[0].iter_coords().next().unwrap();
+    let data = out_pmt.get_tile(coord).await.unwrap().unwrap();
+    assert_ne!(&data[..2], &[0x1f, 0x8b], "tile is still gzipped");
+    let tile = Tile::decode(data.as_ref()).unwrap();
+    assert!(!tile.layers.is_empty());
+
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_strict_rejects_tiles_outside_zoom_range() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_zoom_range_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_zoom_range_output.pmtiles");
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt)
+            .tile_compression(pmtiles::Compression::None)
+            .min_zoom(0)
+            .max_zoom(2),
+        &[
+            (TileCoord::new(0, 0, 0).unwrap(), point_tile("pois")),
+            (TileCoord::new(5, 3, 4).unwrap(), point_tile("pois")),
+        ],
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        ..Default::default()
+    };
+    assert!(run(args).await.is_ok(), "non-strict runs only warn");
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        strict: true,
+        ..Default::default()
+    };
+    let err = run(args).await.unwrap_err();
+    assert!(err.to_string().contains("5/3/4"), "{}", err);
+
+    fs::remove_file(&input_path).unwrap();
+    let _ = fs::remove_file(&output_path);
+}
+
+#[tokio::test]
+async fn test_events_ndjson() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let output_path = std::env::temp_dir().join("mvt_wrangler_events.pmtiles");
+    let events_path = std::env::temp_dir().join("mvt_wrangler_events.ndjson");
+
+    let args = Args {
+        input: PathBuf::from(input_pmtiles_path),
+        output: output_path.clone(),
+        filter: Some(PathBuf::from("tests/fixtures/filter.geojson")),
+        events: Some(events_path.clone()),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let count_tiles = async |path: &Path| {
+        let reader = Arc::new(AsyncPmTilesReader::new_with_path(path).await.unwrap());
+        let entries = reader.entries().try_collect::<Vec<_>>().await.unwrap();
+        entries.iter().flat_map(|e| e.iter_coords()).count()
+    };
+    let input_tiles = count_tiles(Path::new(input_pmtiles_path)).await;
+    let output_tiles = count_tiles(&output_path).await;
+
+    let events = fs::read_to_string(&events_path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    let count_outcome = |outcome: &str| events.iter().filter(|e| e["outcome"] == outcome).count();
+
+    assert_eq!(events.len(), input_tiles);
+    assert_eq!(count_outcome("processed"), output_tiles);
+    assert_eq!(count_outcome("skipped"), input_tiles - output_tiles);
+    assert_eq!(count_outcome("error"), 0);
+    assert!(events.iter().all(|e| e["tile"].as_str().unwrap().split('/').count() == 3
+        && e["timestamp"].is_u64()));
+
+    fs::remove_file(&output_path).unwrap();
+    fs::remove_file(&events_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_reorder_window_writes_same_tiles() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let ordered_path = std::env::temp_dir().join("mvt_wrangler_reorder_ordered.pmtiles");
+    let windowed_path = std::env::temp_dir().join("mvt_wrangler_reorder_windowed.pmtiles");
+
+    for (path, reorder_window) in [(&ordered_path, None), (&windowed_path, Some(0))] {
+        let args = Args {
+            input: PathBuf::from(input_pmtiles_path),
+            output: path.clone(),
+            reorder_window,
+            ..Default::default()
+        };
+        run(args).await.unwrap();
+    }
+
+    let ordered = Arc::new(
+        AsyncPmTilesReader::new_with_path(&ordered_path)
+            .await
+            .unwrap(),
+    );
+    let windowed = Arc::new(
+        AsyncPmTilesReader::new_with_path(&windowed_path)
+            .await
+            .unwrap(),
+    );
+
+    let coords = |reader: Arc<AsyncPmTilesReader<_>>| async move {
+        let entries = reader.entries().try_collect::<Vec<_>>().await.unwrap();
+        entries
+            .iter()
+            .flat_map(|e| e.iter_coords())
+            .collect::<Vec<_>>()
+    };
+    let ordered_coords = coords(ordered.clone()).await;
+    assert_eq!(ordered_coords, coords(windowed.clone()).await);
+
+    for coord in ordered_coords {
+        let expected = ordered.get_tile(coord).await.unwrap().unwrap();
+        let actual = windowed.get_tile(coord).await.unwrap().unwrap();
+        assert_eq!(decoded_tile(&expected), decoded_tile(&actual));
+    }
+
+    fs::remove_file(&ordered_path).unwrap();
+    fs::remove_file(&windowed_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_autotune_writes_same_tiles() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let default_path = std::env::temp_dir().join("mvt_wrangler_autotune_default.pmtiles");
+    let autotuned_path = std::env::temp_dir().join("mvt_wrangler_autotune_tuned.pmtiles");
+
+    for (path, autotune) in [(&default_path, None), (&autotuned_path, Some(8))] {
+        let args = Args {
+            input: PathBuf::from(input_pmtiles_path),
+            output: path.clone(),
+            filter: Some(PathBuf::from("tests/fixtures/filter.geojson")),
+            autotune,
+            ..Default::default()
+        };
+        run(args).await.unwrap();
+    }
+
+    let expected = Arc::new(
+        AsyncPmTilesReader::new_with_path(&default_path)
+            .await
+            .unwrap(),
+    );
+    let actual = AsyncPmTilesReader::new_with_path(&autotuned_path)
+        .await
+        .unwrap();
+    let entries = expected
+        .clone()
+        .entries()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    for coord in entries.iter().flat_map(|e| e.iter_coords()) {
+        let expected = expected.get_tile(coord).await.unwrap().unwrap();
+        let actual = actual.get_tile(coord).await.unwrap().unwrap();
+        assert_eq!(decoded_tile(&expected), decoded_tile(&actual));
+    }
+
+    fs::remove_file(&default_path).unwrap();
+    fs::remove_file(&autotuned_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_sharded_output_matches_single_writer() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let single_path = std::env::temp_dir().join("mvt_wrangler_shards_single.pmtiles");
+    let sharded_path = std::env::temp_dir().join("mvt_wrangler_shards_merged.pmtiles");
+
+    for (path, shards) in [(&single_path, None), (&sharded_path, Some(3))] {
+        let args = Args {
+            input: PathBuf::from(input_pmtiles_path),
+            output: path.clone(),
+            filter: Some(PathBuf::from("tests/fixtures/filter.geojson")),
+            shards,
+            ..Default::default()
+        };
+        run(args).await.unwrap();
+    }
+    for i in 0..3 {
+        let shard_path = sharded_path.with_extension(format!("pmtiles.shard{i}.tmp"));
+        assert!(!shard_path.exists(), "shard archives are removed");
+    }
+
+    let expected = Arc::new(
+        AsyncPmTilesReader::new_with_path(&single_path)
+            .await
+            .unwrap(),
+    );
+    let actual = Arc::new(
+        AsyncPmTilesReader::new_with_path(&sharded_path)
+            .await
+            .unwrap(),
+    );
+    let coords = |reader: Arc<AsyncPmTilesReader<_>>| async move {
+        let entries = reader.entries().try_collect::<Vec<_>>().await.unwrap();
+        entries
+            .iter()
+            .flat_map(|e| e.iter_coords())
+            .collect::<Vec<_>>()
+    };
+    let expected_coords = coords(expected.clone()).await;
+    assert_eq!(expected_coords, coords(actual.clone()).await);
+    for coord in expected_coords {
+        let expected = expected.get_tile(coord).await.unwrap().unwrap();
+        let actual = actual.get_tile(coord).await.unwrap().unwrap();
+        assert!(expected == actual, "tile {:?} differs", coord);
+    }
+
+    fs::remove_file(&single_path).unwrap();
+    fs::remove_file(&sharded_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_sharded_progress_counts_the_whole_run() {
+    let output_path = std::env::temp_dir().join("mvt_wrangler_shards_progress.pmtiles");
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let progress: ProgressCallback = Arc::new(move |event: ProgressEvent| {
+        recorded.lock().unwrap().push(event);
+    });
+    let args = Args {
+        input: PathBuf::from("tests/fixtures/input.pmtiles"),
+        output: output_path.clone(),
+        shards: Some(3),
+        ..Default::default()
+    };
+    let options = RunOptions {
+        progress: Some(progress),
+    };
+    run_with_options(args, options).await.unwrap();
+
+    let events = events.lock().unwrap();
+    let total = events[0].total;
+    assert_eq!(events.len(), total);
+    for (n, event) in events.iter().enumerate() {
+        assert_eq!(event.processed, n + 1);
+        assert_eq!(event.total, total);
+        assert!(event.read >= event.transformed, "{event:?}");
+        assert!(event.transformed >= event.processed, "{event:?}");
+    }
+    let last = events.last().unwrap();
+    assert_eq!([last.read, last.transformed], [total; 2]);
+
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_shards_reject_whole_run_options() {
+    let output_path = std::env::temp_dir().join("mvt_wrangler_shards_rejected.pmtiles");
+    let manifest_path = std::env::temp_dir().join("mvt_wrangler_shards_rejected.csv");
+
+    // Built directly, so clap's conflicts don't apply
+    let args = Args {
+        input: PathBuf::from("tests/fixtures/input.pmtiles"),
+        output: output_path.clone(),
+        shards: Some(2),
+        checksum_manifest: Some(manifest_path.clone()),
+        ..Default::default()
+    };
+    let err = run(args).await.unwrap_err();
+    assert!(matches!(err, WranglerError::Config(_)), "{err:?}");
+    assert!(!manifest_path.exists());
+    assert!(!output_path.exists());
+}
+
+#[tokio::test]
+async fn test_fast_mode_writes_same_tiles() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let ordered_path = std::env::temp_dir().join("mvt_wrangler_fast_ordered.pmtiles");
+    let fast_path = std::env::temp_dir().join("mvt_wrangler_fast_unordered.pmtiles");
+
+    for (path, fast) in [(&ordered_path, false), (&fast_path, true)] {
+        let args = Args {
+            input: PathBuf::from(input_pmtiles_path),
+            output: path.clone(),
+            fast,
+            ..Default::default()
+        };
+        run(args).await.unwrap();
+    }
+
+    let ordered = Arc::new(
+        AsyncPmTilesReader::new_with_path(&ordered_path)
+            .await
+            .unwrap(),
+    );
+    let fast = Arc::new(AsyncPmTilesReader::new_with_path(&fast_path).await.unwrap());
+
+    let coords = |reader: Arc<AsyncPmTilesReader<_>>| async move {
+        let entries = reader.entries().try_collect::<Vec<_>>().await.unwrap();
+        entries
+            .iter()
+            .flat_map(|e| e.iter_coords())
+            .collect::<HashSet<_>>()
+    };
+    let ordered_coords = coords(ordered.clone()).await;
+    let fast_coords = coords(fast.clone()).await;
+    assert!(!ordered_coords.is_empty());
+    assert_eq!(ordered_coords, fast_coords);
+
+    for coord in ordered_coords {
+        let expected = ordered.get_tile(coord).await.unwrap().unwrap();
+        let actual = fast.get_tile(coord).await.unwrap().unwrap();
+        assert_eq!(decoded_tile(&expected), decoded_tile(&actual));
+    }
+
+    fs::remove_file(&ordered_path).unwrap();
+    fs::remove_file(&fast_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_unsorted_writes_every_tile() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_unsorted_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_unsorted_output.pmtiles");
+    let coords = [
+        (0, 0, 0),
+        (1, 0, 0),
+        (1, 1, 0),
+        (1, 1, 1),
+        (2, 3, 1),
+        (2, 0, 2),
+    ]
+    .map(|(z, x, y)| TileCoord::new(z, x, y).unwrap());
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &coords.map(|coord| (coord, point_tile("pois"))),
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        output_compression: Some(Codec::Gzip),
+        unsorted: true,
+        fast: true,
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    for coord in coords {
+        let data = out_pmt.get_tile(coord).await.unwrap().unwrap();
+        assert_eq!(decoded_tile(&data)[0].0, "pois", "{:?}", coord);
+    }
+
+    // Merging needs the sorted coordinate map
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        merge: vec![input_path.clone()],
+        unsorted: true,
+        ..Default::default()
+    };
+    assert!(run(args).await.is_err());
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_directory_output_writes_tile_files() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_directory_input.pmtiles");
+    let archive_path = std::env::temp_dir().join("mvt_wrangler_directory_output.pmtiles");
+    let directory_path = std::env::temp_dir().join("mvt_wrangler_directory_output");
+    let _ = fs::remove_dir_all(&directory_path);
+    let coords =
+        [(0, 0, 0), (1, 1, 0), (2, 3, 1)].map(|(z, x, y)| TileCoord::new(z, x, y).unwrap());
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt)
+            .tile_compression(pmtiles::Compression::None)
+            .min_zoom(0)
+            .max_zoom(2),
+        &coords.map(|coord| (coord, point_tile("pois"))),
+    );
+
+    // A trailing separator asks for a directory
+    let mut directory_arg = directory_path.clone().into_os_string();
+    directory_arg.push("/");
+    for output in [archive_path.clone(), PathBuf::from(directory_arg)] {
+        let args = Args {
+            input: input_path.clone(),
+            output,
+            output_compression: Some(Codec::Gzip),
+            ..Default::default()
+        };
+        run(args).await.unwrap();
+    }
+
+    // The same bytes as the archive stores, at z/x/y.pbf
+    let out_pmt = AsyncPmTilesReader::new_with_path(&archive_path)
+        .await
+        .unwrap();
+    for coord in coords {
+        let path = directory_path.join(format!("{}/{}/{}.pbf", coord.z(), coord.x(), coord.y()));
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data, out_pmt.get_tile(coord).await.unwrap().unwrap());
+        assert_eq!(decoded_tile(&data)[0].0, "pois");
+    }
+    let metadata: Value =
+        serde_json::from_str(&fs::read_to_string(directory_path.join("metadata.json")).unwrap())
+            .unwrap();
+    assert_eq!(metadata["format"], "pbf");
+    assert_eq!(metadata["compression"], "gzip");
+    assert_eq!(metadata["minzoom"], 0);
+    assert_eq!(metadata["maxzoom"], 2);
+
+    // The directory now holds the first run's tiles
+    let args = || Args {
+        input: input_path.clone(),
+        output: directory_path.clone(),
+        ..Default::default()
+    };
+    let err = run(args()).await.unwrap_err();
+    assert!(matches!(err, WranglerError::Config(_)), "{err:?}");
+    run(Args {
+        overwrite: true,
+        ..args()
+    })
+    .await
+    .unwrap();
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&archive_path).unwrap();
+    fs::remove_dir_all(&directory_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_batch_by_zoom_writes_same_archive() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let unbatched_path = std::env::temp_dir().join("mvt_wrangler_unbatched.pmtiles");
+    let batched_path = std::env::temp_dir().join("mvt_wrangler_batched.pmtiles");
+
+    for (path, batch_by_zoom) in [(&unbatched_path, false), (&batched_path, true)] {
+        let args = Args {
+            input: PathBuf::from(input_pmtiles_path),
+            output: path.clone(),
+            filter: Some(PathBuf::from("tests/fixtures/filter.geojson")),
+            batch_by_zoom,
+            ..Default::default()
+        };
+        run(args).await.unwrap();
+    }
+
+    // Tiles are written in the same order either way, so the archives are identical
+    assert_eq!(
+        fs::read(&unbatched_path).unwrap(),
+        fs::read(&batched_path).unwrap()
+    );
+
+    fs::remove_file(&unbatched_path).unwrap();
+    fs::remove_file(&batched_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_checksum_manifest_lists_every_tile() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let output_path = std::env::temp_dir().join("mvt_wrangler_manifest.pmtiles");
+    let manifest_paths =
+        [1, 2].map(|run| std::env::temp_dir().join(format!("mvt_wrangler_manifest_{run}.csv")));
+
+    for manifest_path in &manifest_paths {
+        let args = Args {
+            input: PathBuf::from(input_pmtiles_path),
+            output: output_path.clone(),
+            checksum_manifest: Some(manifest_path.clone()),
+            ..Default::default()
+        };
+        run(args).await.unwrap();
+    }
+
+    let manifest = fs::read_to_string(&manifest_paths[0]).unwrap();
+    assert_eq!(manifest, fs::read_to_string(&manifest_paths[1]).unwrap());
+
+    let mut lines = manifest.lines();
+    assert_eq!(lines.next(), Some("tile,xxhash64"));
+    let rows = lines
+        .map(|line| line.split_once(',').unwrap())
+        .collect::<Vec<_>>();
+
+    let reader = Arc::new(
+        AsyncPmTilesReader::new_with_path(&output_path)
+            .await
+            .unwrap(),
+    );
+    let entries = reader
+        .clone()
+        .entries()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    let coords = entries
+        .iter()
+        .flat_map(|e| e.iter_coords())
+        .collect::<Vec<_>>();
+    assert_eq!(rows.len(), coords.len());
+    for ((tile, hash), coord) in rows.iter().zip(coords) {
+        let coord = TileCoord::from(coord);
+        assert_eq!(*tile, format!("{}/{}/{}", coord.z(), coord.x(), coord.y()));
+        assert_eq!(hash.len(), 16);
+    }
+
+    fs::remove_file(&output_path).unwrap();
+    for manifest_path in &manifest_paths {
+        fs::remove_file(manifest_path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_input_compression_override_reads_mislabeled_archive() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_mislabeled_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_mislabeled_output.pmtiles");
+    // The header declares gzip, but the tile is stored uncompressed
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::Gzip),
+        &[(TileCoord::new(0, 0, 0).unwrap(), point_tile("pois"))],
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        ..Default::default()
+    };
+    assert!(run(args).await.is_err());
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        input_compression: Some(Codec::None),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    let data = out_pmt
+        .get_tile(TileCoord::new(0, 0, 0).unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    let layers = decoded_tile(&data);
+    assert_eq!(layers.len(), 1);
+    assert_eq!(layers[0].0, "pois");
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_input_compression_override_reads_gzip_labeled_none() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_mislabeled_gzip_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_mislabeled_gzip_output.pmtiles");
+    // The header declares no compression, but the tile is stored gzipped
+    let mut stored = Vec::new();
+    {
+        let mut encoder = flate2::write::GzEncoder::new(&mut stored, flate2::Compression::best());
+        std::io::Write::write_all(&mut encoder, &point_tile("pois")).unwrap();
+        encoder.finish().unwrap();
+    }
+    let coord = TileCoord::new(0, 0, 0).unwrap();
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &[(coord, stored)],
+    );
+
+    // Going by the header, the gzip bytes are handed to the MVT decoder as they are
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        run(args).await,
+        Err(WranglerError::TileDecode { .. })
+    ));
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        input_compression: Some(Codec::Gzip),
+        output_compression: Some(Codec::Gzip),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    let layers = decoded_tile(&out_pmt.get_tile(coord).await.unwrap().unwrap());
+    assert_eq!(layers.len(), 1);
+    assert_eq!(layers[0].0, "pois");
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_directory_compression_none() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let output_path = std::env::temp_dir().join("mvt_wrangler_directory_none.pmtiles");
+
+    let args = Args {
+        input: PathBuf::from(input_pmtiles_path),
+        output: output_path.clone(),
+        directory_compression: Codec::None,
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    // The header's internal compression isn't exposed by the reader, so check the raw
+    // header: byte 97 is the internal compression (1 = none), 98 the tile compression
+    // (2 = gzip).
+    let header = fs::read(&output_path).unwrap();
+    assert_eq!(&header[..7], b"PMTiles");
+    assert_eq!(header[97], 1);
+    assert_eq!(header[98], 2);
+
+    // Uncompressed directories must still be readable
+    let out_pmt = Arc::new(
+        AsyncPmTilesReader::new_with_path(&output_path)
+            .await
+            .unwrap(),
+    );
+    let entries = out_pmt
+        .clone()
+        .entries()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert!(!entries.is_empty());
+
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_merge_combines_overlapping_tiles() {
+    let first_path = std::env::temp_dir().join("mvt_wrangler_merge_first.pmtiles");
+    let second_path = std::env::temp_dir().join("mvt_wrangler_merge_second.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_merge_output.pmtiles");
+    let shared = TileCoord::new(1, 0, 0).unwrap();
+    let only_second = TileCoord::new(1, 1, 1).unwrap();
+    let builder = || PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None);
+    write_archive(&first_path, builder(), &[(shared, point_tile("pois"))]);
+    write_archive(
+        &second_path,
+        builder(),
+        &[
+            (shared, point_tile("pois")),
+            (only_second, point_tile("roads")),
+        ],
+    );
+
+    let args = Args {
+        input: first_path.clone(),
+        output: output_path.clone(),
+        merge: vec![second_path.clone()],
+        output_compression: Some(Codec::Gzip),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    let layers = decoded_tile(&out_pmt.get_tile(shared).await.unwrap().unwrap());
+    assert_eq!(layers.len(), 1);
+    assert_eq!(layers[0].0, "pois");
+    assert_eq!(layers[0].1.len(), 2, "features from both inputs");
+
+    let layers = decoded_tile(&out_pmt.get_tile(only_second).await.unwrap().unwrap());
+    assert_eq!(layers[0].0, "roads");
+
+    for path in [&first_path, &second_path, &output_path] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_filter_dir_merges_every_file() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_filter_dir_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_filter_dir_output.pmtiles");
+    let filter_dir = std::env::temp_dir().join("mvt_wrangler_filter_dir");
+    let (pois, roads, water) = (
+        TileCoord::new(1, 0, 0).unwrap(),
+        TileCoord::new(1, 1, 0).unwrap(),
+        TileCoord::new(1, 1, 1).unwrap(),
+    );
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &[
+            (pois, point_tile("pois")),
+            (roads, point_tile("roads")),
+            (water, point_tile("water")),
+        ],
+    );
+    fs::create_dir_all(&filter_dir).unwrap();
+    let filter = |layer: &str| {
+        format!(
+            r#"{{
+              "type": "FeatureCollection",
+              "features": [{{
+                "type": "Feature",
+                "geometry": {{"type": "Polygon", "coordinates": [[[-180, -89], [180, -89], [180, 89], [-180, 89], [-180, -89]]]}},
+                "properties": {{"layers": {{"{layer}": {{"feature": ["==", ["tag", "kind"], "park"]}}}}}}
+              }}]
+            }}"#
+        )
+    };
+    fs::write(filter_dir.join("pois.geojson"), filter("pois")).unwrap();
+    // Scoped to `roads` by its file name, so `water` is left alone
+    fs::write(filter_dir.join("roads.geojson"), filter("*")).unwrap();
+    fs::write(filter_dir.join("notes.txt"), "not a filter").unwrap();
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        filter_dir: Some(filter_dir.clone()),
+        scope_by_filename: true,
+        output_compression: Some(Codec::Gzip),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    assert!(out_pmt.get_tile(pois).await.unwrap().is_none());
+    assert!(out_pmt.get_tile(roads).await.unwrap().is_none());
+    let layers = decoded_tile(&out_pmt.get_tile(water).await.unwrap().unwrap());
+    assert_eq!(layers[0].0, "water");
+
+    fs::remove_dir_all(&filter_dir).unwrap();
+    for path in [&input_path, &output_path] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_stable_output_is_byte_identical() {
+    let outputs = [
+        std::env::temp_dir().join("mvt_wrangler_stable_first.pmtiles"),
+        std::env::temp_dir().join("mvt_wrangler_stable_second.pmtiles"),
+    ];
+    for output in &outputs {
+        let args = Args {
+            input: PathBuf::from("tests/fixtures/input.pmtiles"),
+            output: output.clone(),
+            filter: Some(PathBuf::from("tests/fixtures/filter.geojson")),
+            stable_output: true,
+            ..Default::default()
+        };
+        run(args).await.unwrap();
+    }
+
+    let [first, second] = outputs.each_ref().map(|path| fs::read(path).unwrap());
+    assert!(first == second, "both runs wrote the same bytes");
+
+    for path in &outputs {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_max_tile_bytes() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_max_bytes_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_max_bytes_output.pmtiles");
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &[(TileCoord::new(3, 1, 2).unwrap(), point_tile("pois"))],
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        max_tile_bytes: Some(8),
+        ..Default::default()
+    };
+    assert!(run(args).await.is_ok(), "oversized tiles only warn");
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        max_tile_bytes: Some(8),
+        strict: true,
+        ..Default::default()
+    };
+    let err = run(args).await.unwrap_err();
+    assert!(err.to_string().contains("3/1/2"), "{}", err);
+
+    let manifest_path = std::env::temp_dir().join("mvt_wrangler_oversized_tiles.csv");
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        max_tile_bytes: Some(8),
+        strict: true,
+        checksum_manifest: Some(manifest_path.clone()),
+        ..Default::default()
+    };
+    assert!(run(args).await.is_err());
+    assert!(!manifest_path.exists(), "a failed run leaves no manifest");
+
+    fs::remove_file(&input_path).unwrap();
+    let _ = fs::remove_file(&output_path);
+}
+
+#[tokio::test]
+async fn test_transform_config_matches_flags() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_config_input.pmtiles");
+    let config_path = std::env::temp_dir().join("mvt_wrangler_config.json");
+    let from_config_path = std::env::temp_dir().join("mvt_wrangler_config_file.pmtiles");
+    let from_flags_path = std::env::temp_dir().join("mvt_wrangler_config_flags.pmtiles");
+    let coord = TileCoord::new(0, 0, 0).unwrap();
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &[(coord, point_tile("pois"))],
+    );
+    fs::write(
+        &config_path,
+        r#"{"add_tags": ["pois:source=config", "*:kind=\"shop\""], "target_extent": 8192}"#,
+    )
+    .unwrap();
+
+    let args = Args {
+        input: input_path.clone(),
+        output: from_config_path.clone(),
+        output_compression: Some(Codec::Gzip),
+        transform_config: Some(config_path.clone()),
+        // flags given on the command line take precedence
+        add_tag: vec![AddTag::parse("pois:source=cli").unwrap()],
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let args = Args {
+        input: input_path.clone(),
+        output: from_flags_path.clone(),
+        output_compression: Some(Codec::Gzip),
+        add_tag: vec![AddTag::parse("pois:source=cli").unwrap()],
+        target_extent: Some(8192),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let read_tile = async |path: &Path| {
+        let reader = AsyncPmTilesReader::new_with_path(path).await.unwrap();
+        decoded_tile(&reader.get_tile(coord).await.unwrap().unwrap())
+    };
+    let from_config = read_tile(&from_config_path).await;
+    assert_eq!(from_config, read_tile(&from_flags_path).await);
+    let tags = &from_config[0].1[0].1;
+    assert!(tags["source"].contains("cli"), "{:?}", tags);
+    assert!(tags["kind"].contains("park"), "{:?}", tags);
+
+    for path in [
+        &input_path,
+        &config_path,
+        &from_config_path,
+        &from_flags_path,
+    ] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_only_layers_with_features() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_empty_layer_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_empty_layer_output.pmtiles");
+    let coord = TileCoord::new(0, 0, 0).unwrap();
+    let mut tile = Tile::decode(point_tile("pois").as_slice()).unwrap();
+    let mut empty = tile.layers[0].clone();
+    empty.name = "empty".to_string();
+    empty.features.clear();
+    tile.layers.push(empty);
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt)
+            .tile_compression(pmtiles::Compression::None)
+            .metadata(
+                r#"{"vector_layers":[{"id":"pois","fields":{}},{"id":"empty","fields":{}}]}"#,
+            ),
+        &[(coord, tile.encode_to_vec())],
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        output_compression: Some(Codec::Gzip),
+        only_layers_with_features: true,
+        // show that the layer is removed even when empty layers are otherwise kept
+        drop_empty_layers: Some(false),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    let layers = decoded_tile(&out_pmt.get_tile(coord).await.unwrap().unwrap());
+    let names = layers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["pois"]);
+
+    let metadata: Value = serde_json::from_str(&out_pmt.get_metadata().await.unwrap()).unwrap();
+    let ids = metadata["vector_layers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|layer| layer["id"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec!["pois"]);
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_removed_output_report() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let output_path = std::env::temp_dir().join("mvt_wrangler_removed.pmtiles");
+    let report_path = std::env::temp_dir().join("mvt_wrangler_removed.geojson");
+    let filter_path = std::env::temp_dir().join("mvt_wrangler_removed_filter.geojson");
+    fs::write(
+        &filter_path,
+        r#"{
+          "type": "FeatureCollection",
+          "features": [{
+            "type": "Feature",
+            "geometry": {"type": "Polygon", "coordinates": [[[-180, -89], [180, -89], [180, 89], [-180, 89], [-180, -89]]]},
+            "properties": {"layers": {"earth": {"feature": ["==", ["type"], "Polygon"]}}}
+          }]
+        }"#,
+    )
+    .unwrap();
+
+    let args = Args {
+        input: PathBuf::from(input_pmtiles_path),
+        output: output_path.clone(),
+        filter: Some(filter_path.clone()),
+        removed_output: Some(report_path.clone()),
+        removed_limit: 5,
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let report: Value = serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+    assert_eq!(report["type"], "FeatureCollection");
+    let features = report["features"].as_array().unwrap();
+    assert_eq!(features.len(), 5, "capped at --removed-limit");
+    for feature in features {
+        assert_eq!(feature["layer"], "earth");
+        let geometry_type = feature["geometry"]["type"].as_str().unwrap();
+        assert!(geometry_type.ends_with("Polygon"), "{}", geometry_type);
+        let ring = &feature["geometry"]["coordinates"];
+        let first = if geometry_type == "Polygon" {
+            &ring[0][0]
+        } else {
+            &ring[0][0][0]
+        };
+        let (lon, lat) = (first[0].as_f64().unwrap(), first[1].as_f64().unwrap());
+        assert!((-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat));
+    }
+
+    for path in [&output_path, &report_path, &filter_path] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_bad_paths_are_errors() {
+    let output_path = std::env::temp_dir().join("mvt_wrangler_bad_paths.pmtiles");
+    let args = || Args {
+        input: PathBuf::from("tests/fixtures/input.pmtiles"),
+        output: output_path.clone(),
+        ..Default::default()
+    };
+
+    let err = run(Args {
+        input: PathBuf::from("tests/fixtures/missing.pmtiles"),
+        ..args()
+    })
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(&err, WranglerError::Io(e) if e.kind() == std::io::ErrorKind::NotFound),
+        "{err:?}"
+    );
+
+    let err = run(Args {
+        filter: Some(PathBuf::from("tests/fixtures/missing.geojson")),
+        ..args()
+    })
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(&err, WranglerError::Io(e) if e.kind() == std::io::ErrorKind::NotFound),
+        "{err:?}"
+    );
+
+    let err = run(Args {
+        output: std::env::temp_dir().join("mvt_wrangler_bad_paths.mbtiles"),
+        ..args()
+    })
+    .await
+    .unwrap_err();
+    assert!(matches!(err, WranglerError::Config(_)), "{err:?}");
+    assert!(!output_path.exists());
+}
+
+#[tokio::test]
+async fn test_validate_tiles_rejects_non_mvt_archive() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_validate_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_validate_output.pmtiles");
+    let tiles = (0..4)
+        .map(|x| {
+            (
+                TileCoord::new(2, x, 1).unwrap(),
+                b"\x89PNG\r\n\x1a\n".to_vec(),
+            )
+        })
+        .collect::<Vec<_>>();
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &tiles,
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        validate_tiles: Some(100),
+        ..Default::default()
+    };
+    match run(args).await {
+        Err(WranglerError::InvalidTiles { failed, sampled }) => {
+            assert_eq!((failed, sampled), (4, 4));
+        }
+        other => panic!("Expected InvalidTiles error, got {:?}", other),
+    }
+
+    fs::remove_file(&input_path).unwrap();
+    let _ = fs::remove_file(&output_path);
+}
+
+#[tokio::test]
+async fn test_passthrough_untouched_tiles_are_byte_identical() {
+    let input_pmtiles_path = "tests/fixtures/input.pmtiles";
+    let filter_geojson_path = std::env::temp_dir().join("mvt_wrangler_passthrough.geojson");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_passthrough.pmtiles");
+
+    // The fixture filter has a global feature touching every tile; keep only the local ones
+    let mut fixture: Value =
+        serde_json::from_str(&fs::read_to_string("tests/fixtures/filter.geojson").unwrap())
+            .unwrap();
+    fixture["features"]
+        .as_array_mut()
+        .unwrap()
+        .retain(|f| f["properties"]["name"] != "Global");
+    fs::write(&filter_geojson_path, fixture.to_string()).unwrap();
+
+    let args = Args {
+        input: PathBuf::from(input_pmtiles_path),
+        output: output_path.clone(),
+        filter: Some(filter_geojson_path.clone()),
+        passthrough_untouched: true,
+        drop_empty_layers: Some(false),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let in_pmt = Arc::new(
+        AsyncPmTilesReader::new_with_path(input_pmtiles_path)
+            .await
+            .unwrap(),
+    );
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    let filter = load_filter(filter_geojson_path.to_str().unwrap())
+        .compile()
+        .unwrap();
+    let entries = in_pmt
+        .clone()
+        .entries()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+
+    let (mut untouched, mut touched) = (0, 0);
+    for coord in entries.iter().flat_map(|e| e.iter_coords()) {
+        let tile_coord = TileCoord::from(coord);
+        let input = in_pmt.get_tile(coord).await.unwrap().unwrap();
+        let Some(output) = out_pmt.get_tile(coord).await.unwrap() else {
+            continue;
+        };
+        if filter
+            .get_filter_features(&tile_bounds(&tile_coord))
+            .is_empty()
+        {
+            assert_eq!(input, output, "untouched tile changed");
+            untouched += 1;
+        } else {
+            touched += 1;
+        }
+    }
+    assert!(untouched > 0 && touched > 0);
+
+    fs::remove_file(&filter_geojson_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_flip_y_moves_tiles_to_mirrored_row() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_flip_y_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_flip_y_output.pmtiles");
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &[
+            (TileCoord::new(3, 2, 0).unwrap(), point_tile("top")),
+            (TileCoord::new(3, 2, 5).unwrap(), point_tile("middle")),
+        ],
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        flip_y: true,
+        output_compression: Some(Codec::Gzip),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    let tile = |y| out_pmt.get_tile(TileCoord::new(3, 2, y).unwrap());
+    assert_eq!(decoded_tile(&tile(7).await.unwrap().unwrap())[0].0, "top");
+    assert_eq!(
+        decoded_tile(&tile(2).await.unwrap().unwrap())[0].0,
+        "middle"
+    );
+    assert!(tile(0).await.unwrap().is_none());
+    assert!(tile(5).await.unwrap().is_none());
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_tiles_file_restricts_processing() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_tiles_file_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_tiles_file_output.pmtiles");
+    let tiles_path = std::env::temp_dir().join("mvt_wrangler_tiles_file.txt");
+    let coords =
+        [(1, 0, 0), (1, 1, 0), (1, 1, 1)].map(|(z, x, y)| TileCoord::new(z, x, y).unwrap());
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &coords.map(|coord| (coord, point_tile("pois"))),
+    );
+    // Two tiles in the input and one that isn't
+    fs::write(&tiles_path, "1/0/0\n1/1/1\n5/3/3\n").unwrap();
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        tiles_file: Some(tiles_path.clone()),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    assert!(out_pmt.get_tile(coords[0]).await.unwrap().is_some());
+    assert!(out_pmt.get_tile(coords[1]).await.unwrap().is_none());
+    assert!(out_pmt.get_tile(coords[2]).await.unwrap().is_some());
+
+    for path in [&input_path, &output_path, &tiles_path] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_content_hash_in_metadata() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_hash_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_hash_output.pmtiles");
+    let coord = TileCoord::new(1, 0, 0).unwrap();
+    let hash = |layer: &'static str| {
+        let (input_path, output_path) = (input_path.clone(), output_path.clone());
+        async move {
+            write_archive(
+                &input_path,
+                PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+                &[(coord, point_tile(layer))],
+            );
+            let args = Args {
+                input: input_path,
+                output: output_path.clone(),
+                content_hash: true,
+                ..Default::default()
+            };
+            run(args).await.unwrap();
+
+            let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+                .await
+                .unwrap();
+            assert!(out_pmt.get_tile(coord).await.unwrap().is_some());
+            let metadata: Value =
+                serde_json::from_str(&out_pmt.get_metadata().await.unwrap()).unwrap();
+            metadata["content_hash"].as_str().unwrap().to_string()
+        }
+    };
+
+    let first = hash("pois").await;
+    assert_eq!(first.len(), 16);
+    assert_eq!(hash("pois").await, first);
+    assert_ne!(hash("roads").await, first);
+    assert!(!output_path.with_extension("pmtiles.tmp").exists());
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_emit_tilejson_writes_sidecar() {
+    let input_path = PathBuf::from("tests/fixtures/input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_tilejson.pmtiles");
+    let tilejson_path = std::env::temp_dir().join("mvt_wrangler_tilejson.pmtiles.json");
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        emit_tilejson: true,
+        tiles_url: Some("https://tiles.example.com/{z}/{x}/{y}.mvt".to_string()),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let in_pmt = AsyncPmTilesReader::new_with_path(&input_path)
+        .await
+        .unwrap();
+    let header = in_pmt.get_header();
+    let tilejson: Value =
+        serde_json::from_str(&fs::read_to_string(&tilejson_path).unwrap()).unwrap();
+    assert_eq!(tilejson["tilejson"], "3.0.0");
+    assert_eq!(
+        tilejson["tiles"],
+        serde_json::json!(["https://tiles.example.com/{z}/{x}/{y}.mvt"])
+    );
+    assert_eq!(tilejson["minzoom"], header.min_zoom);
+    assert_eq!(tilejson["maxzoom"], header.max_zoom);
+    let layer_ids = tilejson["vector_layers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|layer| layer["id"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert!(layer_ids.contains(&"earth"), "{:?}", layer_ids);
+
+    fs::remove_file(&output_path).unwrap();
+    fs::remove_file(&tilejson_path).unwrap();
+}
+
+#[test]
+fn test_list_tiles_prints_sorted_coordinates() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_list_tiles.pmtiles");
+    let mut coords = [
+        (0, 0, 0),
+        (1, 0, 0),
+        (1, 0, 1),
+        (1, 1, 0),
+        (1, 1, 1),
+        (2, 3, 1),
+    ]
+    .map(|(z, x, y)| TileCoord::new(z, x, y).unwrap());
+    // Stored in tile id order, which within a zoom level is not x/y order
+    coords.sort_by_key(|&coord| TileId::from(coord).value());
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &coords.map(|coord| (coord, point_tile("pois"))),
+    );
+    // No output path is needed
+    let run_binary = |extra: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_mvt-wrangler"))
+            .arg(&input_path)
+            .arg("--list-tiles")
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(
+        run_binary(&[]),
+        "0/0/0\n1/0/0\n1/0/1\n1/1/0\n1/1/1\n2/3/1\n"
+    );
+    assert_eq!(run_binary(&["--count-only"]), "6\n");
+
+    fs::remove_file(&input_path).unwrap();
+}
+
+#[test]
+fn test_identity_transform_reports_output_identical_to_input() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_noop_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_noop_output.pmtiles");
+    // Compressed differently from how the output is, so only the contents can match
+    let mut stored = Vec::new();
+    {
+        let mut encoder = flate2::write::GzEncoder::new(&mut stored, flate2::Compression::best());
+        std::io::Write::write_all(&mut encoder, &point_tile("pois")).unwrap();
+        encoder.finish().unwrap();
+    }
+    let coords =
+        [(0, 0, 0), (1, 0, 1), (1, 1, 1)].map(|(z, x, y)| TileCoord::new(z, x, y).unwrap());
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::Gzip),
+        &coords.map(|coord| (coord, stored.clone())),
+    );
+    let run_binary = |extra: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_mvt-wrangler"))
+            .arg(&input_path)
+            .arg(&output_path)
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert!(run_binary(&[]).contains("Output identical to input"));
+    let stdout = run_binary(&["--where", r#"["==", ["tag", "kind"], "park"]"#]);
+    assert!(stdout.contains("Output differs from input"));
+    let stdout = run_binary(&["--flip-y"]);
+    assert!(stdout.contains("Output differs from input"));
+    // 1/0/1 is left out, so the tiles written are unchanged but the output isn't
+    let stdout = run_binary(&["--bbox", "10,-80,170,-10"]);
+    assert!(stdout.contains("Output differs from input"));
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_no_recompress_keeps_stored_bytes() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_no_recompress_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_no_recompress_output.pmtiles");
+    let coord = TileCoord::new(0, 0, 0).unwrap();
+    // Compressed differently from how the output would be, so reuse is visible
+    let mut stored = Vec::new();
+    {
+        let mut encoder = flate2::write::GzEncoder::new(&mut stored, flate2::Compression::best());
+        std::io::Write::write_all(&mut encoder, &point_tile("pois")).unwrap();
+        encoder.finish().unwrap();
+    }
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::Gzip),
+        &[(coord, stored.clone())],
+    );
+    let output_tile = |args: Args| async {
+        run(args).await.unwrap();
+        let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+            .await
+            .unwrap();
+        out_pmt.get_tile(coord).await.unwrap().unwrap().to_vec()
+    };
+
+    let unchanged = output_tile(Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        no_recompress: true,
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(unchanged, stored);
+
+    let recompressed = output_tile(Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        ..Default::default()
+    })
+    .await;
+    assert_ne!(recompressed, stored);
+
+    // A tile the transform changes is compressed as usual
+    let changed = output_tile(Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        no_recompress: true,
+        add_tag: vec![AddTag::parse("pois:source=test").unwrap()],
+        ..Default::default()
+    })
+    .await;
+    assert_ne!(changed, stored);
+    assert_eq!(decoded_tile(&changed)[0].1.len(), 1);
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_no_recompress_is_decided_per_tile() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_no_recompress_mixed_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_no_recompress_mixed_output.pmtiles");
+    let gzip_best = |data: &[u8]| {
+        let mut stored = Vec::new();
+        let mut encoder = flate2::write::GzEncoder::new(&mut stored, flate2::Compression::best());
+        std::io::Write::write_all(&mut encoder, data).unwrap();
+        encoder.finish().unwrap();
+        stored
+    };
+    let tiles = [
+        (
+            TileCoord::new(1, 0, 0).unwrap(),
+            gzip_best(&point_tile("pois")),
+        ),
+        (
+            TileCoord::new(1, 1, 0).unwrap(),
+            gzip_best(&point_tile("roads")),
+        ),
+        (
+            TileCoord::new(1, 0, 1).unwrap(),
+            gzip_best(&point_tile("pois")),
+        ),
+    ];
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::Gzip),
+        &tiles,
+    );
+
+    // Only the tiles with a `pois` layer are changed
+    run(Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        no_recompress: true,
+        add_tag: vec![AddTag::parse("pois:source=test").unwrap()],
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    for (coord, stored) in &tiles {
+        let written = out_pmt.get_tile(*coord).await.unwrap().unwrap().to_vec();
+        let layers = decoded_tile(&written);
+        if layers[0].0 == "roads" {
+            assert_eq!(&written, stored);
+        } else {
+            assert_ne!(&written, stored);
+            assert_eq!(layers[0].0, "pois");
+        }
+    }
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_fail_on_empty_output() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_empty_output_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_empty_output_output.pmtiles");
+    let filter_path = std::env::temp_dir().join("mvt_wrangler_empty_output_filter.geojson");
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &[(TileCoord::new(0, 0, 0).unwrap(), point_tile("pois"))],
+    );
+    fs::write(
+        &filter_path,
+        r#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[-180, -90], [-180, 90], [180, 90], [180, -90], [-180, -90]]]
+                },
+                "properties": {"layers": {"*": {"feature": true}}}
+            }]
+        }"#,
+    )
+    .unwrap();
+    let args = |fail_on_empty_output| Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        output_compression: Some(Codec::Gzip),
+        filter: Some(filter_path.clone()),
+        fail_on_empty_output,
+        ..Default::default()
+    };
+
+    run(args(false)).await.unwrap();
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    let coord = TileCoord::new(0, 0, 0).unwrap();
+    let tile = out_pmt.get_tile(coord).await.unwrap();
+    assert!(tile.is_none_or(|data| decoded_tile(&data).iter().all(|(_, f)| f.is_empty())));
+
+    let err = run(args(true)).await.unwrap_err();
+    assert!(matches!(err, WranglerError::EmptyOutput), "{:?}", err);
+
+    for path in [&input_path, &output_path, &filter_path] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_bbox_skips_tiles_outside_it() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_bbox_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_bbox_output.pmtiles");
+    let coords = [(1, 0, 0), (1, 1, 0), (1, 0, 1), (1, 1, 1)]
+        .map(|(z, x, y)| TileCoord::new(z, x, y).unwrap());
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &coords.map(|coord| (coord, point_tile("pois"))),
+    );
+
+    let handled = Arc::new(Mutex::new(Vec::new()));
+    let recorded = handled.clone();
+    let progress: ProgressCallback = Arc::new(move |event: ProgressEvent| {
+        recorded.lock().unwrap().push(event.coord);
+    });
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        filter: Some(PathBuf::from("tests/fixtures/filter.geojson")),
+        bbox: Some([130.0, 30.0, 140.0, 40.0]),
+        ..Default::default()
+    };
+    let options = RunOptions {
+        progress: Some(progress),
+    };
+    run_with_options(args, options).await.unwrap();
+
+    // Only the north-eastern tile is read, transformed and written
+    assert_eq!(*handled.lock().unwrap(), vec![coords[1]]);
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    for coord in coords {
+        let written = out_pmt.get_tile(coord).await.unwrap().is_some();
+        assert_eq!(written, coord == coords[1], "{:?}", coord);
+    }
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_progress_callback_is_called_per_tile() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_progress_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_progress_output.pmtiles");
+    let coords =
+        [(1, 0, 0), (1, 1, 0), (1, 1, 1)].map(|(z, x, y)| TileCoord::new(z, x, y).unwrap());
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &coords.map(|coord| (coord, point_tile("pois"))),
+    );
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let progress: ProgressCallback = Arc::new(move |event: ProgressEvent| {
+        recorded.lock().unwrap().push(event);
+    });
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        ..Default::default()
+    };
+    let options = RunOptions {
+        progress: Some(progress),
+    };
+    run_with_options(args, options).await.unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), coords.len());
+    // Tiles are written in tile id order
+    let mut expected = coords.to_vec();
+    expected.sort_by_key(|&coord| pmtiles::TileId::from(coord));
+    for (n, event) in events.iter().enumerate() {
+        assert_eq!(event.processed, n + 1);
+        assert_eq!(event.total, coords.len());
+        assert_eq!(event.coord, expected[n]);
+    }
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_progress_stage_counts_reach_total() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_stages_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_stages_output.pmtiles");
+    let coords = [(2, 0, 0), (2, 1, 0), (2, 1, 1), (2, 2, 1), (2, 3, 3)]
+        .map(|(z, x, y)| TileCoord::new(z, x, y).unwrap());
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &coords.map(|coord| (coord, point_tile("pois"))),
+    );
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let progress: ProgressCallback = Arc::new(move |event: ProgressEvent| {
+        recorded.lock().unwrap().push(event);
+    });
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        detailed_progress: true,
+        ..Default::default()
+    };
+    let options = RunOptions {
+        progress: Some(progress),
+    };
+    run_with_options(args, options).await.unwrap();
+
+    let events = events.lock().unwrap();
+    // A tile is read before it is transformed, and transformed before it is written
+    for event in events.iter() {
+        assert!(event.read >= event.transformed, "{event:?}");
+        assert!(event.transformed >= event.processed, "{event:?}");
+    }
+    let last = events.last().unwrap();
+    assert_eq!(
+        [last.read, last.transformed, last.processed],
+        [coords.len(); 3]
+    );
+    assert_eq!(last.total, coords.len());
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_malformed_header_bounds_are_corrected() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_bounds_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_bounds_output.pmtiles");
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt)
+            .tile_compression(pmtiles::Compression::None)
+            .bounds(140.0, 35.0, 139.0, 95.0),
+        &[(TileCoord::new(0, 0, 0).unwrap(), point_tile("pois"))],
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        strict: true,
+        ..Default::default()
+    };
+    assert!(run(args).await.is_err());
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    let header = out_pmt.get_header();
+    assert_eq!(
+        [
+            header.min_longitude,
+            header.min_latitude,
+            header.max_longitude,
+            header.max_latitude
+        ],
+        [139.0, 35.0, 140.0, 90.0]
+    );
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_infer_fields_describes_output_layers() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_infer_fields_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_infer_fields_output.pmtiles");
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt).tile_compression(pmtiles::Compression::None),
+        &[
+            (TileCoord::new(2, 1, 1).unwrap(), point_tile("pois")),
+            (TileCoord::new(2, 2, 1).unwrap(), point_tile("roads")),
+        ],
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        infer_fields: true,
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    let metadata: Value = serde_json::from_str(&out_pmt.get_metadata().await.unwrap()).unwrap();
+    let fields = metadata["vector_layers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|layer| (layer["id"].as_str().unwrap(), layer["fields"].clone()))
+        .collect::<BTreeMap<_, _>>();
+    assert_eq!(
+        fields,
+        BTreeMap::from([
+            ("pois", serde_json::json!({ "kind": "String" })),
+            ("roads", serde_json::json!({ "kind": "String" })),
+        ])
+    );
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_precise_bounds_cover_features_only() {
+    let input_path = std::env::temp_dir().join("mvt_wrangler_precise_bounds_input.pmtiles");
+    let output_path = std::env::temp_dir().join("mvt_wrangler_precise_bounds_output.pmtiles");
+    // A point near the north-west corner of two neighbouring tiles
+    let coords = [
+        TileCoord::new(2, 1, 1).unwrap(),
+        TileCoord::new(2, 2, 1).unwrap(),
+    ];
+    let tile_rect = |coord: &TileCoord| tile_bounds(coord).bounding_rect().unwrap();
+    let (west, east) = (tile_rect(&coords[0]), tile_rect(&coords[1]));
+    write_archive(
+        &input_path,
+        PmTilesWriter::new(TileType::Mvt)
+            .tile_compression(pmtiles::Compression::None)
+            .bounds(
+                west.min().x as f32,
+                west.min().y as f32,
+                east.max().x as f32,
+                east.max().y as f32,
+            ),
+        &coords.map(|coord| (coord, point_tile("pois"))),
+    );
+
+    let args = Args {
+        input: input_path.clone(),
+        output: output_path.clone(),
+        precise_bounds: true,
+        ..Default::default()
+    };
+    run(args).await.unwrap();
+
+    let point = |coord: &TileCoord| {
+        let point = geo::Geometry::Point(geo::Point::new(10.0, 10.0));
+        match unproject_from_tile(&point, coord, 4096) {
+            geo::Geometry::Point(point) => point,
+            _ => unreachable!(),
+        }
+    };
+    let (first, second) = (point(&coords[0]), point(&coords[1]));
+    let expected = [first.x(), first.y(), second.x(), second.y()];
+
+    let out_pmt = AsyncPmTilesReader::new_with_path(&output_path)
+        .await
+        .unwrap();
+    let header = out_pmt.get_header();
+    let bounds = [
+        header.min_longitude,
+        header.min_latitude,
+        header.max_longitude,
+        header.max_latitude,
+    ];
+    for (actual, expected) in bounds.iter().zip(expected) {
+        assert!((*actual as f64 - expected).abs() < 1e-4, "{bounds:?}");
+    }
+    // Tighter than the tiles: the features only reach a sliver of them
+    assert!(bounds[0] > west.min().x as f32);
+    assert!(bounds[1] > west.min().y as f32);
+    assert!(bounds[2] < east.max().x as f32);
+    assert!(bounds[3] < east.max().y as f32);
+
+    let metadata: Value = serde_json::from_str(&out_pmt.get_metadata().await.unwrap()).unwrap();
+    let metadata_bounds = metadata["bounds"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_f64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(metadata_bounds, expected);
+    assert_eq!(
+        metadata["center"][0].as_f64().unwrap(),
+        (first.x() + second.x()) / 2.0
+    );
+
+    fs::remove_file(&input_path).unwrap();
+    fs::remove_file(&output_path).unwrap();
+}